@@ -0,0 +1,883 @@
+use crate::database::{Bms, Cell, Database, Material, Shape};
+use crate::export;
+use crate::filesystem::{ProjectFile, Scene};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+
+// Same tolerances the editor's electrical solver uses
+const VOLTAGE_TOLERANCE_V: f64 = 0.1;
+const GROUP_CAPACITY_TOLERANCE: f64 = 0.05;
+
+/// Library rows needed to rate a pack and price it out.
+#[derive(Debug, Default)]
+pub struct PackLibrary {
+    pub cells: HashMap<i64, Cell>,
+    pub bms: HashMap<i64, Bms>,
+    pub materials: HashMap<i64, Material>,
+    pub shapes: HashMap<i64, Shape>,
+}
+
+impl PackLibrary {
+    pub fn load(db: &Database) -> rusqlite::Result<Self> {
+        Ok(PackLibrary {
+            cells: db.get_cells(None)?.into_iter().map(|c| (c.id, c)).collect(),
+            bms: db.get_bms()?.into_iter().map(|b| (b.id, b)).collect(),
+            materials: db.get_materials()?.into_iter().map(|m| (m.id, m)).collect(),
+            shapes: db.get_shapes()?.into_iter().map(|s| (s.id, s)).collect(),
+        })
+    }
+}
+
+/// Cells wired in parallel, i.e. sharing both terminals.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParallelGroup {
+    pub cells: Vec<String>,
+    pub capacity_ah: f64,
+    pub nominal_voltage: f64,
+    pub max_discharge_a: f64,
+}
+
+/// Electrical ratings of the pack's main series string.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackAnalysis {
+    pub cell_count: usize,
+    pub series: usize,
+    /// Cells in the smallest parallel group of the string
+    pub parallel: usize,
+    pub configuration: String, // e.g. "13S4P"
+    /// `None` when the pack mixes chemistries
+    pub chemistry: Option<String>,
+    pub nominal_voltage: f64,
+    pub max_voltage: f64,
+    pub min_voltage: f64,
+    pub capacity_ah: f64,
+    pub energy_wh: f64,
+    pub max_discharge_a: f64,
+    pub max_charge_a: f64,
+    /// Sum of the groups' parallel resistances, when every cell has a rating
+    pub internal_resistance_mohm: Option<f64>,
+    pub cell_weight_g: f64,
+    /// Parallel groups from the negative end of the string to the positive one
+    pub groups: Vec<ParallelGroup>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    UnknownCell,
+    /// Cell without any connection
+    FloatingCell,
+    /// Connection joining a parallel group's positive and negative side
+    ShortCircuit,
+    /// Groups branch or loop instead of forming one series string
+    SeriesTopology,
+    /// Series links that run negative-to-negative along the string
+    ReversedPolarity,
+    /// Connected cells that are not part of the main string
+    DisconnectedCells,
+    MixedChemistry,
+    VoltageMismatch,
+    UnbalancedGroups,
+    MissingMaterial,
+    UndersizedConnection,
+    BmsSeriesMismatch,
+    BmsUndersized,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DrcIssue {
+    pub rule: Rule,
+    pub severity: Severity,
+    pub message: String,
+    /// Uuids of the cells, connections or components involved
+    pub entities: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DrcReport {
+    /// No errors; warnings don't fail the check
+    pub passed: bool,
+    pub errors: usize,
+    pub warnings: usize,
+    pub issues: Vec<DrcIssue>,
+}
+
+impl DrcReport {
+    fn push(&mut self, rule: Rule, severity: Severity, message: String, mut entities: Vec<String>) {
+        entities.sort();
+        self.issues.push(DrcIssue {
+            rule,
+            severity,
+            message,
+            entities,
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BomCategory {
+    Cell,
+    Bms,
+    Material,
+    Shape,
+    Custom,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BomLine {
+    pub category: BomCategory,
+    /// Library key, see `database::library_key`
+    pub key: Option<String>,
+    pub description: String,
+    pub quantity: f64,
+    pub unit: String, // "pcs" | "m"
+    /// Total for the line, when known
+    pub weight_g: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bom {
+    pub project: String,
+    pub lines: Vec<BomLine>,
+    pub total_weight_g: f64,
+    pub warnings: Vec<String>,
+}
+
+impl Bom {
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("category,key,description,quantity,unit,weight_g\n");
+        for line in &self.lines {
+            let category = serde_json::to_value(line.category)
+                .ok()
+                .and_then(|v| v.as_str().map(str::to_string))
+                .unwrap_or_default();
+            let weight = line
+                .weight_g
+                .map(|w| format!("{:.1}", w))
+                .unwrap_or_default();
+            csv.push_str(&format!(
+                "{},{},{},{},{},{}\n",
+                category,
+                csv_field(line.key.as_deref().unwrap_or("")),
+                csv_field(&line.description),
+                format_quantity(line.quantity),
+                line.unit,
+                weight
+            ));
+        }
+        csv
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn format_quantity(quantity: f64) -> String {
+    if quantity.fract() == 0.0 {
+        format!("{}", quantity as i64)
+    } else {
+        format!("{:.3}", quantity)
+    }
+}
+
+/// How the cells are wired: parallel groups and the series links between them.
+///
+/// A connection between two cells joining like terminals puts them in the
+/// same parallel group; one joining opposite terminals is a series link.
+/// Connections to components don't affect the topology.
+struct Topology {
+    groups: Vec<Vec<String>>,
+    /// `(lower, upper, connection)`: the connection runs from the positive
+    /// side of `lower` to the negative side of `upper`
+    links: Vec<(usize, usize, String)>,
+    /// Connections between a group's own positive and negative side
+    shorts: Vec<String>,
+    /// Groups each group shares a series link with
+    neighbours: Vec<BTreeSet<usize>>,
+    /// Connected components of groups over series links, largest first
+    strings: Vec<Vec<usize>>,
+}
+
+impl Topology {
+    fn build(scene: &Scene) -> Self {
+        let mut uuids: Vec<&String> = scene.cells.keys().collect();
+        uuids.sort();
+        let index: HashMap<&str, usize> = uuids
+            .iter()
+            .enumerate()
+            .map(|(i, u)| (u.as_str(), i))
+            .collect();
+
+        let mut connections: Vec<_> = scene.connections.values().collect();
+        connections.sort_by(|a, b| a.uuid.cmp(&b.uuid));
+        let cell_pairs: Vec<_> = connections
+            .iter()
+            .filter_map(|c| {
+                let source = *index.get(c.source_uuid.as_str())?;
+                let target = *index.get(c.target_uuid.as_str())?;
+                Some((c, source, target))
+            })
+            .collect();
+
+        let mut parent: Vec<usize> = (0..uuids.len()).collect();
+        fn find(parent: &mut [usize], i: usize) -> usize {
+            let mut root = i;
+            while parent[root] != root {
+                root = parent[root];
+            }
+            parent[i] = root;
+            root
+        }
+        for (connection, source, target) in &cell_pairs {
+            if connection.source_terminal == connection.target_terminal {
+                let (a, b) = (find(&mut parent, *source), find(&mut parent, *target));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+
+        let mut members: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+        for (i, uuid) in uuids.iter().enumerate() {
+            members
+                .entry(find(&mut parent, i))
+                .or_default()
+                .push((*uuid).clone());
+        }
+        let groups: Vec<Vec<String>> = members.into_values().collect();
+        let group_of: HashMap<String, usize> = groups
+            .iter()
+            .enumerate()
+            .flat_map(|(g, cells)| cells.iter().map(move |c| (c.clone(), g)))
+            .collect();
+
+        let mut links = Vec::new();
+        let mut shorts = Vec::new();
+        for (connection, _, _) in &cell_pairs {
+            if connection.source_terminal == connection.target_terminal {
+                continue;
+            }
+            let source = group_of[&connection.source_uuid];
+            let target = group_of[&connection.target_uuid];
+            if source == target {
+                shorts.push(connection.uuid.clone());
+            } else if connection.source_terminal == "positive" {
+                links.push((source, target, connection.uuid.clone()));
+            } else {
+                links.push((target, source, connection.uuid.clone()));
+            }
+        }
+
+        // Group strings over series links
+        let mut neighbours: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); groups.len()];
+        for (lower, upper, _) in &links {
+            neighbours[*lower].insert(*upper);
+            neighbours[*upper].insert(*lower);
+        }
+        let mut seen = vec![false; groups.len()];
+        let mut strings = Vec::new();
+        for start in 0..groups.len() {
+            if seen[start] {
+                continue;
+            }
+            seen[start] = true;
+            let mut string = vec![start];
+            let mut next = 0;
+            while next < string.len() {
+                for &n in &neighbours[string[next]] {
+                    if !seen[n] {
+                        seen[n] = true;
+                        string.push(n);
+                    }
+                }
+                next += 1;
+            }
+            strings.push(string);
+        }
+        let cells_in = |string: &Vec<usize>| string.iter().map(|g| groups[*g].len()).sum::<usize>();
+        strings.sort_by_key(|string| std::cmp::Reverse(cells_in(string)));
+
+        let mut topology = Topology {
+            groups,
+            links,
+            shorts,
+            neighbours,
+            strings,
+        };
+        for i in 0..topology.strings.len() {
+            let ordered = topology.order(&topology.strings[i]);
+            topology.strings[i] = ordered;
+        }
+        topology
+    }
+
+    /// Orders a simple chain of groups from its negative end; anything that
+    /// isn't a chain keeps its discovery order.
+    fn order(&self, string: &[usize]) -> Vec<usize> {
+        let neighbours = &self.neighbours;
+        if !self.is_chain(string) || string.len() < 2 {
+            return string.to_vec();
+        }
+        let start = string
+            .iter()
+            .copied()
+            .filter(|g| neighbours[*g].len() == 1)
+            .min_by_key(|g| self.links.iter().filter(|(_, upper, _)| upper == g).count())
+            .unwrap_or(string[0]);
+
+        let mut ordered = vec![start];
+        while let Some(&next) = neighbours[*ordered.last().unwrap()]
+            .iter()
+            .find(|n| !ordered.contains(n))
+        {
+            ordered.push(next);
+        }
+        ordered
+    }
+
+    fn is_chain(&self, string: &[usize]) -> bool {
+        let degree = |g: &usize| self.neighbours[*g].len();
+        let edges = string.iter().map(degree).sum::<usize>() / 2;
+        edges + 1 == string.len() && string.iter().all(|g| degree(g) <= 2)
+    }
+
+    fn main_string(&self) -> &[usize] {
+        self.strings.first().map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    fn cells_of(&self, string: &[usize]) -> Vec<String> {
+        string
+            .iter()
+            .flat_map(|g| self.groups[*g].iter().cloned())
+            .collect()
+    }
+}
+
+fn group_cells<'a>(
+    group: &[String],
+    project: &ProjectFile,
+    library: &'a PackLibrary,
+) -> Vec<&'a Cell> {
+    group
+        .iter()
+        .filter_map(|uuid| project.scene.cells.get(uuid))
+        .filter_map(|instance| library.cells.get(&instance.cell_id))
+        .collect()
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> f64 {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+    if count == 0 {
+        0.0
+    } else {
+        sum / count as f64
+    }
+}
+
+/// Cell uuids of each parallel group of the main series string, from its
+/// negative end.
+pub fn series_groups(scene: &Scene) -> Vec<Vec<String>> {
+    let topology = Topology::build(scene);
+    topology
+        .main_string()
+        .iter()
+        .map(|g| topology.groups[*g].clone())
+        .collect()
+}
+
+/// Rates the pack's main series string. Cells missing from the library are
+/// left out of the figures; `check_design_rules` reports them.
+pub fn analyze(project: &ProjectFile, library: &PackLibrary) -> PackAnalysis {
+    let topology = Topology::build(&project.scene);
+    let string = topology.main_string();
+
+    let mut groups = Vec::new();
+    let mut max_voltage = 0.0;
+    let mut min_voltage = 0.0;
+    let mut max_charge_a = f64::INFINITY;
+    let mut resistance = Some(0.0);
+    for group in string {
+        let cells = group_cells(&topology.groups[*group], project, library);
+        let nominal_voltage = mean(cells.iter().map(|c| c.nominal_voltage));
+        max_voltage += mean(cells.iter().map(|c| c.max_voltage));
+        min_voltage += mean(cells.iter().map(|c| c.min_voltage));
+        max_charge_a = f64::min(max_charge_a, cells.iter().map(|c| c.max_charge_a).sum());
+
+        let conductance: Option<f64> = cells
+            .iter()
+            .map(|c| c.internal_res_mohm.filter(|r| *r > 0.0).map(|r| 1.0 / r))
+            .sum();
+        resistance = match (resistance, conductance) {
+            (Some(total), Some(g)) if g > 0.0 => Some(total + 1.0 / g),
+            _ => None,
+        };
+
+        groups.push(ParallelGroup {
+            cells: topology.groups[*group].clone(),
+            capacity_ah: cells.iter().map(|c| c.capacity_mah as f64).sum::<f64>() / 1000.0,
+            nominal_voltage,
+            max_discharge_a: cells.iter().map(|c| c.max_discharge_a).sum(),
+        });
+    }
+
+    let series = groups.len();
+    let parallel = groups.iter().map(|g| g.cells.len()).min().unwrap_or(0);
+    let nominal_voltage: f64 = groups.iter().map(|g| g.nominal_voltage).sum();
+    let capacity_ah = groups
+        .iter()
+        .map(|g| g.capacity_ah)
+        .fold(f64::INFINITY, f64::min);
+    let capacity_ah = if capacity_ah.is_finite() {
+        capacity_ah
+    } else {
+        0.0
+    };
+    let max_discharge_a = groups
+        .iter()
+        .map(|g| g.max_discharge_a)
+        .fold(f64::INFINITY, f64::min);
+
+    let known: Vec<&Cell> = project
+        .scene
+        .cells
+        .values()
+        .filter_map(|instance| library.cells.get(&instance.cell_id))
+        .collect();
+    let chemistries: BTreeSet<&str> = known.iter().map(|c| c.chemistry.as_str()).collect();
+
+    PackAnalysis {
+        cell_count: project.scene.cells.len(),
+        series,
+        parallel,
+        configuration: format!("{}S{}P", series, parallel),
+        chemistry: match chemistries.len() {
+            1 => chemistries.into_iter().next().map(str::to_string),
+            _ => None,
+        },
+        nominal_voltage,
+        max_voltage,
+        min_voltage,
+        capacity_ah,
+        energy_wh: nominal_voltage * capacity_ah,
+        max_discharge_a: if max_discharge_a.is_finite() {
+            max_discharge_a
+        } else {
+            0.0
+        },
+        max_charge_a: if max_charge_a.is_finite() {
+            max_charge_a
+        } else {
+            0.0
+        },
+        internal_resistance_mohm: resistance.filter(|_| series > 0),
+        cell_weight_g: known.iter().map(|c| c.weight_g).sum(),
+        groups,
+    }
+}
+
+/// Checks the wiring and parts against electrical design rules.
+pub fn check_design_rules(project: &ProjectFile, library: &PackLibrary) -> DrcReport {
+    let scene = &project.scene;
+    let topology = Topology::build(scene);
+    let analysis = analyze(project, library);
+    let mut report = DrcReport::default();
+
+    let mut cells: Vec<_> = scene.cells.values().collect();
+    cells.sort_by(|a, b| a.uuid.cmp(&b.uuid));
+    for cell in &cells {
+        if !library.cells.contains_key(&cell.cell_id) {
+            let name = cell
+                .cell_key
+                .clone()
+                .unwrap_or_else(|| cell.cell_id.to_string());
+            report.push(
+                Rule::UnknownCell,
+                Severity::Error,
+                format!("Cell {} is not in the component library", name),
+                vec![cell.uuid.clone()],
+            );
+        }
+    }
+
+    let connected: BTreeSet<&str> = scene
+        .connections
+        .values()
+        .flat_map(|c| [c.source_uuid.as_str(), c.target_uuid.as_str()])
+        .collect();
+    if cells.len() > 1 {
+        for cell in &cells {
+            if !connected.contains(cell.uuid.as_str()) {
+                report.push(
+                    Rule::FloatingCell,
+                    Severity::Error,
+                    "Cell is not connected to anything".to_string(),
+                    vec![cell.uuid.clone()],
+                );
+            }
+        }
+    }
+
+    for connection in &topology.shorts {
+        report.push(
+            Rule::ShortCircuit,
+            Severity::Error,
+            "Connection joins the positive and negative side of the same parallel group"
+                .to_string(),
+            vec![connection.clone()],
+        );
+    }
+
+    let main = topology.main_string();
+    if main.len() > 1 && !topology.is_chain(main) {
+        report.push(
+            Rule::SeriesTopology,
+            Severity::Error,
+            "Parallel groups branch or loop instead of forming a single series string".to_string(),
+            topology.cells_of(main),
+        );
+    } else if main.len() > 1 {
+        // Along an ordered chain every link should point towards the positive end
+        let position: HashMap<usize, usize> =
+            main.iter().enumerate().map(|(i, g)| (*g, i)).collect();
+        let reversed: Vec<String> = topology
+            .links
+            .iter()
+            .filter(
+                |(lower, upper, _)| match (position.get(lower), position.get(upper)) {
+                    (Some(l), Some(u)) => l > u,
+                    _ => false,
+                },
+            )
+            .map(|(_, _, connection)| connection.clone())
+            .collect();
+        if !reversed.is_empty() {
+            report.push(
+                Rule::ReversedPolarity,
+                Severity::Error,
+                format!(
+                    "{} series connection(s) are wired against the string's polarity",
+                    reversed.len()
+                ),
+                reversed,
+            );
+        }
+    }
+
+    let disconnected: Vec<String> = topology
+        .strings
+        .iter()
+        .skip(1)
+        .flat_map(|string| topology.cells_of(string))
+        .filter(|uuid| connected.contains(uuid.as_str()))
+        .collect();
+    if !disconnected.is_empty() {
+        report.push(
+            Rule::DisconnectedCells,
+            Severity::Error,
+            format!(
+                "{} connected cell(s) are not part of the main series string",
+                disconnected.len()
+            ),
+            disconnected,
+        );
+    }
+
+    let mut chemistries: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for cell in &cells {
+        if let Some(spec) = library.cells.get(&cell.cell_id) {
+            chemistries
+                .entry(spec.chemistry.as_str())
+                .or_default()
+                .push(cell.uuid.clone());
+        }
+    }
+    if chemistries.len() > 1 {
+        let names: Vec<&str> = chemistries.keys().copied().collect();
+        report.push(
+            Rule::MixedChemistry,
+            Severity::Error,
+            format!("Pack mixes {} cells", names.join(", ")),
+            chemistries.into_values().flatten().collect(),
+        );
+    }
+
+    for group in &topology.groups {
+        let voltages: Vec<f64> = group_cells(group, project, library)
+            .iter()
+            .map(|c| c.nominal_voltage)
+            .collect();
+        let spread = voltages.iter().cloned().fold(f64::NEG_INFINITY, f64::max)
+            - voltages.iter().cloned().fold(f64::INFINITY, f64::min);
+        if voltages.len() > 1 && spread > VOLTAGE_TOLERANCE_V {
+            report.push(
+                Rule::VoltageMismatch,
+                Severity::Error,
+                format!("Parallel cells differ by {:.2} V nominal", spread),
+                group.clone(),
+            );
+        }
+    }
+
+    let capacities: Vec<f64> = analysis.groups.iter().map(|g| g.capacity_ah).collect();
+    if let (Some(min), Some(max)) = (
+        capacities.iter().cloned().reduce(f64::min),
+        capacities.iter().cloned().reduce(f64::max),
+    ) {
+        if max > 0.0 && (max - min) / max > GROUP_CAPACITY_TOLERANCE {
+            report.push(
+                Rule::UnbalancedGroups,
+                Severity::Warning,
+                format!(
+                    "Parallel group capacities range from {:.2} to {:.2} Ah",
+                    min, max
+                ),
+                topology.cells_of(main),
+            );
+        }
+    }
+
+    // Each series link between two groups carries its share of the pack current
+    let mut links_between: HashMap<(usize, usize), usize> = HashMap::new();
+    for (lower, upper, _) in &topology.links {
+        *links_between.entry((*lower, *upper)).or_default() += 1;
+    }
+    let mut connections: Vec<_> = scene.connections.values().collect();
+    connections.sort_by(|a, b| a.uuid.cmp(&b.uuid));
+    for connection in connections {
+        let material = match connection.material_id {
+            Some(id) => library.materials.get(&id),
+            None => {
+                report.push(
+                    Rule::MissingMaterial,
+                    Severity::Warning,
+                    "Connection has no material".to_string(),
+                    vec![connection.uuid.clone()],
+                );
+                continue;
+            }
+        };
+        let link = topology
+            .links
+            .iter()
+            .find(|(_, _, uuid)| *uuid == connection.uuid);
+        if let (Some(material), Some((lower, upper, _))) = (material, link) {
+            let current = analysis.max_discharge_a / links_between[&(*lower, *upper)] as f64;
+            if material.max_current_a < current {
+                report.push(
+                    Rule::UndersizedConnection,
+                    Severity::Warning,
+                    format!(
+                        "{} is rated for {:.1} A but may carry {:.1} A",
+                        material.name, material.max_current_a, current
+                    ),
+                    vec![connection.uuid.clone()],
+                );
+            }
+        }
+    }
+
+    let mut components: Vec<_> = scene
+        .components
+        .values()
+        .filter(|c| c.component_type == "bms")
+        .collect();
+    components.sort_by(|a, b| a.uuid.cmp(&b.uuid));
+    for component in components {
+        let Some(bms) = component.reference_id.and_then(|id| library.bms.get(&id)) else {
+            continue;
+        };
+        if analysis.series > 0 && bms.series_count as usize != analysis.series {
+            report.push(
+                Rule::BmsSeriesMismatch,
+                Severity::Error,
+                format!(
+                    "{} {} is for {}S packs but the pack is {}S",
+                    bms.manufacturer, bms.model, bms.series_count, analysis.series
+                ),
+                vec![component.uuid.clone()],
+            );
+        }
+        if bms.max_current_a < analysis.max_discharge_a {
+            report.push(
+                Rule::BmsUndersized,
+                Severity::Warning,
+                format!(
+                    "{} {} is rated for {:.1} A but the cells can deliver {:.1} A",
+                    bms.manufacturer, bms.model, bms.max_current_a, analysis.max_discharge_a
+                ),
+                vec![component.uuid.clone()],
+            );
+        }
+    }
+
+    report.errors = report
+        .issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count();
+    report.warnings = report.issues.len() - report.errors;
+    report.passed = report.errors == 0;
+    report
+}
+
+/// Bill of materials: cells and components by library entry, interconnect
+/// material by length.
+pub fn bill_of_materials(project: &ProjectFile, library: &PackLibrary) -> Bom {
+    let scene = &project.scene;
+    let mut lines = Vec::new();
+    let mut warnings = Vec::new();
+
+    let mut cell_counts: BTreeMap<i64, usize> = BTreeMap::new();
+    for cell in scene.cells.values() {
+        *cell_counts.entry(cell.cell_id).or_default() += 1;
+    }
+    for (id, count) in cell_counts {
+        match library.cells.get(&id) {
+            Some(cell) => lines.push(BomLine {
+                category: BomCategory::Cell,
+                key: Some(cell.library_key()),
+                description: format!(
+                    "{} {} ({} {}, {} mAh)",
+                    cell.manufacturer,
+                    cell.model,
+                    cell.form_factor,
+                    cell.chemistry,
+                    cell.capacity_mah
+                ),
+                quantity: count as f64,
+                unit: "pcs".to_string(),
+                weight_g: Some(cell.weight_g * count as f64),
+            }),
+            None => warnings.push(format!("{} cell(s) reference unknown cell {}", count, id)),
+        }
+    }
+
+    let mut lengths: BTreeMap<i64, f64> = BTreeMap::new();
+    let mut unspecified = 0;
+    for connection in scene.connections.values() {
+        let Some(id) = connection.material_id else {
+            unspecified += 1;
+            continue;
+        };
+        let path = export::connection_path(scene, connection);
+        let length_mm: f64 = path
+            .windows(2)
+            .map(|w| {
+                (0..3)
+                    .map(|i| (w[1][i] - w[0][i]).powi(2))
+                    .sum::<f64>()
+                    .sqrt()
+            })
+            .sum();
+        *lengths.entry(id).or_default() += length_mm;
+    }
+    for (id, length_mm) in lengths {
+        match library.materials.get(&id) {
+            Some(material) => {
+                let size = match (material.thickness_mm, material.width_mm) {
+                    (Some(t), Some(w)) => format!(", {} x {} mm", t, w),
+                    _ => String::new(),
+                };
+                lines.push(BomLine {
+                    category: BomCategory::Material,
+                    key: Some(material.library_key()),
+                    description: format!("{} ({}{})", material.name, material.material_type, size),
+                    // Whole millimetres are plenty for cutting lists
+                    quantity: (length_mm).round() / 1000.0,
+                    unit: "m".to_string(),
+                    weight_g: None,
+                })
+            }
+            None => warnings.push(format!("Connections reference unknown material {}", id)),
+        }
+    }
+    if unspecified > 0 {
+        warnings.push(format!(
+            "{} connection(s) have no material and are not listed",
+            unspecified
+        ));
+    }
+
+    let mut bms_counts: BTreeMap<i64, usize> = BTreeMap::new();
+    let mut shape_counts: BTreeMap<i64, usize> = BTreeMap::new();
+    let mut custom_counts: BTreeMap<String, usize> = BTreeMap::new();
+    for component in scene.components.values() {
+        match (component.component_type.as_str(), component.reference_id) {
+            ("bms", Some(id)) => *bms_counts.entry(id).or_default() += 1,
+            ("shape", Some(id)) => *shape_counts.entry(id).or_default() += 1,
+            _ => {
+                let name = component
+                    .custom_mesh_path
+                    .as_deref()
+                    .and_then(|p| Path::new(p).file_name())
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| component.component_type.clone());
+                *custom_counts.entry(name).or_default() += 1;
+            }
+        }
+    }
+    for (id, count) in bms_counts {
+        match library.bms.get(&id) {
+            Some(bms) => lines.push(BomLine {
+                category: BomCategory::Bms,
+                key: Some(bms.library_key()),
+                description: format!(
+                    "{} {} BMS ({}S, {} A)",
+                    bms.manufacturer, bms.model, bms.series_count, bms.max_current_a
+                ),
+                quantity: count as f64,
+                unit: "pcs".to_string(),
+                weight_g: None,
+            }),
+            None => warnings.push(format!(
+                "{} component(s) reference unknown BMS {}",
+                count, id
+            )),
+        }
+    }
+    for (id, count) in shape_counts {
+        match library.shapes.get(&id) {
+            Some(shape) => lines.push(BomLine {
+                category: BomCategory::Shape,
+                key: Some(shape.library_key()),
+                description: format!("{} ({})", shape.name, shape.category),
+                quantity: count as f64,
+                unit: "pcs".to_string(),
+                weight_g: None,
+            }),
+            None => warnings.push(format!(
+                "{} component(s) reference unknown shape {}",
+                count, id
+            )),
+        }
+    }
+    for (name, count) in custom_counts {
+        lines.push(BomLine {
+            category: BomCategory::Custom,
+            key: None,
+            description: format!("Custom part ({})", name),
+            quantity: count as f64,
+            unit: "pcs".to_string(),
+            weight_g: None,
+        });
+    }
+
+    Bom {
+        project: project.metadata.name.clone(),
+        total_weight_g: lines.iter().filter_map(|l| l.weight_g).sum(),
+        lines,
+        warnings,
+    }
+}
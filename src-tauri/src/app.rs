@@ -0,0 +1,975 @@
+mod api;
+mod jobs;
+
+use crate::{
+    analysis, autosave, bundle, catalog, cell_import, cell_samples, database, diff, library_file,
+    matching, validation,
+};
+use crate::analysis::{Bom, DrcReport, PackAnalysis, PackLibrary};
+use crate::autosave::{Autosave, AutosaveSnapshot, RecoveryInfo};
+use crate::bundle::{BundleExportResult, BundleImport, BundleImportReport};
+use crate::catalog::{Catalog, CatalogChangeset, CatalogStatus, CatalogSyncResult};
+use crate::cell_import::{CellImportOptions, CellImportReport};
+use crate::cell_samples::{CellSampleStats, SampleImportOptions, SampleImportReport};
+use crate::database::Database;
+use crate::diff::{MergeResult, ProjectDiff};
+use crate::error::{DatabaseError, ExportError, FilesystemError};
+use crate::filesystem::{Filesystem, ProjectFile, SaveFormat};
+use crate::history::{History, Snapshot, SnapshotComparison};
+use crate::export::{ExportFormat, ExportLibrary, ExportResult, Exporter};
+use crate::library_file::{LibraryExportOptions, LibraryExportResult};
+use crate::library_keys::LibraryKeys;
+use crate::matching::{MatchOptions, MatchedProject};
+use crate::paths::AppPaths;
+use crate::pool::{DatabasePool, DEFAULT_READERS};
+use crate::recent::{RecentProject, RecentProjects};
+use crate::recovery::RecoveredProject;
+use crate::validation::{LibraryIds, ValidatedProject};
+use api::{ApiServer, ApiServerStatus};
+use jobs::{ExportJob, JobManager, JobProgress};
+use tauri::{AppHandle, Emitter, Manager, RunEvent, State};
+
+pub struct AppState {
+    database: DatabasePool,
+    filesystem: Filesystem,
+    exporter: Exporter,
+    jobs: JobManager,
+    autosave: Autosave,
+    recent: RecentProjects,
+    api: ApiServer,
+}
+
+#[tauri::command]
+async fn get_cells(
+    search: Option<String>,
+    app: AppHandle,
+) -> Result<Vec<database::Cell>, DatabaseError> {
+    run_blocking(&app, move |state| {
+        let db = state.database.reader()?;
+        db.get_cells(search.as_deref())
+            .map_err(DatabaseError::context("Failed to get cells"))
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_cell_by_id(
+    id: i64,
+    app: AppHandle,
+) -> Result<Option<database::Cell>, DatabaseError> {
+    run_blocking(&app, move |state| {
+        let db = state.database.reader()?;
+        db.get_cell_by_id(id)
+            .map_err(DatabaseError::context("Failed to get cell"))
+    })
+    .await
+}
+
+/// Reads cells from a CSV or JSON datasheet and reports what importing
+/// them would add, update, skip or reject. The library is not changed.
+#[tauri::command]
+async fn preview_cell_import(
+    path: String,
+    options: CellImportOptions,
+    app: AppHandle,
+) -> Result<CellImportReport, FilesystemError> {
+    run_blocking(&app, move |state| {
+        let db = state.database.reader()?;
+        cell_import::import_cells(std::path::Path::new(&path), &options, &db, false)
+    })
+    .await
+}
+
+/// Imports cells from a CSV or JSON datasheet in one transaction.
+#[tauri::command]
+async fn import_cells(
+    path: String,
+    options: CellImportOptions,
+    app: AppHandle,
+) -> Result<CellImportReport, FilesystemError> {
+    run_blocking(&app, move |state| {
+        let db = state.database.writer();
+        cell_import::import_cells(std::path::Path::new(&path), &options, &db, true)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_cell_samples(
+    cell_id: i64,
+    app: AppHandle,
+) -> Result<Vec<database::CellSample>, DatabaseError> {
+    run_blocking(&app, move |state| {
+        let db = state.database.reader()?;
+        db.get_cell_samples(cell_id)
+            .map_err(DatabaseError::context("Failed to get cell samples"))
+    })
+    .await
+}
+
+/// Imports a tester's CSV log as measured samples of library cell `cell_id`.
+#[tauri::command]
+async fn import_cell_samples(
+    cell_id: i64,
+    path: String,
+    options: SampleImportOptions,
+    app: AppHandle,
+) -> Result<SampleImportReport, FilesystemError> {
+    run_blocking(&app, move |state| {
+        let db = state.database.writer();
+        cell_samples::import_samples(&db, cell_id, std::path::Path::new(&path), &options)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn delete_cell_sample(id: i64, app: AppHandle) -> Result<(), DatabaseError> {
+    run_blocking(&app, move |state| {
+        let db = state.database.writer();
+        let deleted = db
+            .delete_cell_sample(id)
+            .map_err(DatabaseError::context("Failed to delete cell sample"))?;
+        if deleted {
+            Ok(())
+        } else {
+            Err(DatabaseError::NotFound {
+                entity: "Cell sample",
+                id,
+            })
+        }
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_cell_sample_stats(
+    cell_id: i64,
+    bins: Option<usize>,
+    app: AppHandle,
+) -> Result<CellSampleStats, DatabaseError> {
+    run_blocking(&app, move |state| {
+        let db = state.database.reader()?;
+        cell_samples::statistics(&db, cell_id, bins)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_materials(
+    app: AppHandle,
+) -> Result<Vec<database::Material>, DatabaseError> {
+    run_blocking(&app, move |state| {
+        let db = state.database.reader()?;
+        db.get_materials()
+            .map_err(DatabaseError::context("Failed to get materials"))
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_shapes(
+    app: AppHandle,
+) -> Result<Vec<database::Shape>, DatabaseError> {
+    run_blocking(&app, move |state| {
+        let db = state.database.reader()?;
+        db.get_shapes()
+            .map_err(DatabaseError::context("Failed to get shapes"))
+    })
+    .await
+}
+
+/// Runs `work` on the blocking thread pool, so library queries and file I/O
+/// don't hold up the async runtime other commands are waiting on.
+async fn run_blocking<T, E>(
+    app: &AppHandle,
+    work: impl FnOnce(&AppState) -> Result<T, E> + Send + 'static,
+) -> Result<T, E>
+where
+    T: Send + 'static,
+    E: From<DatabaseError> + Send + 'static,
+{
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || work(&app.state::<AppState>()))
+        .await
+        .map_err(|e| DatabaseError::Task(e.to_string()))?
+}
+
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// Adds a project to the recent list. Failing to do so, or to render its
+/// thumbnail, never fails the save or load that triggered it.
+fn record_recent(state: &AppState, path: &std::path::Path, project: &ProjectFile) {
+    let thumbnail = load_export_library(state)
+        .ok()
+        .and_then(|library| state.exporter.thumbnail(project, &library, THUMBNAIL_SIZE).ok());
+    let _ = state
+        .recent
+        .record(&state.filesystem, path, project, thumbnail.as_deref());
+}
+
+fn load_library_keys(state: &AppState) -> Result<LibraryKeys, DatabaseError> {
+    let db = state.database.reader()?;
+    LibraryKeys::load(&db).map_err(DatabaseError::context("Failed to load library keys"))
+}
+
+/// Saves in `format`, or else in the format of the file being replaced, so
+/// plain projects kept under version control stay plain. The project is
+/// added to the recent list; the caller tells autosave about the save.
+fn write_project(
+    state: &AppState,
+    mut project: ProjectFile,
+    path: &std::path::Path,
+    format: Option<SaveFormat>,
+) -> Result<(), FilesystemError> {
+    load_library_keys(state)?.stamp(&mut project);
+    let format = format
+        .or_else(|| state.filesystem.detect_format(path))
+        .unwrap_or_default();
+    state.filesystem.save_project(&project, path, format)?;
+    record_recent(state, path, &project);
+    Ok(())
+}
+
+#[tauri::command]
+async fn save_project(
+    project: ProjectFile,
+    path: String,
+    format: Option<SaveFormat>,
+    app: AppHandle,
+) -> Result<(), FilesystemError> {
+    run_blocking(&app, move |state| {
+        let path = std::path::Path::new(&path);
+        write_project(state, project, path, format)?;
+        state.autosave.mark_saved(&state.filesystem, path)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn load_project(
+    path: String,
+    app: AppHandle,
+) -> Result<ProjectFile, FilesystemError> {
+    run_blocking(&app, move |state| {
+        let path = std::path::Path::new(&path);
+        let mut project = state.filesystem.load_project(path)?;
+
+        load_library_keys(state)?.resolve(&mut project);
+        record_recent(state, path, &project);
+        Ok(project)
+    })
+    .await
+}
+
+fn load_library_ids(state: &AppState) -> Result<LibraryIds, DatabaseError> {
+    let db = state.database.reader()?;
+    LibraryIds::load(&db).map_err(DatabaseError::context("Failed to load library ids"))
+}
+
+/// Loads a project and checks its references, repairing them if asked.
+#[tauri::command]
+async fn load_project_validated(
+    path: String,
+    repair: bool,
+    app: AppHandle,
+) -> Result<ValidatedProject, FilesystemError> {
+    run_blocking(&app, move |state| {
+        let path = std::path::Path::new(&path);
+        let mut project = state.filesystem.load_project(path)?;
+        load_library_keys(state)?.resolve(&mut project);
+        record_recent(state, path, &project);
+
+        let library = load_library_ids(state)?;
+        let report = validation::validate(&mut project, &library, repair);
+        Ok(ValidatedProject { project, report })
+    })
+    .await
+}
+
+#[tauri::command]
+async fn validate_project(
+    mut project: ProjectFile,
+    repair: bool,
+    app: AppHandle,
+) -> Result<ValidatedProject, DatabaseError> {
+    run_blocking(&app, move |state| {
+        let library = load_library_ids(state)?;
+        let report = validation::validate(&mut project, &library, repair);
+        Ok(ValidatedProject { project, report })
+    })
+    .await
+}
+
+fn load_pack_library(state: &AppState) -> Result<PackLibrary, DatabaseError> {
+    let db = state.database.reader()?;
+    PackLibrary::load(&db).map_err(DatabaseError::context("Failed to load component library"))
+}
+
+/// Configuration and electrical ratings of the pack's series string.
+#[tauri::command]
+async fn analyze_pack(
+    project: ProjectFile,
+    app: AppHandle,
+) -> Result<PackAnalysis, DatabaseError> {
+    run_blocking(&app, move |state| {
+        let library = load_pack_library(state)?;
+        Ok(analysis::analyze(&project, &library))
+    })
+    .await
+}
+
+#[tauri::command]
+async fn check_design_rules(
+    project: ProjectFile,
+    app: AppHandle,
+) -> Result<DrcReport, DatabaseError> {
+    run_blocking(&app, move |state| {
+        let library = load_pack_library(state)?;
+        Ok(analysis::check_design_rules(&project, &library))
+    })
+    .await
+}
+
+#[tauri::command]
+async fn generate_bom(
+    project: ProjectFile,
+    app: AppHandle,
+) -> Result<Bom, DatabaseError> {
+    run_blocking(&app, move |state| {
+        let library = load_pack_library(state)?;
+        Ok(analysis::bill_of_materials(&project, &library))
+    })
+    .await
+}
+
+/// Assigns the measured serials of the pack's cell models to the cells of
+/// its parallel groups, labelling each cell with its serial.
+#[tauri::command]
+async fn match_cells(
+    mut project: ProjectFile,
+    options: MatchOptions,
+    app: AppHandle,
+) -> Result<MatchedProject, FilesystemError> {
+    run_blocking(&app, move |state| {
+        let samples = {
+            let db = state.database.reader()?;
+            matching::measured_samples(&db, &project)
+                .map_err(DatabaseError::context("Failed to read cell samples"))?
+        };
+        let matching = matching::match_cells(&mut project, &samples, &options)?;
+        Ok(MatchedProject { project, matching })
+    })
+    .await
+}
+
+/// Lists what changed from `base` to `other`, matched by uuid.
+#[tauri::command]
+async fn diff_projects(
+    base: ProjectFile,
+    other: ProjectFile,
+) -> Result<ProjectDiff, FilesystemError> {
+    diff::diff(&base, &other)
+}
+
+/// Three-way merge of two edits of the same project.
+#[tauri::command]
+async fn merge_projects(
+    base: ProjectFile,
+    ours: ProjectFile,
+    theirs: ProjectFile,
+) -> Result<MergeResult, FilesystemError> {
+    diff::merge(&base, &ours, &theirs)
+}
+
+/// Saves a named snapshot into the history kept next to the project file.
+#[tauri::command]
+async fn create_snapshot(
+    path: String,
+    mut project: ProjectFile,
+    name: String,
+    note: Option<String>,
+    author: Option<String>,
+    app: AppHandle,
+) -> Result<Snapshot, FilesystemError> {
+    run_blocking(&app, move |state| {
+        load_library_keys(state)?.stamp(&mut project);
+        History::for_project(std::path::Path::new(&path))
+            .create(&state.filesystem, &project, name, note, author)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn list_snapshots(path: String, app: AppHandle) -> Result<Vec<Snapshot>, FilesystemError> {
+    run_blocking(&app, move |_| History::for_project(std::path::Path::new(&path)).list()).await
+}
+
+#[tauri::command]
+async fn restore_snapshot(
+    path: String,
+    id: String,
+    app: AppHandle,
+) -> Result<ProjectFile, FilesystemError> {
+    run_blocking(&app, move |state| {
+        let mut project = History::for_project(std::path::Path::new(&path))
+            .load(&state.filesystem, &id)?;
+
+        load_library_keys(state)?.resolve(&mut project);
+        Ok(project)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn delete_snapshot(
+    path: String,
+    id: String,
+    app: AppHandle,
+) -> Result<(), FilesystemError> {
+    run_blocking(&app, move |state| {
+        History::for_project(std::path::Path::new(&path)).delete(&state.filesystem, &id)
+    })
+    .await
+}
+
+/// Compares snapshot `from` with snapshot `to`, or with the saved project
+/// when `to` is omitted.
+#[tauri::command]
+async fn compare_snapshots(
+    path: String,
+    from: String,
+    to: Option<String>,
+    app: AppHandle,
+) -> Result<SnapshotComparison, FilesystemError> {
+    run_blocking(&app, move |state| {
+        let path = std::path::Path::new(&path);
+        History::for_project(path).compare(&state.filesystem, &from, to.as_deref(), || {
+            state.filesystem.load_project(path)
+        })
+    })
+    .await
+}
+
+/// Salvages what still parses from a project file that fails to load.
+#[tauri::command]
+async fn recover_project(
+    path: String,
+    app: AppHandle,
+) -> Result<RecoveredProject, FilesystemError> {
+    run_blocking(&app, move |state| {
+        let path = std::path::Path::new(&path);
+        let mut recovered = state.filesystem.recover_project(path)?;
+
+        load_library_keys(state)?.resolve(&mut recovered.project);
+        Ok(recovered)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn create_new_project(
+    name: String,
+    state: State<'_, AppState>,
+) -> Result<ProjectFile, FilesystemError> {
+    Ok(state.filesystem.create_new_project(name))
+}
+
+fn load_export_library(state: &AppState) -> Result<ExportLibrary, DatabaseError> {
+    let db = state.database.reader()?;
+    let cells = db
+        .get_cells(None)
+        .map_err(DatabaseError::context("Failed to load component library"))?;
+    let bms = db
+        .get_bms()
+        .map_err(DatabaseError::context("Failed to load component library"))?;
+    Ok(ExportLibrary::new(cells, bms))
+}
+
+#[tauri::command]
+async fn get_export_formats(
+    state: State<'_, AppState>,
+) -> Result<std::collections::HashMap<String, String>, ExportError> {
+    Ok(state.exporter.get_format_extensions())
+}
+
+#[tauri::command]
+async fn export_project(
+    project: ProjectFile,
+    format: ExportFormat,
+    path: String,
+    app: AppHandle,
+) -> Result<ExportResult, ExportError> {
+    run_blocking(&app, move |state| {
+        state.exporter.validate(&format)?;
+
+        let library = load_export_library(state)?;
+        state
+            .exporter
+            .export_to_file(&state.filesystem, &project, &library, &format, &path, &())
+    })
+    .await
+}
+
+/// Starts an export on a worker thread and returns the queued job.
+///
+/// Progress, completion and failure are reported through `export-progress`
+/// events carrying the updated `ExportJob`.
+#[tauri::command]
+async fn start_export(
+    project: ProjectFile,
+    format: ExportFormat,
+    path: String,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<ExportJob, ExportError> {
+    state.exporter.validate(&format)?;
+
+    let (job, cancel) = state.jobs.create(format.name(), &path);
+    let _ = app.emit(jobs::PROGRESS_EVENT, job.clone());
+
+    let id = job.id.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        let progress = JobProgress::new(&app, &state.jobs, &id, cancel);
+        let outcome = load_export_library(&state)
+            .map_err(ExportError::from)
+            .and_then(|library| {
+                state.exporter.export_to_file(
+                    &state.filesystem,
+                    &project,
+                    &library,
+                    &format,
+                    &path,
+                    &progress,
+                )
+            });
+        if let Some(job) = state.jobs.finish(&id, outcome) {
+            let _ = app.emit(jobs::PROGRESS_EVENT, job);
+        }
+    });
+
+    Ok(job)
+}
+
+#[tauri::command]
+async fn cancel_export(
+    job_id: String,
+    state: State<'_, AppState>,
+) -> Result<(), ExportError> {
+    state.jobs.cancel(&job_id)
+}
+
+#[tauri::command]
+async fn get_export_jobs(
+    state: State<'_, AppState>,
+) -> Result<Vec<ExportJob>, ExportError> {
+    Ok(state.jobs.list())
+}
+
+/// Hands the current state of the open project to the autosave service.
+#[tauri::command]
+async fn update_autosave(
+    project: ProjectFile,
+    path: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<(), FilesystemError> {
+    state.autosave.update(project, path);
+    Ok(())
+}
+
+#[tauri::command]
+async fn list_autosaves(
+    state: State<'_, AppState>,
+) -> Result<Vec<AutosaveSnapshot>, FilesystemError> {
+    Ok(state.autosave.snapshots())
+}
+
+/// Snapshot newer than the last explicit save, if the previous session crashed.
+#[tauri::command]
+async fn get_recovery_info(
+    state: State<'_, AppState>,
+) -> Result<Option<RecoveryInfo>, FilesystemError> {
+    Ok(state.autosave.recovery_info())
+}
+
+#[tauri::command]
+async fn recover_autosave(
+    app: AppHandle,
+) -> Result<ProjectFile, FilesystemError> {
+    run_blocking(&app, move |state| {
+        let mut project = state.autosave.recover(&state.filesystem)?;
+
+        load_library_keys(state)?.resolve(&mut project);
+        Ok(project)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn dismiss_recovery(
+    state: State<'_, AppState>,
+) -> Result<(), FilesystemError> {
+    state.autosave.dismiss_recovery();
+    Ok(())
+}
+
+/// Writes the project with the library rows and meshes it uses into a
+/// single zip file.
+#[tauri::command]
+async fn export_bundle(
+    mut project: ProjectFile,
+    path: String,
+    app: AppHandle,
+) -> Result<BundleExportResult, FilesystemError> {
+    run_blocking(&app, move |state| {
+        load_library_keys(state)?.stamp(&mut project);
+        let resource_dir = state.filesystem.get_resource_dir();
+        let (bundle, warnings) = {
+            let db = state.database.reader()?;
+            bundle::collect(project, &db, resource_dir.as_deref())?
+        };
+
+        let data = bundle::write(&bundle)?;
+        let path = std::path::Path::new(&path);
+        state.filesystem.write_atomic(&data, path)?;
+
+        Ok(BundleExportResult {
+            path: path.to_string_lossy().into_owned(),
+            file_size: data.len() as u64,
+            cells: bundle.library.cells.len(),
+            bms: bundle.library.bms.len(),
+            materials: bundle.library.materials.len(),
+            shapes: bundle.library.shapes.len(),
+            meshes: bundle.meshes.len(),
+            warnings,
+        })
+    })
+    .await
+}
+
+/// Opens a bundle, adding any library rows missing locally.
+#[tauri::command]
+async fn import_bundle(
+    path: String,
+    app: AppHandle,
+) -> Result<BundleImport, FilesystemError> {
+    run_blocking(&app, move |state| {
+        let bundle = bundle::open(std::path::Path::new(&path))?;
+        let mesh_dir = state.filesystem.get_mesh_dir()?;
+
+        let db = state.database.writer();
+        bundle::import(bundle, &db, &state.filesystem, &mesh_dir)
+    })
+    .await
+}
+
+/// Writes library tables to a CSV or JSON file for sharing with other
+/// installs.
+#[tauri::command]
+async fn export_library(
+    path: String,
+    options: LibraryExportOptions,
+    app: AppHandle,
+) -> Result<LibraryExportResult, FilesystemError> {
+    run_blocking(&app, move |state| {
+        let db = state.database.reader()?;
+        library_file::export(&state.filesystem, &db, std::path::Path::new(&path), &options)
+    })
+    .await
+}
+
+/// Adds the rows of exported library files that aren't in the library yet.
+#[tauri::command]
+async fn import_library_files(
+    paths: Vec<String>,
+    app: AppHandle,
+) -> Result<BundleImportReport, FilesystemError> {
+    run_blocking(&app, move |state| {
+        let paths: Vec<_> = paths.iter().map(std::path::PathBuf::from).collect();
+        let library = library_file::read(&paths)?;
+
+        let db = state.database.writer();
+        let mut report = BundleImportReport::default();
+        bundle::import_library(&library, &db, &Default::default(), &mut report)?;
+        Ok(report)
+    })
+    .await
+}
+
+/// The catalog the library was last synced from, if any.
+#[tauri::command]
+async fn get_catalog_status(app: AppHandle) -> Result<CatalogStatus, DatabaseError> {
+    run_blocking(&app, move |state| catalog::status(&state.database.reader()?)).await
+}
+
+/// Fetches the catalog at `source`, a path or an http(s) URL, and lists
+/// what syncing with it would change.
+#[tauri::command]
+async fn review_catalog(
+    source: String,
+    app: AppHandle,
+) -> Result<CatalogChangeset, FilesystemError> {
+    run_blocking(&app, move |state| {
+        let catalog = Catalog::fetch(&source)?;
+        Ok(catalog::review(&state.database.reader()?, &catalog)?)
+    })
+    .await
+}
+
+/// Applies the approved changes from a review of the catalog at `source`.
+/// `revision` comes from that review; a catalog that changed since is
+/// refused.
+#[tauri::command]
+async fn apply_catalog(
+    source: String,
+    revision: String,
+    approved: Vec<String>,
+    app: AppHandle,
+) -> Result<CatalogSyncResult, FilesystemError> {
+    run_blocking(&app, move |state| {
+        let catalog = Catalog::fetch(&source)?;
+        catalog.check_revision(&revision)?;
+        Ok(catalog::apply(&state.database.writer(), &catalog, &approved)?)
+    })
+    .await
+}
+
+/// Recently opened projects for the start screen, pinned first.
+#[tauri::command]
+async fn get_recent_projects(
+    state: State<'_, AppState>,
+) -> Result<Vec<RecentProject>, FilesystemError> {
+    Ok(state.recent.list())
+}
+
+#[tauri::command]
+async fn set_recent_project_pinned(
+    path: String,
+    pinned: bool,
+    app: AppHandle,
+) -> Result<(), FilesystemError> {
+    run_blocking(&app, move |state| {
+        state.recent.set_pinned(&state.filesystem, &path, pinned)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn remove_recent_project(
+    path: String,
+    app: AppHandle,
+) -> Result<(), FilesystemError> {
+    run_blocking(&app, move |state| state.recent.remove(&state.filesystem, &path)).await
+}
+
+/// Forgets recent projects whose files are gone; returns their paths.
+#[tauri::command]
+async fn remove_missing_recent_projects(
+    app: AppHandle,
+) -> Result<Vec<String>, FilesystemError> {
+    run_blocking(&app, |state| state.recent.remove_missing(&state.filesystem)).await
+}
+
+/// A recent project's thumbnail as a `data:image/png` URL.
+#[tauri::command]
+async fn get_project_thumbnail(
+    path: String,
+    state: State<'_, AppState>,
+) -> Result<Option<String>, FilesystemError> {
+    use base64::Engine;
+
+    let thumbnail = state
+        .recent
+        .list()
+        .into_iter()
+        .find(|entry| entry.path == path)
+        .and_then(|entry| entry.thumbnail_path)
+        .and_then(|thumbnail| std::fs::read(thumbnail).ok());
+
+    Ok(thumbnail.map(|png| {
+        format!(
+            "data:image/png;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(png)
+        )
+    }))
+}
+
+#[tauri::command]
+async fn import_mesh(
+    path: String,
+    app: AppHandle,
+) -> Result<Vec<u8>, FilesystemError> {
+    run_blocking(&app, move |state| {
+        state.filesystem.import_mesh(std::path::Path::new(&path))
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_api_server_status(
+    state: State<'_, AppState>,
+) -> Result<ApiServerStatus, FilesystemError> {
+    Ok(state.api.status())
+}
+
+/// Starts the local API server, by default on the address it last used.
+#[tauri::command]
+async fn start_api_server(
+    bind: Option<String>,
+    port: Option<u16>,
+    app: AppHandle,
+) -> Result<ApiServerStatus, FilesystemError> {
+    let handle = app.clone();
+    run_blocking(&app, move |state| {
+        state.api.start(&state.filesystem, handle, bind, port)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn stop_api_server(app: AppHandle) -> Result<ApiServerStatus, FilesystemError> {
+    // Waits for the workers to finish their requests
+    run_blocking(&app, |state| state.api.stop(&state.filesystem)).await
+}
+
+#[tauri::command]
+async fn regenerate_api_token(app: AppHandle) -> Result<ApiServerStatus, FilesystemError> {
+    run_blocking(&app, |state| state.api.regenerate_token(&state.filesystem)).await
+}
+
+/// Where the desktop app keeps its data and finds its bundled resources.
+fn resolve_paths(app: &AppHandle) -> Result<AppPaths, String> {
+    let data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data directory: {:?}", e))?;
+    Ok(AppPaths::new(data_dir, app.path().resource_dir().ok()))
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    tauri::Builder::default()
+        .plugin(tauri_plugin_opener::init())
+        .setup(|app| {
+            // Initialize database
+            let paths = resolve_paths(app.handle())?;
+            let db = Database::install(&paths.library_db(), paths.bundled_library_db().as_deref())?;
+            db.init_schema()?;
+            db.seed_data()?;
+            drop(db);
+            let database = DatabasePool::open(&paths.library_db(), DEFAULT_READERS)?;
+
+            // Initialize filesystem and exporter
+            let filesystem = Filesystem::new(paths);
+            let exporter = Exporter::new();
+            let jobs = JobManager::new();
+            let autosave = Autosave::start(filesystem.get_autosave_dir()?)?;
+            let recent = RecentProjects::open(filesystem.get_app_data_dir()?);
+            let api = ApiServer::open(&filesystem.get_app_data_dir()?);
+
+            // Store in app state
+            app.manage(AppState {
+                database,
+                filesystem,
+                exporter,
+                jobs,
+                autosave,
+                recent,
+                api,
+            });
+
+            // A busy port shouldn't keep the app from starting
+            let state = app.state::<AppState>();
+            if let Err(e) = state.api.start_if_enabled(&state.filesystem, app.handle().clone()) {
+                let _ = app.handle().emit("api-server-failed", e.to_string());
+            }
+
+            // Write autosave snapshots in the background
+            let handle = app.handle().clone();
+            std::thread::spawn(move || loop {
+                std::thread::sleep(autosave::TICK);
+                let state = handle.state::<AppState>();
+                let stamp = |project: &mut ProjectFile| -> Result<(), FilesystemError> {
+                    load_library_keys(&state)?.stamp(project);
+                    Ok(())
+                };
+                match state.autosave.tick(&state.filesystem, stamp) {
+                    Ok(Some(snapshot)) => {
+                        let _ = handle.emit("autosave-saved", snapshot);
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        let _ = handle.emit("autosave-failed", e.to_string());
+                    }
+                }
+            });
+
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            get_cells,
+            get_cell_by_id,
+            preview_cell_import,
+            import_cells,
+            get_cell_samples,
+            import_cell_samples,
+            delete_cell_sample,
+            get_cell_sample_stats,
+            get_materials,
+            get_shapes,
+            save_project,
+            load_project,
+            load_project_validated,
+            validate_project,
+            analyze_pack,
+            check_design_rules,
+            generate_bom,
+            match_cells,
+            diff_projects,
+            merge_projects,
+            create_snapshot,
+            list_snapshots,
+            restore_snapshot,
+            delete_snapshot,
+            compare_snapshots,
+            recover_project,
+            create_new_project,
+            get_export_formats,
+            export_project,
+            start_export,
+            cancel_export,
+            get_export_jobs,
+            update_autosave,
+            list_autosaves,
+            get_recovery_info,
+            recover_autosave,
+            dismiss_recovery,
+            get_recent_projects,
+            set_recent_project_pinned,
+            remove_recent_project,
+            remove_missing_recent_projects,
+            get_project_thumbnail,
+            get_api_server_status,
+            start_api_server,
+            stop_api_server,
+            regenerate_api_token,
+            export_bundle,
+            import_bundle,
+            export_library,
+            import_library_files,
+            get_catalog_status,
+            review_catalog,
+            apply_catalog,
+            import_mesh
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let RunEvent::Exit = event {
+                if let Some(state) = app.try_state::<AppState>() {
+                    state.autosave.shutdown();
+                    state.api.shutdown();
+                }
+            }
+        });
+}
@@ -0,0 +1,1077 @@
+use crate::analysis;
+use crate::error::{DatabaseError, ErrorCode, ExportError, FilesystemError};
+use crate::export::ExportFormat;
+use crate::filesystem::{Filesystem, ProjectFile, SaveFormat};
+use crate::validation::{self, ValidatedProject};
+use super::AppState;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use tauri::{AppHandle, Manager};
+use tiny_http::{Header, Method, Request, Response, Server};
+use uuid::Uuid;
+
+const CONFIG_FILE: &str = "api_server.json";
+const DEFAULT_BIND: &str = "127.0.0.1";
+const DEFAULT_PORT: u16 = 7420;
+const PREFIX: &str = "/api/v1";
+const WORKERS: usize = 4;
+const MAX_BODY_BYTES: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiConfig {
+    /// Start the server when the app launches
+    pub enabled: bool,
+    /// Address to listen on; only loopback unless changed
+    pub bind: String,
+    pub port: u16,
+    /// Bearer token every request except `health` and `openapi.json` needs
+    pub token: String,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        ApiConfig {
+            enabled: false,
+            bind: DEFAULT_BIND.to_string(),
+            port: DEFAULT_PORT,
+            token: new_token(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiServerStatus {
+    pub running: bool,
+    /// Base URL of the API while running, e.g. `http://127.0.0.1:7420/api/v1`
+    pub url: Option<String>,
+    pub config: ApiConfig,
+}
+
+struct Running {
+    server: Arc<Server>,
+    stopping: Arc<AtomicBool>,
+    workers: Vec<JoinHandle<()>>,
+    url: String,
+}
+
+/// Embedded HTTP server exposing library, project, analysis and export
+/// operations to other programs on the workstation.
+pub struct ApiServer {
+    config_path: PathBuf,
+    config: Mutex<ApiConfig>,
+    running: Mutex<Option<Running>>,
+}
+
+impl ApiServer {
+    pub fn open(data_dir: &Path) -> Self {
+        let config_path = data_dir.join(CONFIG_FILE);
+        let config = fs::read_to_string(&config_path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+
+        ApiServer {
+            config_path,
+            config: Mutex::new(config),
+            running: Mutex::new(None),
+        }
+    }
+
+    pub fn status(&self) -> ApiServerStatus {
+        let url = self.running.lock().unwrap().as_ref().map(|r| r.url.clone());
+        ApiServerStatus {
+            running: url.is_some(),
+            url,
+            config: self.config.lock().unwrap().clone(),
+        }
+    }
+
+    /// Starts the server if it was left enabled.
+    pub fn start_if_enabled(&self, filesystem: &Filesystem, app: AppHandle) -> Result<(), FilesystemError> {
+        if self.config.lock().unwrap().enabled {
+            self.start(filesystem, app, None, None)?;
+        }
+        Ok(())
+    }
+
+    /// (Re)starts the server, optionally on a new address, and enables it
+    /// for future launches.
+    pub fn start(
+        &self,
+        filesystem: &Filesystem,
+        app: AppHandle,
+        bind: Option<String>,
+        port: Option<u16>,
+    ) -> Result<ApiServerStatus, FilesystemError> {
+        self.shutdown();
+
+        let mut config = self.config.lock().unwrap().clone();
+        config.bind = bind.unwrap_or(config.bind);
+        config.port = port.unwrap_or(config.port);
+
+        let server = Server::http((config.bind.as_str(), config.port)).map_err(|e| {
+            FilesystemError::Unavailable(format!(
+                "Failed to start API server on {}:{}: {}",
+                config.bind, config.port, e
+            ))
+        })?;
+        let server = Arc::new(server);
+        let stopping = Arc::new(AtomicBool::new(false));
+        let workers = (0..WORKERS)
+            .map(|_| {
+                let (app, server, stopping) = (app.clone(), server.clone(), stopping.clone());
+                std::thread::spawn(move || serve(app, server, stopping))
+            })
+            .collect();
+
+        *self.running.lock().unwrap() = Some(Running {
+            server,
+            stopping,
+            workers,
+            url: format!("http://{}:{}{}", config.bind, config.port, PREFIX),
+        });
+
+        config.enabled = true;
+        self.save(filesystem, config)?;
+        Ok(self.status())
+    }
+
+    /// Stops the server and keeps it off on future launches.
+    pub fn stop(&self, filesystem: &Filesystem) -> Result<ApiServerStatus, FilesystemError> {
+        self.shutdown();
+        let mut config = self.config.lock().unwrap().clone();
+        config.enabled = false;
+        self.save(filesystem, config)?;
+        Ok(self.status())
+    }
+
+    /// Replaces the token; requests with the old one are refused from now on.
+    pub fn regenerate_token(&self, filesystem: &Filesystem) -> Result<ApiServerStatus, FilesystemError> {
+        let mut config = self.config.lock().unwrap().clone();
+        config.token = new_token();
+        self.save(filesystem, config)?;
+        Ok(self.status())
+    }
+
+    /// Stops the server without changing the saved settings, e.g. on exit.
+    pub fn shutdown(&self) {
+        let Some(running) = self.running.lock().unwrap().take() else {
+            return;
+        };
+        running.stopping.store(true, Ordering::SeqCst);
+        // Each call wakes one worker blocked waiting for a request
+        for _ in &running.workers {
+            running.server.unblock();
+        }
+        for worker in running.workers {
+            let _ = worker.join();
+        }
+    }
+
+    fn token_matches(&self, token: &str) -> bool {
+        let expected = self.config.lock().unwrap().token.clone();
+        // Compare every byte so timing doesn't reveal the matching prefix
+        expected.len() == token.len()
+            && expected
+                .bytes()
+                .zip(token.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+
+    fn save(&self, filesystem: &Filesystem, config: ApiConfig) -> Result<(), FilesystemError> {
+        if let Some(dir) = self.config_path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(FilesystemError::io("Failed to create app data directory", dir))?;
+        }
+        let json = serde_json::to_vec_pretty(&config).map_err(|e| {
+            FilesystemError::Serialization(format!("Failed to serialize API settings: {}", e))
+        })?;
+        filesystem.write_atomic(&json, &self.config_path)?;
+        *self.config.lock().unwrap() = config;
+        Ok(())
+    }
+}
+
+fn new_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+fn serve(app: AppHandle, server: Arc<Server>, stopping: Arc<AtomicBool>) {
+    loop {
+        let mut request = match server.recv() {
+            Ok(request) => request,
+            Err(_) if stopping.load(Ordering::SeqCst) => break,
+            // A client that failed to connect; keep serving the others
+            Err(_) => continue,
+        };
+
+        let state = app.state::<AppState>();
+        let reply = route(&state, &mut request).unwrap_or_else(ApiError::into_reply);
+        let response = reply
+            .into_response()
+            .or_else(|e| e.into_reply().into_response());
+        if let Ok(response) = response {
+            let _ = request.respond(response);
+        }
+    }
+}
+
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+    file_name: Option<String>,
+}
+
+impl Reply {
+    fn json(value: &impl Serialize) -> Result<Reply, ApiError> {
+        let body = serde_json::to_vec(value)
+            .map_err(|e| ApiError::internal(format!("Failed to serialize response: {}", e)))?;
+        Ok(Reply {
+            status: 200,
+            content_type: "application/json",
+            body,
+            file_name: None,
+        })
+    }
+
+    fn no_content() -> Result<Reply, ApiError> {
+        Ok(Reply {
+            status: 204,
+            content_type: "application/json",
+            body: Vec::new(),
+            file_name: None,
+        })
+    }
+
+    fn into_response(self) -> Result<Response<std::io::Cursor<Vec<u8>>>, ApiError> {
+        let mut response = Response::from_data(self.body)
+            .with_status_code(self.status)
+            .with_header(header("Content-Type", self.content_type)?);
+        if let Some(file_name) = self.file_name {
+            response = response.with_header(header(
+                "Content-Disposition",
+                &content_disposition(&file_name),
+            )?);
+        }
+        Ok(response)
+    }
+}
+
+fn header(name: &str, value: &str) -> Result<Header, ApiError> {
+    Header::from_bytes(name.as_bytes(), value.as_bytes())
+        .map_err(|_| ApiError::internal(format!("Invalid {} header", name)))
+}
+
+/// `attachment` with an ASCII `filename` for old clients and the exact name
+/// as RFC 5987 `filename*`. Header values must be ASCII without line
+/// breaks, and project names are neither.
+fn content_disposition(file_name: &str) -> String {
+    let fallback: String = file_name
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+    let encoded: String = file_name
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => (b as char).to_string(),
+            b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback, encoded
+    )
+}
+
+struct ApiError {
+    status: u16,
+    code: ErrorCode,
+    message: String,
+}
+
+impl ApiError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        ApiError::with_code(ErrorCode::InvalidInput, message)
+    }
+
+    fn not_found(message: impl Into<String>) -> Self {
+        ApiError::with_code(ErrorCode::NotFound, message)
+    }
+
+    fn internal(message: impl Into<String>) -> Self {
+        ApiError::with_code(ErrorCode::Internal, message)
+    }
+
+    fn with_code(code: ErrorCode, message: impl Into<String>) -> Self {
+        let status = match code {
+            ErrorCode::NotFound => 404,
+            ErrorCode::PermissionDenied => 403,
+            ErrorCode::InvalidInput | ErrorCode::Corrupt | ErrorCode::VersionTooNew => 400,
+            ErrorCode::ConstraintViolation => 409,
+            ErrorCode::Busy | ErrorCode::Unavailable => 503,
+            ErrorCode::Io | ErrorCode::Internal => 500,
+        };
+        ApiError {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn into_reply(self) -> Reply {
+        Reply {
+            status: self.status,
+            content_type: "application/json",
+            body: serde_json::to_vec(&json!({ "error": self.message, "code": self.code }))
+                .unwrap_or_default(),
+            file_name: None,
+        }
+    }
+}
+
+impl From<DatabaseError> for ApiError {
+    fn from(e: DatabaseError) -> Self {
+        ApiError::with_code(e.code(), e.to_string())
+    }
+}
+
+impl From<FilesystemError> for ApiError {
+    fn from(e: FilesystemError) -> Self {
+        ApiError::with_code(e.code(), e.to_string())
+    }
+}
+
+impl From<ExportError> for ApiError {
+    fn from(e: ExportError) -> Self {
+        ApiError::with_code(e.code(), e.to_string())
+    }
+}
+
+/// A project sent inline or, with `path`, read from the workstation's disk.
+#[derive(Deserialize)]
+struct ProjectSource {
+    project: Option<ProjectFile>,
+    path: Option<String>,
+}
+
+impl ProjectSource {
+    fn load(self, state: &AppState) -> Result<ProjectFile, ApiError> {
+        match (self.project, self.path) {
+            (Some(mut project), _) => {
+                // Ids in a project from another install only mean something
+                // through its keys
+                super::load_library_keys(state)?.resolve(&mut project);
+                Ok(project)
+            }
+            (None, Some(path)) => {
+                let mut project = state.filesystem.load_project(Path::new(&path))?;
+                super::load_library_keys(state)?.resolve(&mut project);
+                Ok(project)
+            }
+            (None, None) => Err(ApiError::bad_request("Send either \"project\" or \"path\"")),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SaveRequest {
+    project: ProjectFile,
+    path: String,
+    format: Option<SaveFormat>,
+}
+
+#[derive(Deserialize)]
+struct ValidateRequest {
+    #[serde(flatten)]
+    source: ProjectSource,
+    #[serde(default)]
+    repair: bool,
+}
+
+#[derive(Deserialize)]
+struct ExportRequest {
+    #[serde(flatten)]
+    source: ProjectSource,
+    format: ExportFormat,
+    /// Where to write the export on the workstation; without it the file is
+    /// returned in the response
+    output: Option<String>,
+}
+
+fn route(state: &AppState, request: &mut Request) -> Result<Reply, ApiError> {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let method = request.method().clone();
+    let route = path
+        .strip_prefix(PREFIX)
+        .ok_or_else(|| ApiError::not_found(format!("No route for {}", path)))?;
+    let segments: Vec<&str> = route.trim_matches('/').split('/').collect();
+
+    // Open to anyone who can reach the port
+    match (&method, segments.as_slice()) {
+        (Method::Get, ["health"]) => {
+            return Reply::json(&json!({ "status": "ok", "version": env!("CARGO_PKG_VERSION") }))
+        }
+        (Method::Get, ["openapi.json"]) => return Reply::json(&openapi()),
+        _ => {}
+    }
+
+    let authorized = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .and_then(|h| h.value.as_str().strip_prefix("Bearer "))
+        .is_some_and(|token| state.api.token_matches(token.trim()));
+    if !authorized {
+        return Err(ApiError {
+            status: 401,
+            code: ErrorCode::PermissionDenied,
+            message: "Missing or invalid bearer token".to_string(),
+        });
+    }
+
+    let db_error = DatabaseError::context("Failed to query library");
+    match (&method, segments.as_slice()) {
+        (Method::Get, ["library", "cells"]) => {
+            let search = query_param(query, "search");
+            let db = state.database.reader()?;
+            Reply::json(&db.get_cells(search.as_deref()).map_err(db_error)?)
+        }
+        (Method::Get, ["library", "cells", id]) => {
+            let id: i64 = id
+                .parse()
+                .map_err(|_| ApiError::bad_request("Cell id must be a number"))?;
+            let db = state.database.reader()?;
+            match db.get_cell_by_id(id).map_err(db_error)? {
+                Some(cell) => Reply::json(&cell),
+                None => Err(DatabaseError::NotFound { entity: "Cell", id }.into()),
+            }
+        }
+        (Method::Get, ["library", "bms"]) => {
+            Reply::json(&state.database.reader()?.get_bms().map_err(db_error)?)
+        }
+        (Method::Get, ["library", "materials"]) => Reply::json(
+            &state
+                .database
+                .reader()?
+                .get_materials()
+                .map_err(db_error)?,
+        ),
+        (Method::Get, ["library", "shapes"]) => Reply::json(
+            &state
+                .database
+                .reader()?
+                .get_shapes()
+                .map_err(db_error)?,
+        ),
+        (Method::Post, ["projects", "load"]) => {
+            let source: ProjectSource = read_json(request)?;
+            if source.path.is_none() {
+                return Err(ApiError::bad_request("\"path\" is required"));
+            }
+            Reply::json(&source.load(state)?)
+        }
+        (Method::Post, ["projects", "save"]) => {
+            let SaveRequest {
+                project,
+                path,
+                format,
+            } = read_json(request)?;
+            let path = Path::new(&path);
+            super::write_project(state, project, path, format)?;
+            state.autosave.record_save(&state.filesystem, path)?;
+            Reply::no_content()
+        }
+        (Method::Post, ["projects", "validate"]) => {
+            let ValidateRequest { source, repair } = read_json(request)?;
+            let mut project = source.load(state)?;
+            let library = super::load_library_ids(state)?;
+            let report = validation::validate(&mut project, &library, repair);
+            Reply::json(&ValidatedProject { project, report })
+        }
+        (Method::Post, ["analysis", "pack"]) => {
+            let project = read_json::<ProjectSource>(request)?.load(state)?;
+            let library = super::load_pack_library(state)?;
+            Reply::json(&analysis::analyze(&project, &library))
+        }
+        (Method::Post, ["analysis", "drc"]) => {
+            let project = read_json::<ProjectSource>(request)?.load(state)?;
+            let library = super::load_pack_library(state)?;
+            Reply::json(&analysis::check_design_rules(&project, &library))
+        }
+        (Method::Post, ["analysis", "bom"]) => {
+            let project = read_json::<ProjectSource>(request)?.load(state)?;
+            let library = super::load_pack_library(state)?;
+            let bom = analysis::bill_of_materials(&project, &library);
+            match query_param(query, "format").as_deref() {
+                Some("csv") => Ok(Reply {
+                    status: 200,
+                    content_type: "text/csv",
+                    body: bom.to_csv().into_bytes(),
+                    file_name: None,
+                }),
+                _ => Reply::json(&bom),
+            }
+        }
+        (Method::Post, ["export"]) => {
+            let ExportRequest {
+                source,
+                format,
+                output,
+            } = read_json(request)?;
+            let project = source.load(state)?;
+            state.exporter.validate(&format)?;
+            let library = super::load_export_library(state)?;
+
+            match output {
+                Some(path) => {
+                    let result = state
+                        .exporter
+                        .export_to_file(&state.filesystem, &project, &library, &format, &path, &())?;
+                    Reply::json(&result)
+                }
+                None => {
+                    // Only the main file; companions need `output` to land next to it
+                    let output = state
+                        .exporter
+                        .export(&project, &library, &format, &())?;
+                    Ok(Reply {
+                        status: 200,
+                        content_type: "application/octet-stream",
+                        body: output.data,
+                        file_name: Some(format!(
+                            "{}.{}",
+                            project.metadata.name,
+                            format.extension()
+                        )),
+                    })
+                }
+            }
+        }
+        _ => Err(ApiError::not_found(format!(
+            "No route for {} {}",
+            method, path
+        ))),
+    }
+}
+
+fn read_json<T: DeserializeOwned>(request: &mut Request) -> Result<T, ApiError> {
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(MAX_BODY_BYTES + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| ApiError::bad_request(format!("Failed to read request body: {}", e)))?;
+    if body.len() as u64 > MAX_BODY_BYTES {
+        return Err(ApiError {
+            status: 413,
+            code: ErrorCode::InvalidInput,
+            message: format!("Request body is larger than {} bytes", MAX_BODY_BYTES),
+        });
+    }
+    serde_json::from_slice(&body)
+        .map_err(|e| ApiError::bad_request(format!("Invalid request body: {}", e)))
+}
+
+/// Value of `name` in a URL query string, percent-decoded.
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        (key == name).then(|| percent_decode(value))
+    })
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Component schemas of the OpenAPI document, listing the fields of the
+/// types the endpoints exchange. Keep in step with their serde forms.
+fn schemas() -> Value {
+    let schema = |name: &str| json!({ "$ref": format!("#/components/schemas/{}", name) });
+    let array = |items: Value| json!({ "type": "array", "items": items });
+    let map = |values: Value| json!({ "type": "object", "additionalProperties": values });
+    let nullable = |kind: &str| json!({ "type": kind, "nullable": true });
+    let object = |required: &[&str], properties: Value| {
+        json!({ "type": "object", "required": required, "properties": properties })
+    };
+    let string = json!({ "type": "string" });
+    let number = json!({ "type": "number" });
+    let integer = json!({ "type": "integer" });
+    let boolean = json!({ "type": "boolean" });
+    let library_flags = |mut row: Value| {
+        row["properties"]["user_added"] = json!({ "type": "boolean", "default": false });
+        row["properties"]["deprecated"] = json!({ "type": "boolean", "default": false });
+        row
+    };
+    let export_options = |extra: Value| {
+        let mut options = object(&["selection", "file_name"], json!({
+            "selection": schema("ExportSelection"),
+            "file_name": string
+        }));
+        for (name, kind) in extra.as_object().into_iter().flatten() {
+            options["properties"][name] = kind.clone();
+            options["required"].as_array_mut().unwrap().push(json!(name));
+        }
+        options
+    };
+    let export_format = |format: &str, options: Value| {
+        object(&["format", "options"], json!({
+            "format": { "type": "string", "enum": [format] },
+            "options": options
+        }))
+    };
+
+    json!({
+        "Cell": library_flags(object(
+            &["id", "manufacturer", "model", "form_factor", "chemistry", "nominal_voltage",
+              "max_voltage", "min_voltage", "capacity_mah", "max_discharge_a", "max_charge_a",
+              "weight_g", "length_mm"],
+            json!({
+                "id": integer,
+                "manufacturer": string,
+                "model": string,
+                "form_factor": string,
+                "chemistry": string,
+                "nominal_voltage": number,
+                "max_voltage": number,
+                "min_voltage": number,
+                "capacity_mah": integer,
+                "max_discharge_a": number,
+                "max_charge_a": number,
+                "internal_res_mohm": nullable("number"),
+                "weight_g": number,
+                "diameter_mm": nullable("number"),
+                "length_mm": number,
+                "width_mm": nullable("number"),
+                "height_mm": nullable("number"),
+                "datasheet_url": nullable("string"),
+                "thermal_limit_c": nullable("number"),
+                "cycle_life": nullable("integer")
+            })
+        )),
+        "Bms": library_flags(object(
+            &["id", "manufacturer", "model", "series_count", "max_current_a", "length_mm",
+              "width_mm", "height_mm"],
+            json!({
+                "id": integer,
+                "manufacturer": string,
+                "model": string,
+                "series_count": integer,
+                "max_current_a": number,
+                "balance_current_ma": nullable("number"),
+                "length_mm": number,
+                "width_mm": number,
+                "height_mm": number,
+                "pinout_json": nullable("string")
+            })
+        )),
+        "Material": library_flags(object(
+            &["id", "name", "material_type", "resistance_mohm_per_m", "max_current_a"],
+            json!({
+                "id": integer,
+                "name": string,
+                "material_type": string,
+                "thickness_mm": nullable("number"),
+                "width_mm": nullable("number"),
+                "resistance_mohm_per_m": number,
+                "max_current_a": number
+            })
+        )),
+        "Shape": library_flags(object(
+            &["id", "name", "category", "file_path", "default_scale"],
+            json!({
+                "id": integer,
+                "name": string,
+                "category": string,
+                "file_path": string,
+                "default_scale": string
+            })
+        )),
+        "Vector3": { "type": "array", "items": number, "minItems": 3, "maxItems": 3 },
+        "ProjectFile": object(&["version", "metadata", "scene", "settings", "camera"], json!({
+            "version": string,
+            "metadata": object(&["name", "created", "modified"], json!({
+                "name": string,
+                "created": { "type": "string", "format": "date-time" },
+                "modified": { "type": "string", "format": "date-time" },
+                "author": nullable("string")
+            })),
+            "scene": object(&["cells", "connections", "components", "groups"], json!({
+                "cells": map(schema("CellInstance")),
+                "connections": map(schema("Connection")),
+                "components": map(schema("Component")),
+                "groups": map(schema("Group"))
+            })),
+            "settings": object(&["units", "grid_size", "snap_enabled", "hex_packing_enabled"], json!({
+                "units": { "type": "string", "enum": ["mm", "in"] },
+                "grid_size": number,
+                "snap_enabled": boolean,
+                "hex_packing_enabled": boolean
+            })),
+            "camera": object(&["position", "target", "zoom"], json!({
+                "position": schema("Vector3"),
+                "target": schema("Vector3"),
+                "zoom": number
+            }))
+        })),
+        "CellInstance": object(&["uuid", "cell_id", "position", "rotation"], json!({
+            "uuid": string,
+            "cell_id": integer,
+            "cell_key": { "type": "string", "description": "Stable library key of `cell_id`, e.g. `samsung/30q`" },
+            "position": schema("Vector3"),
+            "rotation": schema("Vector3"),
+            "custom_label": nullable("string"),
+            "group_id": nullable("string")
+        })),
+        "Connection": object(
+            &["uuid", "connection_type", "source_uuid", "source_terminal", "target_uuid",
+              "target_terminal"],
+            json!({
+                "uuid": string,
+                "connection_type": { "type": "string", "enum": ["series", "parallel", "busbar"] },
+                "source_uuid": string,
+                "source_terminal": { "type": "string", "enum": ["positive", "negative"] },
+                "target_uuid": string,
+                "target_terminal": { "type": "string", "enum": ["positive", "negative"] },
+                "material_id": nullable("integer"),
+                "material_key": string,
+                "path": { "type": "array", "items": schema("Vector3"), "nullable": true }
+            })
+        ),
+        "Component": object(&["uuid", "component_type", "position", "rotation", "scale"], json!({
+            "uuid": string,
+            "component_type": { "type": "string", "enum": ["bms", "shape", "custom"] },
+            "reference_id": nullable("integer"),
+            "reference_key": string,
+            "position": schema("Vector3"),
+            "rotation": schema("Vector3"),
+            "scale": schema("Vector3"),
+            "custom_mesh_path": nullable("string")
+        })),
+        "Group": object(&["uuid", "name", "member_uuids", "locked", "visible"], json!({
+            "uuid": string,
+            "name": string,
+            "member_uuids": array(string.clone()),
+            "color": nullable("string"),
+            "locked": boolean,
+            "visible": boolean
+        })),
+        "ValidatedProject": object(&["project", "report"], json!({
+            "project": schema("ProjectFile"),
+            "report": object(&["issues"], json!({
+                "issues": array(object(&["kind", "path", "message", "repaired"], json!({
+                    "kind": {
+                        "type": "string",
+                        "enum": ["key-mismatch", "dangling-connection", "dangling-group-member",
+                                 "dangling-group-id", "group-membership", "unknown-cell",
+                                 "unknown-material", "unknown-reference"]
+                    },
+                    "path": { "type": "string", "description": "JSON path of the offending field" },
+                    "message": string,
+                    "repaired": boolean
+                })))
+            }))
+        })),
+        "PackAnalysis": object(
+            &["cell_count", "series", "parallel", "configuration", "nominal_voltage",
+              "max_voltage", "min_voltage", "capacity_ah", "energy_wh", "max_discharge_a",
+              "max_charge_a", "cell_weight_g", "groups"],
+            json!({
+                "cell_count": integer,
+                "series": integer,
+                "parallel": { "type": "integer", "description": "Cells in the smallest parallel group" },
+                "configuration": { "type": "string", "example": "13S4P" },
+                "chemistry": { "type": "string", "nullable": true, "description": "Null when the pack mixes chemistries" },
+                "nominal_voltage": number,
+                "max_voltage": number,
+                "min_voltage": number,
+                "capacity_ah": number,
+                "energy_wh": number,
+                "max_discharge_a": number,
+                "max_charge_a": number,
+                "internal_resistance_mohm": nullable("number"),
+                "cell_weight_g": number,
+                "groups": array(object(&["cells", "capacity_ah", "nominal_voltage", "max_discharge_a"], json!({
+                    "cells": array(string.clone()),
+                    "capacity_ah": number,
+                    "nominal_voltage": number,
+                    "max_discharge_a": number
+                })))
+            })
+        ),
+        "DrcReport": object(&["passed", "errors", "warnings", "issues"], json!({
+            "passed": { "type": "boolean", "description": "No errors; warnings don't fail the check" },
+            "errors": integer,
+            "warnings": integer,
+            "issues": array(object(&["rule", "severity", "message", "entities"], json!({
+                "rule": {
+                    "type": "string",
+                    "enum": ["unknown-cell", "floating-cell", "short-circuit", "series-topology",
+                             "reversed-polarity", "disconnected-cells", "mixed-chemistry",
+                             "voltage-mismatch", "unbalanced-groups", "missing-material",
+                             "undersized-connection", "bms-series-mismatch", "bms-undersized"]
+                },
+                "severity": { "type": "string", "enum": ["error", "warning"] },
+                "message": string,
+                "entities": array(string.clone())
+            })))
+        })),
+        "Bom": object(&["project", "lines", "total_weight_g", "warnings"], json!({
+            "project": string,
+            "lines": array(object(&["category", "description", "quantity", "unit"], json!({
+                "category": { "type": "string", "enum": ["cell", "bms", "material", "shape", "custom"] },
+                "key": nullable("string"),
+                "description": string,
+                "quantity": number,
+                "unit": { "type": "string", "enum": ["pcs", "m"] },
+                "weight_g": nullable("number")
+            }))),
+            "total_weight_g": number,
+            "warnings": array(string.clone())
+        })),
+        "ExportSelection": {
+            "type": "object",
+            "required": ["mode"],
+            "description": "STL and OBJ accept every mode, glTF all but `holders-only`, 3MF and STEP only `all` and `selected`",
+            "properties": {
+                "mode": { "type": "string", "enum": ["all", "selected", "holders-only", "cells-only"] },
+                "uuids": { "type": "array", "items": string, "description": "Entities to export with `selected`" }
+            }
+        },
+        "ExportFormat": {
+            "oneOf": [
+                export_format("stl", export_options(json!({
+                    "merge_geometries": boolean,
+                    "apply_transforms": boolean,
+                    "scale": number
+                }))),
+                export_format("3mf", export_options(json!({
+                    "include_colors": boolean,
+                    "include_materials": boolean,
+                    "separate_objects": boolean,
+                    "build_plate_origin": boolean
+                }))),
+                export_format("gltf", export_options(json!({
+                    "binary": { "type": "boolean", "description": "GLB when true, .gltf with an embedded buffer otherwise" },
+                    "include_connections": boolean,
+                    "segments": integer
+                }))),
+                export_format("step", export_options(json!({
+                    "group_assemblies": { "type": "boolean", "description": "One sub-assembly per scene group" }
+                }))),
+                export_format("obj", export_options(json!({
+                    "merge_geometries": boolean,
+                    "apply_transforms": boolean,
+                    "scale": number,
+                    "include_materials": { "type": "boolean", "description": "Write a companion .mtl file" }
+                })))
+            ],
+            "discriminator": { "propertyName": "format" }
+        },
+        "ExportResult": object(&["path", "file_size", "companion_files", "warnings"], json!({
+            "path": string,
+            "file_size": integer,
+            "companion_files": array(string.clone()),
+            "warnings": array(string.clone())
+        }))
+    })
+}
+
+/// OpenAPI 3 description of the endpoints.
+fn openapi() -> Value {
+    let project_source = json!({
+        "type": "object",
+        "description": "Either an inline project or the path of a project file on the workstation",
+        "properties": {
+            "project": { "$ref": "#/components/schemas/ProjectFile" },
+            "path": { "type": "string" }
+        }
+    });
+    let json_body = |schema: Value| json!({ "required": true, "content": { "application/json": { "schema": schema } } });
+    let json_response = |description: &str, schema: Value| json!({ "description": description, "content": { "application/json": { "schema": schema } } });
+    let schema = |name: &str| json!({ "$ref": format!("#/components/schemas/{}", name) });
+    let array_of = |name: &str| json!({ "type": "array", "items": schema(name) });
+    let errors = json!({
+        "400": { "$ref": "#/components/responses/Error" },
+        "401": { "$ref": "#/components/responses/Error" },
+        "500": { "$ref": "#/components/responses/Error" }
+    });
+    let with_errors = |ok: Value| {
+        let mut responses = errors.clone();
+        responses["200"] = ok;
+        responses
+    };
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "CellForge API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Library, project, analysis and export operations of a running CellForge workstation. Send the token from the app's API settings as a bearer token."
+        },
+        "servers": [{ "url": PREFIX }],
+        "security": [{ "bearerAuth": [] }],
+        "paths": {
+            "/health": {
+                "get": {
+                    "summary": "Check that the server is up",
+                    "security": [],
+                    "responses": { "200": json_response("Server is running", json!({ "type": "object", "properties": { "status": { "type": "string" }, "version": { "type": "string" } } })) }
+                }
+            },
+            "/openapi.json": {
+                "get": {
+                    "summary": "This document",
+                    "security": [],
+                    "responses": { "200": json_response("OpenAPI document", json!({ "type": "object" })) }
+                }
+            },
+            "/library/cells": {
+                "get": {
+                    "summary": "List library cells",
+                    "parameters": [{ "name": "search", "in": "query", "required": false, "schema": { "type": "string" }, "description": "Full-text search over manufacturer and model" }],
+                    "responses": with_errors(json_response("Cells", array_of("Cell")))
+                }
+            },
+            "/library/cells/{id}": {
+                "get": {
+                    "summary": "Get a library cell",
+                    "parameters": [{ "name": "id", "in": "path", "required": true, "schema": { "type": "integer" } }],
+                    "responses": {
+                        "200": json_response("Cell", schema("Cell")),
+                        "404": { "$ref": "#/components/responses/Error" }
+                    }
+                }
+            },
+            "/library/bms": { "get": { "summary": "List library BMS boards", "responses": with_errors(json_response("BMS boards", array_of("Bms"))) } },
+            "/library/materials": { "get": { "summary": "List interconnect materials", "responses": with_errors(json_response("Materials", array_of("Material"))) } },
+            "/library/shapes": { "get": { "summary": "List library shapes", "responses": with_errors(json_response("Shapes", array_of("Shape"))) } },
+            "/projects/load": {
+                "post": {
+                    "summary": "Load a project file from the workstation",
+                    "requestBody": json_body(json!({ "type": "object", "required": ["path"], "properties": { "path": { "type": "string" } } })),
+                    "responses": with_errors(json_response("Project", schema("ProjectFile")))
+                }
+            },
+            "/projects/save": {
+                "post": {
+                    "summary": "Save a project file on the workstation",
+                    "requestBody": json_body(json!({
+                        "type": "object",
+                        "required": ["project", "path"],
+                        "properties": {
+                            "project": { "$ref": "#/components/schemas/ProjectFile" },
+                            "path": { "type": "string" },
+                            "format": { "type": "string", "enum": ["compressed", "plain"], "description": "Defaults to the existing file's format" }
+                        }
+                    })),
+                    "responses": { "204": { "description": "Saved" }, "400": { "$ref": "#/components/responses/Error" }, "401": { "$ref": "#/components/responses/Error" }, "500": { "$ref": "#/components/responses/Error" } }
+                }
+            },
+            "/projects/validate": {
+                "post": {
+                    "summary": "Check a project's references against the library",
+                    "requestBody": json_body(json!({ "allOf": [project_source, { "type": "object", "properties": { "repair": { "type": "boolean", "default": false } } }] })),
+                    "responses": with_errors(json_response("Project, repaired if requested, and the issues found", schema("ValidatedProject")))
+                }
+            },
+            "/analysis/pack": {
+                "post": {
+                    "summary": "Pack configuration and electrical ratings",
+                    "requestBody": json_body(project_source.clone()),
+                    "responses": with_errors(json_response("Pack specification", schema("PackAnalysis")))
+                }
+            },
+            "/analysis/drc": {
+                "post": {
+                    "summary": "Run the design rule check",
+                    "requestBody": json_body(project_source.clone()),
+                    "responses": with_errors(json_response("Design rule report", schema("DrcReport")))
+                }
+            },
+            "/analysis/bom": {
+                "post": {
+                    "summary": "Bill of materials",
+                    "parameters": [{ "name": "format", "in": "query", "required": false, "schema": { "type": "string", "enum": ["json", "csv"] } }],
+                    "requestBody": json_body(project_source.clone()),
+                    "responses": with_errors(json!({
+                        "description": "Bill of materials",
+                        "content": {
+                            "application/json": { "schema": schema("Bom") },
+                            "text/csv": { "schema": { "type": "string" } }
+                        }
+                    }))
+                }
+            },
+            "/export": {
+                "post": {
+                    "summary": "Export geometry",
+                    "description": "Writes the export to `output` on the workstation, or returns the file when `output` is omitted.",
+                    "requestBody": json_body(json!({
+                        "allOf": [project_source, {
+                            "type": "object",
+                            "required": ["format"],
+                            "properties": {
+                                "format": schema("ExportFormat"),
+                                "output": { "type": "string" }
+                            }
+                        }]
+                    })),
+                    "responses": with_errors(json!({
+                        "description": "Export result, or the exported file",
+                        "content": {
+                            "application/json": { "schema": schema("ExportResult") },
+                            "application/octet-stream": { "schema": { "type": "string", "format": "binary" } }
+                        }
+                    }))
+                }
+            }
+        },
+        "components": {
+            "securitySchemes": { "bearerAuth": { "type": "http", "scheme": "bearer" } },
+            "schemas": schemas(),
+            "responses": {
+                "Error": json_response("Error", json!({ "type": "object", "properties": { "error": { "type": "string" }, "code": { "type": "string" } } }))
+            }
+        }
+    })
+}
@@ -0,0 +1,229 @@
+use crate::error::{ErrorCode, ExportError};
+use crate::export::{ExportProgress, ExportResult};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+use uuid::Uuid;
+
+/// Event carrying an `ExportJob` whenever its progress or status changes.
+pub const PROGRESS_EVENT: &str = "export-progress";
+
+// Finished jobs kept for `get_export_jobs`; running jobs are never dropped
+const MAX_RECENT_JOBS: usize = 20;
+const EMIT_INTERVAL: Duration = Duration::from_millis(100);
+// Estimates from the first few percent are mostly noise
+const MIN_ETA_FRACTION: f64 = 0.05;
+
+const CANCELLED: &str = "Export cancelled";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(
+            self,
+            JobStatus::Completed | JobStatus::Failed | JobStatus::Cancelled
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportJob {
+    pub id: String,
+    pub format: String,
+    pub path: String,
+    pub status: JobStatus,
+    pub stage: String,
+    pub percent: f64,
+    pub eta_seconds: Option<f64>,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    pub result: Option<ExportResult>,
+    pub error: Option<String>,
+    pub error_code: Option<ErrorCode>,
+}
+
+struct JobEntry {
+    job: ExportJob,
+    cancel: Arc<AtomicBool>,
+}
+
+/// Tracks background export jobs, newest first.
+pub struct JobManager {
+    jobs: Mutex<VecDeque<JobEntry>>,
+}
+
+impl JobManager {
+    pub fn new() -> Self {
+        JobManager {
+            jobs: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Registers a queued job and returns it with its cancellation flag.
+    pub fn create(&self, format: &str, path: &str) -> (ExportJob, Arc<AtomicBool>) {
+        let job = ExportJob {
+            id: Uuid::new_v4().to_string(),
+            format: format.to_string(),
+            path: path.to_string(),
+            status: JobStatus::Queued,
+            stage: String::new(),
+            percent: 0.0,
+            eta_seconds: None,
+            started_at: chrono::Utc::now().to_rfc3339(),
+            finished_at: None,
+            result: None,
+            error: None,
+            error_code: None,
+        };
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.push_front(JobEntry {
+            job: job.clone(),
+            cancel: cancel.clone(),
+        });
+
+        let mut finished = 0;
+        jobs.retain(|entry| {
+            if !entry.job.status.is_finished() {
+                return true;
+            }
+            finished += 1;
+            finished <= MAX_RECENT_JOBS
+        });
+
+        (job, cancel)
+    }
+
+    pub fn list(&self) -> Vec<ExportJob> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.iter().map(|entry| entry.job.clone()).collect()
+    }
+
+    /// Asks a running job to stop. The job reports `cancelled` once the
+    /// exporter reaches its next progress checkpoint.
+    pub fn cancel(&self, id: &str) -> Result<(), ExportError> {
+        let jobs = self.jobs.lock().unwrap();
+        let entry = jobs
+            .iter()
+            .find(|entry| entry.job.id == id)
+            .ok_or_else(|| ExportError::UnknownJob { id: id.to_string() })?;
+
+        if entry.job.status.is_finished() {
+            return Err(ExportError::JobFinished { id: id.to_string() });
+        }
+
+        entry.cancel.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn update(&self, id: &str, apply: impl FnOnce(&mut ExportJob)) -> Option<ExportJob> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let entry = jobs.iter_mut().find(|entry| entry.job.id == id)?;
+        apply(&mut entry.job);
+        Some(entry.job.clone())
+    }
+
+    /// Records the outcome of a job. An error after cancellation was
+    /// requested is reported as `cancelled` rather than `failed`.
+    pub fn finish(&self, id: &str, outcome: Result<ExportResult, ExportError>) -> Option<ExportJob> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let entry = jobs.iter_mut().find(|entry| entry.job.id == id)?;
+        let job = &mut entry.job;
+
+        job.eta_seconds = None;
+        job.finished_at = Some(chrono::Utc::now().to_rfc3339());
+        match outcome {
+            Ok(result) => {
+                job.status = JobStatus::Completed;
+                job.percent = 100.0;
+                job.result = Some(result);
+            }
+            Err(_) if entry.cancel.load(Ordering::Relaxed) => {
+                job.status = JobStatus::Cancelled;
+                job.error = Some(CANCELLED.to_string());
+            }
+            Err(e) => {
+                job.status = JobStatus::Failed;
+                job.error_code = Some(e.code());
+                job.error = Some(e.to_string());
+            }
+        }
+
+        Some(job.clone())
+    }
+}
+
+/// Progress sink for one job: updates the manager, emits throttled
+/// `export-progress` events and stops the exporter once cancelled.
+pub struct JobProgress<'a> {
+    app: &'a AppHandle,
+    jobs: &'a JobManager,
+    id: &'a str,
+    cancel: Arc<AtomicBool>,
+    started: Instant,
+    last_emit: RefCell<Option<(Instant, String)>>,
+}
+
+impl<'a> JobProgress<'a> {
+    pub fn new(app: &'a AppHandle, jobs: &'a JobManager, id: &'a str, cancel: Arc<AtomicBool>) -> Self {
+        JobProgress {
+            app,
+            jobs,
+            id,
+            cancel,
+            started: Instant::now(),
+            last_emit: RefCell::new(None),
+        }
+    }
+}
+
+impl ExportProgress for JobProgress<'_> {
+    fn report(&self, stage: &str, fraction: f64) -> Result<(), String> {
+        if self.cancel.load(Ordering::Relaxed) {
+            return Err(CANCELLED.to_string());
+        }
+
+        // Always emit stage changes; within a stage, at most every EMIT_INTERVAL
+        let mut last_emit = self.last_emit.borrow_mut();
+        if let Some((at, last_stage)) = last_emit.as_ref() {
+            if last_stage == stage && at.elapsed() < EMIT_INTERVAL {
+                return Ok(());
+            }
+        }
+        *last_emit = Some((Instant::now(), stage.to_string()));
+
+        let fraction = fraction.clamp(0.0, 1.0);
+        let eta_seconds = if fraction >= MIN_ETA_FRACTION {
+            let elapsed = self.started.elapsed().as_secs_f64();
+            Some(elapsed * (1.0 - fraction) / fraction)
+        } else {
+            None
+        };
+
+        let job = self.jobs.update(self.id, |job| {
+            job.status = JobStatus::Running;
+            job.stage = stage.to_string();
+            job.percent = fraction * 100.0;
+            job.eta_seconds = eta_seconds;
+        });
+        if let Some(job) = job {
+            let _ = self.app.emit(PROGRESS_EVENT, job);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,285 @@
+use crate::error::FilesystemError;
+use crate::filesystem::{Filesystem, ProjectFile};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often the background thread checks for pending changes.
+pub const TICK: Duration = Duration::from_secs(1);
+
+// Quiet period after the last edit before a snapshot is written
+const DEBOUNCE: Duration = Duration::from_secs(5);
+// Upper bound on how long continuous editing can postpone a snapshot
+const MAX_DELAY: Duration = Duration::from_secs(60);
+const SNAPSHOT_LIMIT: usize = 5;
+
+const MANIFEST_FILE: &str = "autosave.json";
+const LOCK_FILE: &str = "session.lock";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutosaveSnapshot {
+    pub file_name: String,
+    pub created_at: String,
+    pub project_name: String,
+    pub project_path: Option<String>,
+}
+
+/// Snapshot offered to the user after an unclean shutdown.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveryInfo {
+    pub snapshot: AutosaveSnapshot,
+    pub snapshot_path: String,
+    pub last_saved_at: Option<String>,
+}
+
+/// Persistent autosave state, newest snapshot first.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Manifest {
+    /// Last explicit save per project path, for the paths snapshots have
+    saves: BTreeMap<String, String>,
+    /// Last time an untitled project was saved under a name
+    untitled_saved_at: Option<String>,
+    snapshots: Vec<AutosaveSnapshot>,
+}
+
+impl Manifest {
+    /// When the project a snapshot was taken of was last saved.
+    fn saved_at(&self, snapshot: &AutosaveSnapshot) -> Option<&String> {
+        match &snapshot.project_path {
+            Some(path) => self.saves.get(path),
+            None => self.untitled_saved_at.as_ref(),
+        }
+    }
+}
+
+struct Pending {
+    project: ProjectFile,
+    path: Option<String>,
+    dirty_since: Instant,
+    changed_at: Instant,
+}
+
+/// Debounced background saves of the open project into rotating snapshots.
+///
+/// A session lock file is held while the app runs; finding it on startup
+/// means the previous session did not shut down cleanly.
+pub struct Autosave {
+    dir: PathBuf,
+    manifest: Mutex<Manifest>,
+    pending: Mutex<Option<Pending>>,
+    recovery: Mutex<Option<RecoveryInfo>>,
+    // Whether the open project has no path yet, so a save names it
+    untitled: Mutex<bool>,
+}
+
+impl Autosave {
+    /// Opens the autosave directory, checks for an unclean shutdown and takes
+    /// the session lock.
+    pub fn start(dir: PathBuf) -> Result<Self, FilesystemError> {
+        fs::create_dir_all(&dir)
+            .map_err(FilesystemError::io("Failed to create autosave directory", &dir))?;
+
+        let manifest = read_manifest(&dir);
+        let lock_path = dir.join(LOCK_FILE);
+        let recovery = if lock_path.exists() {
+            recovery_candidate(&dir, &manifest)
+        } else {
+            None
+        };
+
+        let lock = format!(
+            "pid={}\nstarted={}\n",
+            std::process::id(),
+            chrono::Utc::now().to_rfc3339()
+        );
+        fs::write(&lock_path, lock)
+            .map_err(FilesystemError::io("Failed to write session lock", &lock_path))?;
+
+        Ok(Autosave {
+            dir,
+            manifest: Mutex::new(manifest),
+            pending: Mutex::new(None),
+            recovery: Mutex::new(recovery),
+            untitled: Mutex::new(false),
+        })
+    }
+
+    /// Records the latest state of the open project; it is written once
+    /// edits settle down.
+    pub fn update(&self, project: ProjectFile, path: Option<String>) {
+        let now = Instant::now();
+        *self.untitled.lock().unwrap() = path.is_none();
+        let mut pending = self.pending.lock().unwrap();
+        let dirty_since = pending.as_ref().map(|p| p.dirty_since).unwrap_or(now);
+        *pending = Some(Pending {
+            project,
+            path,
+            dirty_since,
+            changed_at: now,
+        });
+    }
+
+    /// Notes an explicit save of the open project to `path`; its older
+    /// snapshots are no longer offered for recovery. Snapshots of other
+    /// projects are.
+    pub fn mark_saved(&self, filesystem: &Filesystem, path: &Path) -> Result<(), FilesystemError> {
+        *self.pending.lock().unwrap() = None;
+        let untitled = std::mem::take(&mut *self.untitled.lock().unwrap());
+        self.save_recorded(filesystem, path, untitled)
+    }
+
+    /// Notes a save to `path` made outside the app, e.g. through the API.
+    /// The open project's unsaved changes are kept.
+    pub fn record_save(&self, filesystem: &Filesystem, path: &Path) -> Result<(), FilesystemError> {
+        self.save_recorded(filesystem, path, false)
+    }
+
+    fn save_recorded(
+        &self,
+        filesystem: &Filesystem,
+        path: &Path,
+        untitled: bool,
+    ) -> Result<(), FilesystemError> {
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut manifest = self.manifest.lock().unwrap();
+        if untitled {
+            manifest.untitled_saved_at = Some(now.clone());
+        }
+        manifest.saves.insert(path.to_string_lossy().into_owned(), now);
+        write_manifest(filesystem, &self.dir, &mut manifest)
+    }
+
+    /// Writes a snapshot if the project is dirty and edits have settled, or
+    /// have been going on for longer than `MAX_DELAY`. `prepare` runs on the
+    /// project just before it is written, e.g. to stamp library keys so a
+    /// recovered snapshot resolves to the cells it was saved with.
+    pub fn tick(
+        &self,
+        filesystem: &Filesystem,
+        prepare: impl FnOnce(&mut ProjectFile) -> Result<(), FilesystemError>,
+    ) -> Result<Option<AutosaveSnapshot>, FilesystemError> {
+        let pending = {
+            let mut pending = self.pending.lock().unwrap();
+            let due = pending.as_ref().is_some_and(|p| {
+                p.changed_at.elapsed() >= DEBOUNCE || p.dirty_since.elapsed() >= MAX_DELAY
+            });
+            if !due {
+                return Ok(None);
+            }
+            pending.take()
+        };
+        let Some(mut pending) = pending else {
+            return Ok(None);
+        };
+        if let Err(e) = prepare(&mut pending.project) {
+            self.pending.lock().unwrap().get_or_insert(pending);
+            return Err(e);
+        }
+
+        let now = chrono::Utc::now();
+        let snapshot = AutosaveSnapshot {
+            file_name: format!("{}.cellforge", now.format("%Y%m%dT%H%M%S%.3fZ")),
+            created_at: now.to_rfc3339(),
+            project_name: pending.project.metadata.name.clone(),
+            project_path: pending.path.clone(),
+        };
+
+        let data = filesystem.encode_project(&pending.project)?;
+        if let Err(e) = filesystem.write_atomic(&data, &self.dir.join(&snapshot.file_name)) {
+            // Keep the changes so the next tick retries, unless newer ones arrived
+            self.pending.lock().unwrap().get_or_insert(pending);
+            return Err(e);
+        }
+
+        let mut manifest = self.manifest.lock().unwrap();
+        manifest.snapshots.insert(0, snapshot.clone());
+        if manifest.snapshots.len() > SNAPSHOT_LIMIT {
+            for old in manifest.snapshots.split_off(SNAPSHOT_LIMIT) {
+                let _ = fs::remove_file(self.dir.join(&old.file_name));
+            }
+        }
+        write_manifest(filesystem, &self.dir, &mut manifest)?;
+
+        Ok(Some(snapshot))
+    }
+
+    pub fn snapshots(&self) -> Vec<AutosaveSnapshot> {
+        self.manifest.lock().unwrap().snapshots.clone()
+    }
+
+    pub fn recovery_info(&self) -> Option<RecoveryInfo> {
+        self.recovery.lock().unwrap().clone()
+    }
+
+    /// Loads the snapshot offered for recovery; the offer is withdrawn either way.
+    pub fn recover(&self, filesystem: &Filesystem) -> Result<ProjectFile, FilesystemError> {
+        let recovery = self
+            .recovery
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| FilesystemError::Unavailable("No autosave to recover".to_string()))?;
+        filesystem.load_project(Path::new(&recovery.snapshot_path))
+    }
+
+    pub fn dismiss_recovery(&self) {
+        *self.recovery.lock().unwrap() = None;
+    }
+
+    /// Releases the session lock on a clean exit.
+    pub fn shutdown(&self) {
+        let _ = fs::remove_file(self.dir.join(LOCK_FILE));
+    }
+}
+
+fn read_manifest(dir: &Path) -> Manifest {
+    fs::read_to_string(dir.join(MANIFEST_FILE))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Replaces the manifest atomically, so a crash mid-write can't lose the
+/// snapshot list recovery depends on.
+fn write_manifest(
+    filesystem: &Filesystem,
+    dir: &Path,
+    manifest: &mut Manifest,
+) -> Result<(), FilesystemError> {
+    // Only saves older than a snapshot still hide anything
+    let Manifest { saves, snapshots, .. } = manifest;
+    saves.retain(|path, _| snapshots.iter().any(|s| s.project_path.as_ref() == Some(path)));
+
+    let json = serde_json::to_vec_pretty(manifest).map_err(|e| {
+        FilesystemError::Serialization(format!("Failed to serialize autosave manifest: {}", e))
+    })?;
+    filesystem.write_atomic(&json, &dir.join(MANIFEST_FILE))
+}
+
+/// Newest snapshot on disk taken after its project was last saved.
+fn recovery_candidate(dir: &Path, manifest: &Manifest) -> Option<RecoveryInfo> {
+    manifest
+        .snapshots
+        .iter()
+        .filter(|snapshot| dir.join(&snapshot.file_name).exists())
+        .find(|snapshot| {
+            let created = chrono::DateTime::parse_from_rfc3339(&snapshot.created_at).ok();
+            let last_saved = manifest
+                .saved_at(snapshot)
+                .and_then(|at| chrono::DateTime::parse_from_rfc3339(at).ok());
+            match (created, last_saved) {
+                (Some(created), Some(saved)) => created > saved,
+                (Some(_), None) => true,
+                (None, _) => false,
+            }
+        })
+        .map(|snapshot| RecoveryInfo {
+            snapshot: snapshot.clone(),
+            snapshot_path: dir.join(&snapshot.file_name).to_string_lossy().into_owned(),
+            last_saved_at: manifest.saved_at(snapshot).cloned(),
+        })
+}
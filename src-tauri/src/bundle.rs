@@ -0,0 +1,492 @@
+use crate::database::{Bms, Cell, Database, Material, Shape};
+use crate::error::{DatabaseError, FilesystemError};
+use crate::filesystem::{Filesystem, ProjectFile};
+use crate::library_keys::LibraryKeys;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+const FORMAT: &str = "cellforge-bundle";
+const FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+const PROJECT_ENTRY: &str = "project.json";
+const LIBRARY_ENTRY: &str = "library.json";
+
+/// Library rows referenced by a bundled project, with their ids from the
+/// exporting install.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BundleLibrary {
+    pub cells: Vec<Cell>,
+    pub bms: Vec<Bms>,
+    pub materials: Vec<Material>,
+    pub shapes: Vec<Shape>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    format: String,
+    version: u32,
+    created: String,
+    meshes: Vec<MeshEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MeshEntry {
+    entry: String,
+    original_path: String,
+}
+
+/// Mesh file as referenced by the project, e.g. a `custom_mesh_path`.
+pub struct BundledMesh {
+    pub original_path: String,
+    pub data: Vec<u8>,
+}
+
+pub struct Bundle {
+    pub project: ProjectFile,
+    pub library: BundleLibrary,
+    pub meshes: Vec<BundledMesh>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleExportResult {
+    pub path: String,
+    pub file_size: u64,
+    pub cells: usize,
+    pub bms: usize,
+    pub materials: usize,
+    pub shapes: usize,
+    pub meshes: usize,
+    pub warnings: Vec<String>,
+}
+
+/// How a bundled library row was mapped onto the local library.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryMatch {
+    pub kind: String, // "cell" | "bms" | "material" | "shape"
+    pub name: String,
+    pub bundled_id: i64,
+    pub local_id: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BundleImportReport {
+    /// Rows found locally by library key
+    pub matched: Vec<LibraryMatch>,
+    /// Rows added to the local library
+    pub added: Vec<LibraryMatch>,
+    /// Extracted mesh files
+    pub meshes: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleImport {
+    pub project: ProjectFile,
+    pub report: BundleImportReport,
+}
+
+/// Local ids of imported library rows, keyed by their id in the source.
+#[derive(Debug, Default)]
+pub struct LibraryIdMap {
+    pub cells: HashMap<i64, i64>,
+    pub bms: HashMap<i64, i64>,
+    pub materials: HashMap<i64, i64>,
+    pub shapes: HashMap<i64, i64>,
+}
+
+/// Gathers the library rows and mesh files a project references.
+///
+/// Relative shape paths are looked up under `resource_dir`. Anything that
+/// cannot be found is left out with a warning.
+pub fn collect(
+    project: ProjectFile,
+    db: &Database,
+    resource_dir: Option<&Path>,
+) -> Result<(Bundle, Vec<String>), DatabaseError> {
+    let scene = &project.scene;
+    let mut warnings = Vec::new();
+    let mut library = BundleLibrary::default();
+
+    let cell_ids: BTreeSet<i64> = scene.cells.values().map(|c| c.cell_id).collect();
+    let material_ids: BTreeSet<i64> = scene.connections.values().filter_map(|c| c.material_id).collect();
+    let reference_ids = |kind: &str| -> BTreeSet<i64> {
+        scene
+            .components
+            .values()
+            .filter(|c| c.component_type == kind)
+            .filter_map(|c| c.reference_id)
+            .collect()
+    };
+
+    let db_error = |e| DatabaseError::context("Failed to read library")(e);
+    for id in cell_ids {
+        match db.get_cell_by_id(id).map_err(db_error)? {
+            Some(cell) => library.cells.push(cell),
+            None => warnings.push(format!("Cell {} is not in the library and was not bundled", id)),
+        }
+    }
+    for id in material_ids {
+        match db.get_material_by_id(id).map_err(db_error)? {
+            Some(material) => library.materials.push(material),
+            None => warnings.push(format!("Material {} is not in the library and was not bundled", id)),
+        }
+    }
+    for id in reference_ids("bms") {
+        match db.get_bms_by_id(id).map_err(db_error)? {
+            Some(bms) => library.bms.push(bms),
+            None => warnings.push(format!("BMS {} is not in the library and was not bundled", id)),
+        }
+    }
+    for id in reference_ids("shape") {
+        match db.get_shape_by_id(id).map_err(db_error)? {
+            Some(shape) => library.shapes.push(shape),
+            None => warnings.push(format!("Shape {} is not in the library and was not bundled", id)),
+        }
+    }
+
+    let mut mesh_paths: BTreeSet<String> = scene
+        .components
+        .values()
+        .filter_map(|c| c.custom_mesh_path.clone())
+        .collect();
+    mesh_paths.extend(library.shapes.iter().map(|s| s.file_path.clone()));
+
+    let mut meshes = Vec::new();
+    for original_path in mesh_paths {
+        let mut path = PathBuf::from(&original_path);
+        if path.is_relative() && !path.exists() {
+            if let Some(resource_dir) = resource_dir {
+                path = resource_dir.join(&path);
+            }
+        }
+        match fs::read(&path) {
+            Ok(data) => meshes.push(BundledMesh { original_path, data }),
+            Err(e) => warnings.push(format!("Mesh {} was not bundled: {}", original_path, e)),
+        }
+    }
+
+    Ok((
+        Bundle {
+            project,
+            library,
+            meshes,
+        },
+        warnings,
+    ))
+}
+
+/// Serializes a bundle into a zip archive.
+pub fn write(bundle: &Bundle) -> Result<Vec<u8>, FilesystemError> {
+    // The archive is built in memory, so these only fail on a bug
+    let write_error = |e: &dyn std::fmt::Display| {
+        FilesystemError::Serialization(format!("Failed to write bundle: {}", e))
+    };
+    let zip_error = |e: zip::result::ZipError| write_error(&e);
+    let io_error = |e: std::io::Error| write_error(&e);
+    let json_error = |e: serde_json::Error| {
+        FilesystemError::Serialization(format!("Failed to serialize bundle: {}", e))
+    };
+
+    let mesh_entries: Vec<MeshEntry> = bundle
+        .meshes
+        .iter()
+        .enumerate()
+        .map(|(index, mesh)| {
+            let file_name = Path::new(&mesh.original_path)
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or("mesh");
+            MeshEntry {
+                entry: format!("meshes/{}-{}", index, file_name),
+                original_path: mesh.original_path.clone(),
+            }
+        })
+        .collect();
+
+    let manifest = Manifest {
+        format: FORMAT.to_string(),
+        version: FORMAT_VERSION,
+        created: chrono::Utc::now().to_rfc3339(),
+        meshes: mesh_entries.clone(),
+    };
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let documents = [
+        (MANIFEST_ENTRY, serde_json::to_vec_pretty(&manifest).map_err(json_error)?),
+        (PROJECT_ENTRY, serde_json::to_vec_pretty(&bundle.project).map_err(json_error)?),
+        (LIBRARY_ENTRY, serde_json::to_vec_pretty(&bundle.library).map_err(json_error)?),
+    ];
+    for (name, contents) in &documents {
+        zip.start_file(*name, options).map_err(zip_error)?;
+        zip.write_all(contents).map_err(io_error)?;
+    }
+    for (entry, mesh) in mesh_entries.iter().zip(&bundle.meshes) {
+        zip.start_file(entry.entry.as_str(), options).map_err(zip_error)?;
+        zip.write_all(&mesh.data).map_err(io_error)?;
+    }
+
+    Ok(zip.finish().map_err(zip_error)?.into_inner())
+}
+
+/// Reads and parses a bundle written by `write`.
+pub fn open(path: &Path) -> Result<Bundle, FilesystemError> {
+    let data = fs::read(path).map_err(FilesystemError::io("Failed to read bundle", path))?;
+    let corrupt = |reason: String| FilesystemError::Corrupt {
+        path: path.to_path_buf(),
+        reason,
+    };
+
+    let mut archive =
+        ZipArchive::new(Cursor::new(data)).map_err(|e| corrupt(format!("not a zip file: {}", e)))?;
+
+    let mut read_entry = |name: &str| -> Result<Vec<u8>, FilesystemError> {
+        let mut file = archive
+            .by_name(name)
+            .map_err(|e| corrupt(format!("bundle entry {} is missing: {}", name, e)))?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)
+            .map_err(|e| corrupt(format!("failed to read bundle entry {}: {}", name, e)))?;
+        Ok(contents)
+    };
+
+    let manifest: Manifest = serde_json::from_slice(&read_entry(MANIFEST_ENTRY)?)
+        .map_err(|e| corrupt(format!("invalid bundle manifest: {}", e)))?;
+    if manifest.format != FORMAT {
+        return Err(corrupt("not a CellForge bundle".to_string()));
+    }
+    if manifest.version > FORMAT_VERSION {
+        return Err(FilesystemError::VersionTooNew {
+            path: path.to_path_buf(),
+            version: manifest.version.to_string(),
+            supported: FORMAT_VERSION.to_string(),
+        });
+    }
+
+    let project: ProjectFile = serde_json::from_slice(&read_entry(PROJECT_ENTRY)?)
+        .map_err(|e| corrupt(format!("invalid bundled project: {}", e)))?;
+    let library: BundleLibrary = serde_json::from_slice(&read_entry(LIBRARY_ENTRY)?)
+        .map_err(|e| corrupt(format!("invalid bundled library: {}", e)))?;
+
+    let mut meshes = Vec::new();
+    for entry in manifest.meshes {
+        meshes.push(BundledMesh {
+            data: read_entry(&entry.entry)?,
+            original_path: entry.original_path,
+        });
+    }
+
+    Ok(Bundle {
+        project,
+        library,
+        meshes,
+    })
+}
+
+/// Maps a bundle onto the local library and extracts its meshes.
+///
+/// Rows are matched by their library key; unmatched rows are added. The project's ids and mesh
+/// paths are rewritten to the local ones.
+pub fn import(
+    bundle: Bundle,
+    db: &Database,
+    filesystem: &Filesystem,
+    mesh_dir: &Path,
+) -> Result<BundleImport, FilesystemError> {
+    let Bundle {
+        mut project,
+        library,
+        meshes,
+    } = bundle;
+    let mut report = BundleImportReport::default();
+
+    // Meshes first, so added shapes can point at their extracted file
+    let mut mesh_paths: HashMap<String, String> = HashMap::new();
+    if !meshes.is_empty() {
+        let target_dir = mesh_dir.join(Uuid::new_v4().to_string());
+        fs::create_dir_all(&target_dir)
+            .map_err(FilesystemError::io("Failed to create mesh directory", &target_dir))?;
+
+        for (index, mesh) in meshes.iter().enumerate() {
+            let file_name = Path::new(&mesh.original_path)
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or("mesh");
+            let path = target_dir.join(format!("{}-{}", index, file_name));
+            filesystem.write_atomic(&mesh.data, &path)?;
+
+            let path = path.to_string_lossy().into_owned();
+            report.meshes.push(path.clone());
+            mesh_paths.insert(mesh.original_path.clone(), path);
+        }
+    }
+
+    let ids = import_library(&library, db, &mesh_paths, &mut report)?;
+
+    // Rewrite the project's references
+    let scene = &mut project.scene;
+    let mut unknown_cells = BTreeSet::new();
+    for cell in scene.cells.values_mut() {
+        match ids.cells.get(&cell.cell_id) {
+            Some(id) => cell.cell_id = *id,
+            None => {
+                unknown_cells.insert(cell.cell_id);
+            }
+        }
+    }
+    for id in unknown_cells {
+        report
+            .warnings
+            .push(format!("Cell {} was not in the bundle; its id was kept as is", id));
+    }
+    for connection in scene.connections.values_mut() {
+        if let Some(material_id) = connection.material_id {
+            connection.material_id = ids.materials.get(&material_id).copied();
+            if connection.material_id.is_none() {
+                report.warnings.push(format!(
+                    "Material {} was not in the bundle and was cleared",
+                    material_id
+                ));
+            }
+        }
+    }
+    for component in scene.components.values_mut() {
+        let ids = match component.component_type.as_str() {
+            "bms" => Some(&ids.bms),
+            "shape" => Some(&ids.shapes),
+            _ => None,
+        };
+        if let (Some(ids), Some(reference_id)) = (ids, component.reference_id) {
+            component.reference_id = ids.get(&reference_id).copied();
+            if component.reference_id.is_none() {
+                report.warnings.push(format!(
+                    "{} {} was not in the bundle and was cleared",
+                    component.component_type, reference_id
+                ));
+            }
+        }
+        if let Some(path) = &component.custom_mesh_path {
+            match mesh_paths.get(path) {
+                Some(extracted) => component.custom_mesh_path = Some(extracted.clone()),
+                None => report
+                    .warnings
+                    .push(format!("Mesh {} was not in the bundle", path)),
+            }
+        }
+    }
+
+    LibraryKeys::load(db)
+        .map_err(DatabaseError::context("Failed to load library keys"))?
+        .stamp(&mut project);
+    Ok(BundleImport { project, report })
+}
+
+/// Adds the rows of `library` that aren't in the local library yet,
+/// matching by library key, and records each row in `report`. Added rows
+/// are marked `user_added`.
+///
+/// Added shapes whose `file_path` is in `mesh_paths` point at the local copy.
+pub fn import_library(
+    library: &BundleLibrary,
+    db: &Database,
+    mesh_paths: &HashMap<String, String>,
+    report: &mut BundleImportReport,
+) -> Result<LibraryIdMap, DatabaseError> {
+    let db_error = |e| DatabaseError::context("Failed to update library")(e);
+    let mut ids = LibraryIdMap::default();
+
+    let mut record = |kind: &str, name: String, bundled_id: i64, local: Option<i64>, added: i64| {
+        let entry = LibraryMatch {
+            kind: kind.to_string(),
+            name,
+            bundled_id,
+            local_id: local.unwrap_or(added),
+        };
+        let local_id = entry.local_id;
+        if local.is_some() {
+            report.matched.push(entry);
+        } else {
+            report.added.push(entry);
+        }
+        local_id
+    };
+
+    // All or nothing, and readers never see a half-imported library
+    let transaction = db.transaction().map_err(db_error)?;
+    let mut keys = LibraryKeys::load(db).map_err(db_error)?;
+    for cell in &library.cells {
+        let local = keys.cells.id(&cell.library_key());
+        let added = match local {
+            Some(_) => 0,
+            None => db
+                .insert_cell(&Cell {
+                    user_added: true,
+                    ..cell.clone()
+                })
+                .map_err(db_error)?,
+        };
+        keys.cells.insert(cell.library_key(), local.unwrap_or(added));
+        let name = format!("{} {}", cell.manufacturer, cell.model);
+        ids.cells.insert(cell.id, record("cell", name, cell.id, local, added));
+    }
+
+    for bms in &library.bms {
+        let local = keys.bms.id(&bms.library_key());
+        let added = match local {
+            Some(_) => 0,
+            None => db
+                .insert_bms(&Bms {
+                    user_added: true,
+                    ..bms.clone()
+                })
+                .map_err(db_error)?,
+        };
+        keys.bms.insert(bms.library_key(), local.unwrap_or(added));
+        let name = format!("{} {}", bms.manufacturer, bms.model);
+        ids.bms.insert(bms.id, record("bms", name, bms.id, local, added));
+    }
+
+    for material in &library.materials {
+        let local = keys.materials.id(&material.library_key());
+        let added = match local {
+            Some(_) => 0,
+            None => db
+                .insert_material(&Material {
+                    user_added: true,
+                    ..material.clone()
+                })
+                .map_err(db_error)?,
+        };
+        keys.materials.insert(material.library_key(), local.unwrap_or(added));
+        ids.materials.insert(material.id, record("material", material.name.clone(), material.id, local, added));
+    }
+
+    for shape in &library.shapes {
+        let local = keys.shapes.id(&shape.library_key());
+        let added = match local {
+            Some(_) => 0,
+            None => {
+                let mut shape = shape.clone();
+                shape.user_added = true;
+                if let Some(path) = mesh_paths.get(&shape.file_path) {
+                    shape.file_path = path.clone();
+                }
+                db.insert_shape(&shape).map_err(db_error)?
+            }
+        };
+        keys.shapes.insert(shape.library_key(), local.unwrap_or(added));
+        ids.shapes.insert(shape.id, record("shape", shape.name.clone(), shape.id, local, added));
+    }
+
+    transaction.commit().map_err(db_error)?;
+    Ok(ids)
+}
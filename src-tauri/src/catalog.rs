@@ -0,0 +1,434 @@
+use crate::bundle::BundleLibrary;
+use crate::cell_import::FieldChange;
+use crate::database::Database;
+use crate::error::{DatabaseError, FilesystemError};
+use crate::library_file::{self, LibraryExportOptions, LibraryTable};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+/// Far above any real library; stops a wrong URL from filling memory
+const MAX_CATALOG_BYTES: u64 = 64 * 1024 * 1024;
+
+const META_SOURCE: &str = "catalog_source";
+const META_VERSION: &str = "catalog_version";
+const META_SYNCED_AT: &str = "catalog_synced_at";
+
+/// A shared library catalog: a JSON library file (see `library_file`) with
+/// a `catalog_version`, read from a path such as a network share or
+/// downloaded from an http(s) URL.
+pub struct Catalog {
+    pub source: String,
+    pub version: String,
+    /// Checksum of the file, so changes approved in a review are applied to
+    /// the catalog that was reviewed
+    pub revision: String,
+    library: BundleLibrary,
+}
+
+/// The catalog the library was last synced from.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CatalogStatus {
+    pub source: Option<String>,
+    pub version: Option<String>,
+    pub synced_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Add,
+    Update,
+    /// The catalog withdrew the row; it is kept but marked `deprecated`
+    Deprecate,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogChange {
+    /// `<table>:<library key>`, the same in every review of a catalog
+    pub id: String,
+    pub table: LibraryTable,
+    pub kind: ChangeKind,
+    pub name: String,
+    pub local_id: Option<i64>,
+    /// Differences from the local row; empty for additions
+    pub changes: Vec<FieldChange>,
+    /// The local row was added on this install, so the catalog never
+    /// overwrites it
+    pub protected: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogChangeset {
+    pub source: String,
+    pub version: String,
+    pub revision: String,
+    /// Catalog version the library was last synced to
+    pub local_version: Option<String>,
+    pub changes: Vec<CatalogChange>,
+    /// Catalog rows the library already matches
+    pub unchanged: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogSyncResult {
+    pub version: String,
+    pub added: usize,
+    pub updated: usize,
+    pub deprecated: usize,
+    /// Changes that weren't approved or would overwrite user-added rows
+    pub skipped: usize,
+}
+
+impl Catalog {
+    /// Reads the catalog at `source`, a file path or an http(s) URL.
+    pub fn fetch(source: &str) -> Result<Self, FilesystemError> {
+        let source = source.trim();
+        let data = if source.starts_with("http://") || source.starts_with("https://") {
+            download(source)?
+        } else {
+            let path = Path::new(source);
+            std::fs::read(path).map_err(FilesystemError::io("Failed to read catalog", path))?
+        };
+        Catalog::parse(source, &data)
+    }
+
+    fn parse(source: &str, data: &[u8]) -> Result<Self, FilesystemError> {
+        let path = Path::new(source);
+        let corrupt = |reason: String| FilesystemError::Corrupt {
+            path: path.to_path_buf(),
+            reason,
+        };
+        let value: Value =
+            serde_json::from_slice(data).map_err(|e| corrupt(format!("invalid JSON: {}", e)))?;
+        if value.get("format").is_none() {
+            return Err(corrupt("not a CellForge library catalog".to_string()));
+        }
+        let version = match value.get("catalog_version") {
+            Some(Value::String(version)) if !version.trim().is_empty() => {
+                version.trim().to_string()
+            }
+            Some(Value::Number(version)) => version.to_string(),
+            _ => return Err(corrupt("missing catalog_version".to_string())),
+        };
+
+        Ok(Catalog {
+            source: source.to_string(),
+            version,
+            revision: format!("{:08x}", crc32fast::hash(data)),
+            library: library_file::from_json(path, value)?,
+        })
+    }
+
+    /// Fails when the catalog changed since the review that produced
+    /// `revision`, so nothing unreviewed is applied.
+    pub fn check_revision(&self, revision: &str) -> Result<(), FilesystemError> {
+        if self.revision != revision {
+            return Err(FilesystemError::InvalidInput(format!(
+                "The catalog at {} changed since it was reviewed; review it again",
+                self.source
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn download(url: &str) -> Result<Vec<u8>, FilesystemError> {
+    // ureq's errors already name the URL
+    let unavailable = |reason: String| {
+        FilesystemError::Unavailable(format!("Failed to download catalog: {}", reason))
+    };
+    let response = ureq::AgentBuilder::new()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .get(url)
+        .call()
+        .map_err(|e| unavailable(e.to_string()))?;
+
+    let mut data = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_CATALOG_BYTES + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| unavailable(format!("{}: {}", url, e)))?;
+    if data.len() as u64 > MAX_CATALOG_BYTES {
+        return Err(FilesystemError::InvalidInput(format!(
+            "Catalog at {} is larger than {} MB",
+            url,
+            MAX_CATALOG_BYTES / (1024 * 1024)
+        )));
+    }
+    Ok(data)
+}
+
+pub fn status(db: &Database) -> Result<CatalogStatus, DatabaseError> {
+    let meta = |key| {
+        db.get_meta(key)
+            .map_err(DatabaseError::context("Failed to read library metadata"))
+    };
+    Ok(CatalogStatus {
+        source: meta(META_SOURCE)?,
+        version: meta(META_VERSION)?,
+        synced_at: meta(META_SYNCED_AT)?,
+    })
+}
+
+/// A row of either library, reduced to what the comparison needs.
+struct Entry {
+    key: String,
+    name: String,
+    /// Position in its table, to get the typed row back
+    index: usize,
+    values: Map<String, Value>,
+}
+
+impl Entry {
+    fn new<T: Serialize>(index: usize, row: &T, key: String, name: String) -> Self {
+        let values = match serde_json::to_value(row) {
+            Ok(Value::Object(values)) => values,
+            _ => Map::new(),
+        };
+        Entry {
+            key,
+            name,
+            index,
+            values,
+        }
+    }
+
+    fn id(&self) -> i64 {
+        self.values.get("id").and_then(Value::as_i64).unwrap_or(0)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.values
+            .get(name)
+            .and_then(Value::as_bool)
+            .unwrap_or(false)
+    }
+}
+
+fn entries(library: &BundleLibrary, table: LibraryTable) -> Vec<Entry> {
+    match table {
+        LibraryTable::Cells => library
+            .cells
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let name = format!("{} {}", c.manufacturer, c.model);
+                Entry::new(i, c, c.library_key(), name)
+            })
+            .collect(),
+        LibraryTable::Bms => library
+            .bms
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                let name = format!("{} {}", b.manufacturer, b.model);
+                Entry::new(i, b, b.library_key(), name)
+            })
+            .collect(),
+        LibraryTable::Materials => library
+            .materials
+            .iter()
+            .enumerate()
+            .map(|(i, m)| Entry::new(i, m, m.library_key(), m.name.clone()))
+            .collect(),
+        LibraryTable::Shapes => library
+            .shapes
+            .iter()
+            .enumerate()
+            .map(|(i, s)| Entry::new(i, s, s.library_key(), s.name.clone()))
+            .collect(),
+    }
+}
+
+/// Fields of the catalog row that differ locally. Row ids are local and
+/// `user_added` describes this install, so neither counts.
+fn compare(local: &Map<String, Value>, remote: &Map<String, Value>) -> Vec<FieldChange> {
+    remote
+        .iter()
+        .filter(|(field, _)| !matches!(field.as_str(), "id" | "user_added"))
+        .filter_map(|(field, imported)| {
+            let current = local.get(field).unwrap_or(&Value::Null);
+            let same = match (current.as_f64(), imported.as_f64()) {
+                (Some(a), Some(b)) => a == b,
+                _ => current == imported,
+            };
+            (!same).then(|| FieldChange {
+                field: field.clone(),
+                current: current.clone(),
+                imported: imported.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Changes with the index of their catalog row, and the unchanged count.
+fn plan(
+    db: &Database,
+    catalog: &Catalog,
+) -> Result<(Vec<(CatalogChange, usize)>, usize), DatabaseError> {
+    let local = library_file::select(db, &LibraryExportOptions::default())?;
+    let mut changes = Vec::new();
+    let mut unchanged = 0;
+
+    for table in LibraryTable::ALL {
+        // With duplicate local rows the lowest id wins, as in `LibraryKeys`
+        let mut by_key: HashMap<String, Entry> = HashMap::new();
+        for entry in entries(&local, table) {
+            match by_key.get(&entry.key) {
+                Some(existing) if existing.id() < entry.id() => {}
+                _ => {
+                    by_key.insert(entry.key.clone(), entry);
+                }
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for remote in entries(&catalog.library, table) {
+            if !seen.insert(remote.key.clone()) {
+                continue;
+            }
+            let change = match by_key.get(&remote.key) {
+                // Withdrawn before it ever reached this install
+                None if remote.flag("deprecated") => continue,
+                None => CatalogChange {
+                    id: format!("{}:{}", table.name(), remote.key),
+                    table,
+                    kind: ChangeKind::Add,
+                    name: remote.name.clone(),
+                    local_id: None,
+                    changes: Vec::new(),
+                    protected: false,
+                },
+                Some(local) => {
+                    let differences = compare(&local.values, &remote.values);
+                    if differences.is_empty() {
+                        unchanged += 1;
+                        continue;
+                    }
+                    let kind = if remote.flag("deprecated") && !local.flag("deprecated") {
+                        ChangeKind::Deprecate
+                    } else {
+                        ChangeKind::Update
+                    };
+                    CatalogChange {
+                        id: format!("{}:{}", table.name(), remote.key),
+                        table,
+                        kind,
+                        name: local.name.clone(),
+                        local_id: Some(local.id()),
+                        changes: differences,
+                        protected: local.flag("user_added"),
+                    }
+                }
+            };
+            changes.push((change, remote.index));
+        }
+    }
+    Ok((changes, unchanged))
+}
+
+/// What applying `catalog` would change in the library.
+pub fn review(db: &Database, catalog: &Catalog) -> Result<CatalogChangeset, DatabaseError> {
+    let (changes, unchanged) = plan(db, catalog)?;
+    Ok(CatalogChangeset {
+        source: catalog.source.clone(),
+        version: catalog.version.clone(),
+        revision: catalog.revision.clone(),
+        local_version: status(db)?.version,
+        changes: changes.into_iter().map(|(change, _)| change).collect(),
+        unchanged,
+    })
+}
+
+/// Applies the changes of `catalog` whose ids are in `approved`, in one
+/// transaction. Rows added on this install are never touched.
+pub fn apply(
+    db: &Database,
+    catalog: &Catalog,
+    approved: &[String],
+) -> Result<CatalogSyncResult, DatabaseError> {
+    let write_error = DatabaseError::context;
+    let transaction = db
+        .transaction()
+        .map_err(write_error("Failed to start catalog sync"))?;
+    let (changes, _) = plan(db, catalog)?;
+    let approved: HashSet<&str> = approved.iter().map(String::as_str).collect();
+
+    let mut result = CatalogSyncResult {
+        version: catalog.version.clone(),
+        added: 0,
+        updated: 0,
+        deprecated: 0,
+        skipped: 0,
+    };
+    for (change, index) in &changes {
+        if change.protected || !approved.contains(change.id.as_str()) {
+            result.skipped += 1;
+            continue;
+        }
+
+        let library = &catalog.library;
+        let written = match (change.table, change.local_id) {
+            (LibraryTable::Cells, id) => {
+                let mut cell = library.cells[*index].clone();
+                cell.user_added = false;
+                match id {
+                    Some(id) => db.update_cell(id, &cell).map(|_| ()),
+                    None => db.insert_cell(&cell).map(|_| ()),
+                }
+            }
+            (LibraryTable::Bms, id) => {
+                let mut bms = library.bms[*index].clone();
+                bms.user_added = false;
+                match id {
+                    Some(id) => db.update_bms(id, &bms).map(|_| ()),
+                    None => db.insert_bms(&bms).map(|_| ()),
+                }
+            }
+            (LibraryTable::Materials, id) => {
+                let mut material = library.materials[*index].clone();
+                material.user_added = false;
+                match id {
+                    Some(id) => db.update_material(id, &material).map(|_| ()),
+                    None => db.insert_material(&material).map(|_| ()),
+                }
+            }
+            (LibraryTable::Shapes, id) => {
+                let mut shape = library.shapes[*index].clone();
+                shape.user_added = false;
+                match id {
+                    Some(id) => db.update_shape(id, &shape).map(|_| ()),
+                    None => db.insert_shape(&shape).map(|_| ()),
+                }
+            }
+        };
+        written.map_err(write_error("Failed to apply catalog change"))?;
+
+        match change.kind {
+            ChangeKind::Add => result.added += 1,
+            ChangeKind::Update => result.updated += 1,
+            ChangeKind::Deprecate => result.deprecated += 1,
+        }
+    }
+
+    let synced_at = chrono::Utc::now().to_rfc3339();
+    for (key, value) in [
+        (META_SOURCE, catalog.source.as_str()),
+        (META_VERSION, catalog.version.as_str()),
+        (META_SYNCED_AT, synced_at.as_str()),
+    ] {
+        db.set_meta(key, value)
+            .map_err(write_error("Failed to record catalog version"))?;
+    }
+    transaction
+        .commit()
+        .map_err(write_error("Failed to commit catalog sync"))?;
+    Ok(result)
+}
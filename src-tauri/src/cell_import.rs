@@ -0,0 +1,796 @@
+use crate::database::{library_key, Cell, Database};
+use crate::error::{DatabaseError, FilesystemError};
+use crate::library_keys::LibraryKeys;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Values accepted by the `form_factor` and `chemistry` CHECK constraints.
+pub const FORM_FACTORS: &[&str] = &["18650", "21700", "26650", "4680", "prismatic", "pouch"];
+pub const CHEMISTRIES: &[&str] = &["NMC", "NCA", "LFP", "LTO", "LCO"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    Csv,
+    Json,
+}
+
+impl ImportFormat {
+    pub(crate) fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "csv" | "txt" => Some(ImportFormat::Csv),
+            "json" => Some(ImportFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// What happens to a row whose manufacturer and model are already in the
+/// library.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Keep the library row as it is
+    #[default]
+    Skip,
+    /// Take the imported values, keeping library values the file leaves blank
+    Merge,
+    /// Replace the library row; blank optional values clear it
+    Overwrite,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CellImportOptions {
+    /// Taken from the file extension when not given
+    pub format: Option<ImportFormat>,
+    /// Column name in the file to `Cell` field, e.g. `"Capacity" ->
+    /// "capacity_mah"`. An empty field ignores the column. Columns not
+    /// listed are matched by field name or a common alias.
+    pub mapping: HashMap<String, String>,
+    /// Unit of the values in the file per `Cell` field, e.g. `"capacity_mah"
+    /// -> "Ah"`. Overrides a unit in the column header, like `Capacity (Ah)`.
+    pub units: HashMap<String, String>,
+    pub policy: ConflictPolicy,
+    /// Field separator for CSV, `,` by default
+    pub delimiter: Option<char>,
+}
+
+/// How a column of the file was read.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportColumn {
+    pub column: String,
+    /// `None` for columns that are ignored
+    pub field: Option<String>,
+    /// Unit the values are converted from, `None` if already in the
+    /// field's own unit
+    pub unit: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportAction {
+    Add,
+    Update,
+    Skip,
+    Reject,
+}
+
+/// A value that differs between the library and the file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub current: Value,
+    pub imported: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CellImportRow {
+    /// 1-based position among the data rows of the file
+    pub row: usize,
+    pub name: String,
+    pub action: ImportAction,
+    /// Library row with the same manufacturer and model
+    pub existing_id: Option<i64>,
+    /// The cell as it is (or would be) written; `None` when rejected
+    pub cell: Option<Cell>,
+    /// Differences from the existing row, also listed for skipped rows
+    pub changes: Vec<FieldChange>,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CellImportReport {
+    /// `false` for a preview, which leaves the library untouched
+    pub committed: bool,
+    pub policy: ConflictPolicy,
+    pub columns: Vec<ImportColumn>,
+    pub rows: Vec<CellImportRow>,
+    pub added: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    pub rejected: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Text,
+    Real,
+    Integer,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Quantity {
+    None,
+    Voltage,
+    Current,
+    Capacity,
+    Resistance,
+    Mass,
+    Length,
+    Temperature,
+}
+
+struct Field {
+    name: &'static str,
+    kind: Kind,
+    quantity: Quantity,
+    required: bool,
+    aliases: &'static [&'static str],
+}
+
+const fn field(
+    name: &'static str,
+    kind: Kind,
+    quantity: Quantity,
+    required: bool,
+    aliases: &'static [&'static str],
+) -> Field {
+    Field {
+        name,
+        kind,
+        quantity,
+        required,
+        aliases,
+    }
+}
+
+// Every `Cell` column except `id`, which is local to an install
+#[rustfmt::skip]
+const FIELDS: &[Field] = &[
+    field("manufacturer", Kind::Text, Quantity::None, true, &["brand", "make"]),
+    field("model", Kind::Text, Quantity::None, true, &[]),
+    field("form_factor", Kind::Text, Quantity::None, true, &["format", "size"]),
+    field("chemistry", Kind::Text, Quantity::None, true, &[]),
+    field("nominal_voltage", Kind::Real, Quantity::Voltage, true, &["nominal"]),
+    field("max_voltage", Kind::Real, Quantity::Voltage, true, &["charge_voltage"]),
+    field("min_voltage", Kind::Real, Quantity::Voltage, true, &["cutoff_voltage"]),
+    field("capacity_mah", Kind::Integer, Quantity::Capacity, true, &["capacity"]),
+    field("max_discharge_a", Kind::Real, Quantity::Current, true, &["max_discharge"]),
+    field("max_charge_a", Kind::Real, Quantity::Current, true, &["max_charge"]),
+    field("internal_res_mohm", Kind::Real, Quantity::Resistance, false, &["internal_resistance", "ir"]),
+    field("weight_g", Kind::Real, Quantity::Mass, true, &["weight", "mass"]),
+    field("diameter_mm", Kind::Real, Quantity::Length, false, &["diameter"]),
+    field("length_mm", Kind::Real, Quantity::Length, true, &["length"]),
+    field("width_mm", Kind::Real, Quantity::Length, false, &["width"]),
+    field("height_mm", Kind::Real, Quantity::Length, false, &["height"]),
+    field("datasheet_url", Kind::Text, Quantity::None, false, &["datasheet"]),
+    field("thermal_limit_c", Kind::Real, Quantity::Temperature, false, &["thermal_limit", "max_temperature"]),
+    field("cycle_life", Kind::Integer, Quantity::None, false, &["cycles"]),
+];
+
+fn field_index(name: &str) -> Option<usize> {
+    FIELDS.iter().position(|f| f.name == name)
+}
+
+/// Lowercased with `Ω`/`µ` spelled out, so `mΩ`, `mohm` and `MOhm` agree.
+fn normalize_unit(unit: &str) -> String {
+    unit.trim()
+        .replace(['\u{3a9}', '\u{2126}'], "ohm")
+        .replace(['µ', 'μ'], "u")
+        .replace('°', "")
+        .to_lowercase()
+}
+
+/// Converts `value` in `unit` to the field's own unit, or `None` if the
+/// unit doesn't measure that quantity.
+pub(crate) fn convert(quantity: Quantity, unit: &str, value: f64) -> Option<f64> {
+    let factor = match (quantity, normalize_unit(unit).as_str()) {
+        (Quantity::Voltage, "v") => 1.0,
+        (Quantity::Voltage, "mv") => 1e-3,
+        (Quantity::Current, "a") => 1.0,
+        (Quantity::Current, "ma") => 1e-3,
+        (Quantity::Capacity, "mah") => 1.0,
+        (Quantity::Capacity, "ah") => 1e3,
+        (Quantity::Resistance, "mohm") => 1.0,
+        (Quantity::Resistance, "ohm") => 1e3,
+        (Quantity::Resistance, "uohm") => 1e-3,
+        (Quantity::Mass, "g") => 1.0,
+        (Quantity::Mass, "kg") => 1e3,
+        (Quantity::Mass, "mg") => 1e-3,
+        (Quantity::Mass, "oz") => 28.349523125,
+        (Quantity::Mass, "lb") => 453.59237,
+        (Quantity::Length, "mm") => 1.0,
+        (Quantity::Length, "cm") => 10.0,
+        (Quantity::Length, "m") => 1e3,
+        (Quantity::Length, "in") => 25.4,
+        (Quantity::Temperature, "c") => 1.0,
+        (Quantity::Temperature, "f") => return Some((value - 32.0) * 5.0 / 9.0),
+        (Quantity::Temperature, "k") => return Some(value - 273.15),
+        _ => return None,
+    };
+    Some(value * factor)
+}
+
+pub(crate) fn is_native_unit(quantity: Quantity, unit: &str) -> bool {
+    matches!(
+        (quantity, normalize_unit(unit).as_str()),
+        (Quantity::Voltage, "v")
+            | (Quantity::Current, "a")
+            | (Quantity::Capacity, "mah")
+            | (Quantity::Resistance, "mohm")
+            | (Quantity::Mass, "g")
+            | (Quantity::Length, "mm")
+            | (Quantity::Temperature, "c")
+    )
+}
+
+/// Splits `Capacity (Ah)` or `Capacity [Ah]` into a lookup name
+/// (`capacity`) and the unit.
+pub(crate) fn split_header(header: &str) -> (String, Option<String>) {
+    let header = header.trim();
+    let mut name = header;
+    let mut unit = None;
+    for (open, close) in [('(', ')'), ('[', ']')] {
+        if let (Some(start), true) = (header.rfind(open), header.ends_with(close)) {
+            name = &header[..start];
+            unit = Some(header[start + 1..header.len() - 1].trim().to_string());
+            break;
+        }
+    }
+
+    let mut normalized = String::new();
+    for c in name.trim().chars() {
+        if c.is_alphanumeric() {
+            normalized.extend(c.to_lowercase());
+        } else if !normalized.is_empty() && !normalized.ends_with('_') {
+            normalized.push('_');
+        }
+    }
+    let normalized = normalized.trim_end_matches('_').to_string();
+    (normalized, unit.filter(|u| !u.is_empty()))
+}
+
+/// Where each column of the file goes.
+struct ColumnPlan {
+    field: Option<usize>,
+    unit: Option<String>,
+}
+
+fn plan_columns(
+    headers: &[String],
+    options: &CellImportOptions,
+) -> Result<Vec<ColumnPlan>, FilesystemError> {
+    for field in options.mapping.values().chain(options.units.keys()) {
+        if !field.is_empty() && field_index(field).is_none() {
+            return Err(FilesystemError::InvalidInput(format!(
+                "Unknown cell field '{}'",
+                field
+            )));
+        }
+    }
+
+    let mut plans = Vec::with_capacity(headers.len());
+    let mut mapped: HashMap<usize, &str> = HashMap::new();
+    for header in headers {
+        let (name, header_unit) = split_header(header);
+        let field = match options.mapping.get(header.trim()) {
+            Some(field) if field.is_empty() => None,
+            Some(field) => field_index(field),
+            None => FIELDS
+                .iter()
+                .position(|f| f.name == name || f.aliases.contains(&name.as_str())),
+        };
+
+        let Some(index) = field else {
+            plans.push(ColumnPlan {
+                field: None,
+                unit: None,
+            });
+            continue;
+        };
+        if let Some(other) = mapped.insert(index, header) {
+            return Err(FilesystemError::InvalidInput(format!(
+                "Columns '{}' and '{}' both map to {}",
+                other, header, FIELDS[index].name
+            )));
+        }
+
+        let quantity = FIELDS[index].quantity;
+        let unit = options
+            .units
+            .get(FIELDS[index].name)
+            .cloned()
+            .or(header_unit)
+            .filter(|_| quantity != Quantity::None);
+        if let Some(unit) = &unit {
+            if convert(quantity, unit, 1.0).is_none() {
+                return Err(FilesystemError::InvalidInput(format!(
+                    "Unit '{}' of column '{}' can't be converted to {}",
+                    unit, header, FIELDS[index].name
+                )));
+            }
+        }
+        plans.push(ColumnPlan {
+            field: Some(index),
+            unit: unit.filter(|u| !is_native_unit(quantity, u)),
+        });
+    }
+    Ok(plans)
+}
+
+/// A data row reduced to the fields it has values for, converted to the
+/// field's unit.
+struct ParsedRow {
+    row: usize,
+    values: Map<String, Value>,
+    errors: Vec<String>,
+}
+
+fn parse_value(field: &Field, unit: Option<&str>, raw: &Value) -> Result<Option<Value>, String> {
+    let text = match raw {
+        Value::Null => return Ok(None),
+        Value::String(s) if s.trim().is_empty() => return Ok(None),
+        Value::String(s) => s.trim().to_string(),
+        other => other.to_string(),
+    };
+
+    match field.kind {
+        Kind::Text => Ok(Some(Value::String(match field.name {
+            "form_factor" => normalize_form_factor(&text),
+            "chemistry" => normalize_chemistry(&text),
+            _ => text,
+        }))),
+        Kind::Real | Kind::Integer => {
+            let number = match raw {
+                Value::Number(n) => n.as_f64(),
+                _ => text.parse::<f64>().ok(),
+            }
+            .filter(|n| n.is_finite())
+            .ok_or_else(|| format!("{}: '{}' is not a number", field.name, text))?;
+            let number = match unit {
+                // Rounded so 0.07 kg reads as 70 g, not 70.00000000000001
+                Some(unit) => {
+                    let converted = convert(field.quantity, unit, number).unwrap_or(number);
+                    (converted * 1e6).round() / 1e6
+                }
+                None => number,
+            };
+            if field.kind == Kind::Integer {
+                Ok(Some(Value::from(number.round() as i64)))
+            } else {
+                Ok(Some(Value::from(number)))
+            }
+        }
+    }
+}
+
+/// Spelling variants of allowed values, e.g. `Pouch` or `LiFePO4`. Anything
+/// else is kept as written so validation can quote it.
+fn normalize_form_factor(value: &str) -> String {
+    let lower = value.to_lowercase();
+    if FORM_FACTORS.contains(&lower.as_str()) {
+        lower
+    } else {
+        value.to_string()
+    }
+}
+
+fn normalize_chemistry(value: &str) -> String {
+    match value.to_uppercase().as_str() {
+        "LIFEPO4" => "LFP".to_string(),
+        "NCM" => "NMC".to_string(),
+        upper if CHEMISTRIES.contains(&upper) => upper.to_string(),
+        _ => value.to_string(),
+    }
+}
+
+fn parse_rows(
+    headers: &[String],
+    records: Vec<Vec<Value>>,
+    options: &CellImportOptions,
+) -> Result<(Vec<ImportColumn>, Vec<ParsedRow>), FilesystemError> {
+    let plans = plan_columns(headers, options)?;
+    let columns = headers
+        .iter()
+        .zip(&plans)
+        .map(|(header, plan)| ImportColumn {
+            column: header.clone(),
+            field: plan.field.map(|i| FIELDS[i].name.to_string()),
+            unit: plan.unit.clone(),
+        })
+        .collect();
+
+    let rows = records
+        .into_iter()
+        .enumerate()
+        .map(|(i, record)| {
+            let mut row = ParsedRow {
+                row: i + 1,
+                values: Map::new(),
+                errors: Vec::new(),
+            };
+            for (plan, raw) in plans.iter().zip(&record) {
+                let Some(index) = plan.field else { continue };
+                match parse_value(&FIELDS[index], plan.unit.as_deref(), raw) {
+                    Ok(Some(value)) => {
+                        row.values.insert(FIELDS[index].name.to_string(), value);
+                    }
+                    Ok(None) => {}
+                    Err(e) => row.errors.push(e),
+                }
+            }
+            row
+        })
+        .collect();
+    Ok((columns, rows))
+}
+
+pub(crate) fn read_csv(
+    path: &Path,
+    data: &[u8],
+    delimiter: Option<char>,
+) -> Result<(Vec<String>, Vec<Vec<Value>>), FilesystemError> {
+    let corrupt = |e: csv::Error| FilesystemError::Corrupt {
+        path: path.to_path_buf(),
+        reason: e.to_string(),
+    };
+    let delimiter = delimiter.unwrap_or(',');
+    if !delimiter.is_ascii() {
+        return Err(FilesystemError::InvalidInput(format!(
+            "Delimiter '{}' is not a single-byte character",
+            delimiter
+        )));
+    }
+
+    // Lines starting with `#` are comments, e.g. a library export's header
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter as u8)
+        .comment(Some(b'#'))
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data);
+    let headers = reader
+        .headers()
+        .map_err(corrupt)?
+        .iter()
+        .map(|h| h.trim_start_matches('\u{feff}').to_string())
+        .collect();
+    let mut records = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(corrupt)?;
+        if record.iter().all(str::is_empty) {
+            continue;
+        }
+        records.push(
+            record
+                .iter()
+                .map(|v| Value::String(v.to_string()))
+                .collect(),
+        );
+    }
+    Ok((headers, records))
+}
+
+/// Accepts an array of objects, or an object with such an array under
+/// `cells`.
+fn read_json(path: &Path, data: &[u8]) -> Result<(Vec<String>, Vec<Vec<Value>>), FilesystemError> {
+    let corrupt = |reason: String| FilesystemError::Corrupt {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let value: Value = serde_json::from_slice(data).map_err(|e| corrupt(e.to_string()))?;
+    let entries = match value {
+        Value::Array(entries) => entries,
+        Value::Object(mut object) => match object.remove("cells") {
+            Some(Value::Array(entries)) => entries,
+            _ => return Err(corrupt("expected a 'cells' array".to_string())),
+        },
+        _ => return Err(corrupt("expected an array of cells".to_string())),
+    };
+
+    let mut headers: Vec<String> = Vec::new();
+    let mut objects = Vec::with_capacity(entries.len());
+    for (i, entry) in entries.into_iter().enumerate() {
+        let Value::Object(object) = entry else {
+            return Err(corrupt(format!("entry {} is not an object", i + 1)));
+        };
+        for key in object.keys() {
+            if !headers.contains(key) {
+                headers.push(key.clone());
+            }
+        }
+        objects.push(object);
+    }
+    let records = objects
+        .into_iter()
+        .map(|mut object| {
+            headers
+                .iter()
+                .map(|h| object.remove(h).unwrap_or(Value::Null))
+                .collect()
+        })
+        .collect();
+    Ok((headers, records))
+}
+
+/// Checks a complete cell against the table's constraints and basic
+/// plausibility.
+fn validate(cell: &Cell) -> Vec<String> {
+    let mut errors = Vec::new();
+    if cell.manufacturer.trim().is_empty() || cell.model.trim().is_empty() {
+        errors.push("manufacturer and model must not be empty".to_string());
+    }
+    if !FORM_FACTORS.contains(&cell.form_factor.as_str()) {
+        errors.push(format!(
+            "form_factor: '{}' is not one of {}",
+            cell.form_factor,
+            FORM_FACTORS.join(", ")
+        ));
+    }
+    if !CHEMISTRIES.contains(&cell.chemistry.as_str()) {
+        errors.push(format!(
+            "chemistry: '{}' is not one of {}",
+            cell.chemistry,
+            CHEMISTRIES.join(", ")
+        ));
+    }
+    if !(cell.min_voltage > 0.0
+        && cell.min_voltage <= cell.nominal_voltage
+        && cell.nominal_voltage <= cell.max_voltage)
+    {
+        errors.push(format!(
+            "voltages must satisfy 0 < min ({}) <= nominal ({}) <= max ({})",
+            cell.min_voltage, cell.nominal_voltage, cell.max_voltage
+        ));
+    }
+    if cell.capacity_mah <= 0 {
+        errors.push("capacity_mah must be positive".to_string());
+    }
+    if cell.max_discharge_a <= 0.0 || cell.max_charge_a <= 0.0 {
+        errors.push("max_discharge_a and max_charge_a must be positive".to_string());
+    }
+    if cell.weight_g <= 0.0 || cell.length_mm <= 0.0 {
+        errors.push("weight_g and length_mm must be positive".to_string());
+    }
+    let optional = [
+        ("internal_res_mohm", cell.internal_res_mohm),
+        ("diameter_mm", cell.diameter_mm),
+        ("width_mm", cell.width_mm),
+        ("height_mm", cell.height_mm),
+    ];
+    for (name, value) in optional {
+        if value.is_some_and(|v| v <= 0.0) {
+            errors.push(format!("{} must be positive", name));
+        }
+    }
+    if cell.cycle_life.is_some_and(|c| c <= 0) {
+        errors.push("cycle_life must be positive".to_string());
+    }
+    errors
+}
+
+/// Builds the cell to write from `base` (the existing row's values, or
+/// none) with the imported `values` on top.
+fn build_cell(base: Map<String, Value>, values: &Map<String, Value>) -> Result<Cell, Vec<String>> {
+    let mut object = base;
+    object.insert("id".to_string(), Value::from(0));
+    for field in FIELDS {
+        match values.get(field.name) {
+            Some(value) => {
+                object.insert(field.name.to_string(), value.clone());
+            }
+            None => {
+                object.entry(field.name).or_insert(Value::Null);
+            }
+        }
+    }
+
+    let missing: Vec<_> = FIELDS
+        .iter()
+        .filter(|f| f.required && object[f.name].is_null())
+        .map(|f| f.name)
+        .collect();
+    if !missing.is_empty() {
+        return Err(vec![format!("missing {}", missing.join(", "))]);
+    }
+    serde_json::from_value(Value::Object(object)).map_err(|e| vec![e.to_string()])
+}
+
+fn cell_values(cell: &Cell) -> Map<String, Value> {
+    match serde_json::to_value(cell) {
+        Ok(Value::Object(object)) => object,
+        _ => Map::new(),
+    }
+}
+
+fn diff(existing: &Cell, imported: &Cell) -> Vec<FieldChange> {
+    let current = cell_values(existing);
+    let imported = cell_values(imported);
+    FIELDS
+        .iter()
+        .filter_map(|f| {
+            let (a, b) = (&current[f.name], &imported[f.name]);
+            let same = match (a.as_f64(), b.as_f64()) {
+                (Some(x), Some(y)) => x == y,
+                _ => a == b,
+            };
+            (!same).then(|| FieldChange {
+                field: f.name.to_string(),
+                current: a.clone(),
+                imported: b.clone(),
+            })
+        })
+        .collect()
+}
+
+fn plan_rows(
+    rows: Vec<ParsedRow>,
+    db: &Database,
+    policy: ConflictPolicy,
+) -> Result<Vec<CellImportRow>, DatabaseError> {
+    let keys = LibraryKeys::load(db).map_err(DatabaseError::context("Failed to read library"))?;
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut planned = Vec::with_capacity(rows.len());
+
+    for parsed in rows {
+        let text = |field: &str| {
+            parsed
+                .values
+                .get(field)
+                .and_then(Value::as_str)
+                .unwrap_or("")
+        };
+        let name = format!("{} {}", text("manufacturer"), text("model"))
+            .trim()
+            .to_string();
+        let key = (!text("manufacturer").is_empty() && !text("model").is_empty())
+            .then(|| library_key(&[text("manufacturer"), text("model")]));
+        let mut row = CellImportRow {
+            row: parsed.row,
+            name,
+            action: ImportAction::Reject,
+            existing_id: key.as_deref().and_then(|k| keys.cells.id(k)),
+            cell: None,
+            changes: Vec::new(),
+            errors: parsed.errors,
+        };
+
+        if let Some(key) = key {
+            match seen.get(&key) {
+                Some(first) => row.errors.push(format!("same cell as row {}", first)),
+                None => {
+                    seen.insert(key, row.row);
+                }
+            }
+        }
+
+        let existing = match row.existing_id {
+            Some(id) => db
+                .get_cell_by_id(id)
+                .map_err(DatabaseError::context("Failed to read library"))?,
+            None => None,
+        };
+        let base = match (&existing, policy) {
+            (Some(existing), ConflictPolicy::Merge) => cell_values(existing),
+            _ => Map::new(),
+        };
+        // Values that didn't parse would only show up again as missing
+        let built = if row.errors.is_empty() {
+            build_cell(base, &parsed.values)
+        } else {
+            Err(Vec::new())
+        };
+        match built {
+            Ok(mut cell) => {
+                row.errors.extend(validate(&cell));
+                cell.user_added = true;
+                if let Some(existing) = &existing {
+                    cell.id = existing.id;
+                    cell.user_added = existing.user_added;
+                    cell.deprecated = existing.deprecated;
+                    row.changes = diff(existing, &cell);
+                }
+                if row.errors.is_empty() {
+                    row.action = match (&existing, policy) {
+                        (None, _) => ImportAction::Add,
+                        (Some(_), ConflictPolicy::Skip) => ImportAction::Skip,
+                        (Some(_), _) if row.changes.is_empty() => ImportAction::Skip,
+                        (Some(_), _) => ImportAction::Update,
+                    };
+                    row.cell = Some(cell);
+                }
+            }
+            Err(errors) => row.errors.extend(errors),
+        }
+        planned.push(row);
+    }
+    Ok(planned)
+}
+
+/// Reads `path` and matches its rows against the library. With `commit`
+/// the planned additions and updates are written in one transaction;
+/// otherwise the library is left untouched and the report is a preview.
+pub fn import_cells(
+    path: &Path,
+    options: &CellImportOptions,
+    db: &Database,
+    commit: bool,
+) -> Result<CellImportReport, FilesystemError> {
+    let format = options
+        .format
+        .or_else(|| ImportFormat::from_path(path))
+        .ok_or_else(|| {
+            FilesystemError::InvalidInput(format!(
+                "Can't tell the format of {}; choose CSV or JSON",
+                path.display()
+            ))
+        })?;
+    let data =
+        std::fs::read(path).map_err(FilesystemError::io("Failed to read import file", path))?;
+    let (headers, records) = match format {
+        ImportFormat::Csv => read_csv(path, &data, options.delimiter)?,
+        ImportFormat::Json => read_json(path, &data)?,
+    };
+    let (columns, parsed) = parse_rows(&headers, records, options)?;
+
+    // Planning happens inside the transaction so a commit writes exactly
+    // what was checked
+    let transaction = if commit {
+        Some(
+            db.transaction()
+                .map_err(DatabaseError::context("Failed to start import"))?,
+        )
+    } else {
+        None
+    };
+    let mut rows = plan_rows(parsed, db, options.policy)?;
+    if let Some(transaction) = transaction {
+        let write_error = DatabaseError::context;
+        for row in &mut rows {
+            let Some(cell) = row.cell.as_mut() else {
+                continue;
+            };
+            match row.action {
+                ImportAction::Add => {
+                    cell.id = db
+                        .insert_cell(cell)
+                        .map_err(write_error("Failed to add cell"))?;
+                }
+                ImportAction::Update => {
+                    db.update_cell(cell.id, cell)
+                        .map_err(write_error("Failed to update cell"))?;
+                }
+                ImportAction::Skip | ImportAction::Reject => {}
+            }
+        }
+        transaction
+            .commit()
+            .map_err(DatabaseError::context("Failed to commit import"))?;
+    }
+
+    let count = |action| rows.iter().filter(|r| r.action == action).count();
+    Ok(CellImportReport {
+        committed: commit,
+        policy: options.policy,
+        columns,
+        added: count(ImportAction::Add),
+        updated: count(ImportAction::Update),
+        skipped: count(ImportAction::Skip),
+        rejected: count(ImportAction::Reject),
+        rows,
+    })
+}
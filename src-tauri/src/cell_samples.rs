@@ -0,0 +1,492 @@
+use crate::cell_import::{self, ImportColumn, Quantity};
+use crate::database::{CellSample, Database};
+use crate::error::{DatabaseError, FilesystemError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SampleImportOptions {
+    /// Column name in the log to sample field (`serial`, `capacity_mah`,
+    /// `internal_res_mohm`, `voltage`, `measured_at`). An empty field
+    /// ignores the column. Columns not listed are matched by name or a
+    /// common alias.
+    pub mapping: HashMap<String, String>,
+    /// Unit of the log's values per field, e.g. `"internal_res_mohm" ->
+    /// "ohm"`. Overrides a unit in the column header.
+    pub units: HashMap<String, String>,
+    /// Field separator; by default whichever of `,`, `;` and tab the
+    /// header line uses most, as testers differ
+    pub delimiter: Option<char>,
+    /// Decimal separator of the values; by default `,` when fields are
+    /// separated by `;`, as testers set to a European locale write them,
+    /// and `.` otherwise. Values with a `.` are read either way.
+    pub decimal: Option<char>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RejectedSample {
+    /// 1-based position among the data rows of the log
+    pub row: usize,
+    pub serial: String,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SampleImportReport {
+    pub cell_id: i64,
+    pub columns: Vec<ImportColumn>,
+    pub added: usize,
+    /// Serials measured before, whose values were replaced
+    pub updated: usize,
+    pub rejected: Vec<RejectedSample>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistogramBin {
+    pub start: f64,
+    pub end: f64,
+    pub count: usize,
+}
+
+/// Spread of one measured value across the samples of a cell model.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Distribution {
+    pub count: usize,
+    pub mean: f64,
+    /// Sample standard deviation; 0 for a single sample
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+    pub median: f64,
+    /// The datasheet value, when it has one
+    pub rated: Option<f64>,
+    /// `mean / rated`
+    pub mean_of_rated: Option<f64>,
+    pub histogram: Vec<HistogramBin>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CellSampleStats {
+    pub cell_id: i64,
+    pub samples: usize,
+    pub capacity_mah: Option<Distribution>,
+    pub internal_res_mohm: Option<Distribution>,
+    pub voltage: Option<Distribution>,
+}
+
+/// Column index and unit to convert from, per field of `FIELDS`.
+type ColumnPlan = [Option<(usize, Option<String>)>; 5];
+
+struct Field {
+    name: &'static str,
+    quantity: Quantity,
+    aliases: &'static [&'static str],
+}
+
+const SERIAL: usize = 0;
+const MEASURED_AT: usize = 4;
+
+// Measurements sit between the serial and the date
+const FIELDS: &[Field] = &[
+    Field {
+        name: "serial",
+        quantity: Quantity::None,
+        aliases: &["sn", "serial_number", "serial_no", "barcode"],
+    },
+    Field {
+        name: "capacity_mah",
+        quantity: Quantity::Capacity,
+        aliases: &["capacity", "cap", "discharge_capacity"],
+    },
+    Field {
+        name: "internal_res_mohm",
+        quantity: Quantity::Resistance,
+        aliases: &["ir", "internal_resistance", "resistance", "acir", "dcir"],
+    },
+    Field {
+        name: "voltage",
+        quantity: Quantity::Voltage,
+        aliases: &["ocv", "v", "open_circuit_voltage"],
+    },
+    Field {
+        name: "measured_at",
+        quantity: Quantity::None,
+        aliases: &[
+            "date",
+            "time",
+            "timestamp",
+            "tested_at",
+            "test_date",
+            "datetime",
+        ],
+    },
+];
+
+fn plan_columns(
+    headers: &[String],
+    options: &SampleImportOptions,
+) -> Result<(Vec<ImportColumn>, ColumnPlan), FilesystemError> {
+    let field_index = |name: &str| FIELDS.iter().position(|f| f.name == name);
+    for field in options.mapping.values().chain(options.units.keys()) {
+        if !field.is_empty() && field_index(field).is_none() {
+            return Err(FilesystemError::InvalidInput(format!(
+                "Unknown sample field '{}'",
+                field
+            )));
+        }
+    }
+
+    let mut columns = Vec::with_capacity(headers.len());
+    let mut plan: ColumnPlan = Default::default();
+    for (column, header) in headers.iter().enumerate() {
+        let (name, header_unit) = cell_import::split_header(header);
+        let field = match options.mapping.get(header.trim()) {
+            Some(field) if field.is_empty() => None,
+            Some(field) => field_index(field),
+            None => FIELDS
+                .iter()
+                .position(|f| f.name == name || f.aliases.contains(&name.as_str())),
+        };
+        let Some(index) = field else {
+            columns.push(ImportColumn {
+                column: header.clone(),
+                field: None,
+                unit: None,
+            });
+            continue;
+        };
+        if let Some((other, _)) = &plan[index] {
+            return Err(FilesystemError::InvalidInput(format!(
+                "Columns '{}' and '{}' both map to {}",
+                headers[*other], header, FIELDS[index].name
+            )));
+        }
+
+        let quantity = FIELDS[index].quantity;
+        let unit = options
+            .units
+            .get(FIELDS[index].name)
+            .cloned()
+            .or(header_unit)
+            .filter(|_| quantity != Quantity::None);
+        if let Some(unit) = &unit {
+            if cell_import::convert(quantity, unit, 1.0).is_none() {
+                return Err(FilesystemError::InvalidInput(format!(
+                    "Unit '{}' of column '{}' can't be converted to {}",
+                    unit, header, FIELDS[index].name
+                )));
+            }
+        }
+        let unit = unit.filter(|u| !cell_import::is_native_unit(quantity, u));
+        columns.push(ImportColumn {
+            column: header.clone(),
+            field: Some(FIELDS[index].name.to_string()),
+            unit: unit.clone(),
+        });
+        plan[index] = Some((column, unit));
+    }
+
+    if plan[SERIAL].is_none() {
+        return Err(FilesystemError::InvalidInput(
+            "The log has no serial number column; map one to 'serial'".to_string(),
+        ));
+    }
+    Ok((columns, plan))
+}
+
+/// Accepts the ISO forms testers write, keeping the date only when there
+/// is no time.
+fn parse_date(text: &str) -> Option<String> {
+    use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+    if let Ok(at) = DateTime::parse_from_rfc3339(text) {
+        return Some(at.to_rfc3339());
+    }
+    for format in [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y/%m/%d %H:%M:%S",
+    ] {
+        if let Ok(at) = NaiveDateTime::parse_from_str(text, format) {
+            return Some(at.format("%Y-%m-%dT%H:%M:%S").to_string());
+        }
+    }
+    for format in ["%Y-%m-%d", "%Y/%m/%d"] {
+        if let Ok(date) = NaiveDate::parse_from_str(text, format) {
+            return Some(date.format("%Y-%m-%d").to_string());
+        }
+    }
+    None
+}
+
+/// The separator the first line that isn't a comment uses most.
+fn sniff_delimiter(data: &[u8]) -> Option<char> {
+    let header = String::from_utf8_lossy(data)
+        .lines()
+        .find(|line| !line.trim().is_empty() && !line.starts_with('#'))?
+        .to_string();
+    [',', ';', '\t']
+        .into_iter()
+        .map(|d| (header.matches(d).count(), d))
+        .filter(|(count, _)| *count > 0)
+        .max_by_key(|(count, _)| *count)
+        .map(|(_, d)| d)
+}
+
+fn parse_sample(
+    cell_id: i64,
+    record: &[Value],
+    plan: &ColumnPlan,
+    decimal: char,
+) -> Result<CellSample, Vec<String>> {
+    let text = |field: usize| {
+        plan[field]
+            .as_ref()
+            .and_then(|(column, _)| record.get(*column))
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+    };
+    let mut errors = Vec::new();
+
+    let mut measure = |field: usize| {
+        let raw = text(field)?;
+        let Ok(value) = raw.replace(decimal, ".").parse::<f64>() else {
+            errors.push(format!("{}: '{}' is not a number", FIELDS[field].name, raw));
+            return None;
+        };
+        let value = match plan[field].as_ref().and_then(|(_, unit)| unit.as_deref()) {
+            Some(unit) => cell_import::convert(FIELDS[field].quantity, unit, value)
+                .map(|v| (v * 1e6).round() / 1e6)
+                .unwrap_or(value),
+            None => value,
+        };
+        if !(value.is_finite() && value > 0.0) {
+            errors.push(format!("{} must be positive", FIELDS[field].name));
+            return None;
+        }
+        Some(value)
+    };
+    let capacity_mah = measure(1);
+    let internal_res_mohm = measure(2);
+    let voltage = measure(3);
+
+    let serial = text(SERIAL).unwrap_or_default().to_string();
+    if serial.is_empty() {
+        errors.push("missing serial".to_string());
+    }
+    if capacity_mah.is_none()
+        && internal_res_mohm.is_none()
+        && voltage.is_none()
+        && errors.is_empty()
+    {
+        errors.push("no capacity, internal resistance or voltage".to_string());
+    }
+    let measured_at = match text(MEASURED_AT) {
+        Some(raw) => match parse_date(raw) {
+            Some(date) => Some(date),
+            None => {
+                errors.push(format!("measured_at: '{}' is not an ISO date", raw));
+                None
+            }
+        },
+        None => None,
+    };
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(CellSample {
+        id: 0,
+        cell_id,
+        serial,
+        capacity_mah,
+        internal_res_mohm,
+        voltage,
+        measured_at,
+    })
+}
+
+/// Imports a tester's CSV log of cells of the library cell `cell_id` in
+/// one transaction. Rows that can't be read are reported and left out.
+pub fn import_samples(
+    db: &Database,
+    cell_id: i64,
+    path: &Path,
+    options: &SampleImportOptions,
+) -> Result<SampleImportReport, FilesystemError> {
+    db.get_cell_by_id(cell_id)
+        .map_err(DatabaseError::context("Failed to read library"))?
+        .ok_or(DatabaseError::NotFound {
+            entity: "Cell",
+            id: cell_id,
+        })?;
+
+    let data =
+        std::fs::read(path).map_err(FilesystemError::io("Failed to read tester log", path))?;
+    let delimiter = options.delimiter.or_else(|| sniff_delimiter(&data));
+    let decimal = match (options.decimal, delimiter) {
+        (Some(decimal), _) => decimal,
+        (None, Some(';')) => ',',
+        (None, _) => '.',
+    };
+    if delimiter.unwrap_or(',') == decimal {
+        return Err(FilesystemError::InvalidInput(format!(
+            "'{}' can't separate both fields and decimals",
+            decimal
+        )));
+    }
+    let (headers, records) = cell_import::read_csv(path, &data, delimiter)?;
+    let (columns, plan) = plan_columns(&headers, options)?;
+
+    let mut report = SampleImportReport {
+        cell_id,
+        columns,
+        added: 0,
+        updated: 0,
+        rejected: Vec::new(),
+    };
+    let write_error = DatabaseError::context;
+    let transaction = db
+        .transaction()
+        .map_err(write_error("Failed to start import"))?;
+    for (i, record) in records.iter().enumerate() {
+        match parse_sample(cell_id, record, &plan, decimal) {
+            Ok(sample) => {
+                let added = db
+                    .upsert_cell_sample(&sample)
+                    .map_err(write_error("Failed to save sample"))?;
+                if added {
+                    report.added += 1;
+                } else {
+                    report.updated += 1;
+                }
+            }
+            Err(errors) => report.rejected.push(RejectedSample {
+                row: i + 1,
+                serial: plan[SERIAL]
+                    .as_ref()
+                    .and_then(|(column, _)| record.get(*column))
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                errors,
+            }),
+        }
+    }
+    transaction
+        .commit()
+        .map_err(write_error("Failed to commit import"))?;
+    Ok(report)
+}
+
+/// Histogram over `bins` equal-width bins from the smallest to the largest
+/// value; the largest value falls in the last bin.
+fn histogram(sorted: &[f64], bins: usize) -> Vec<HistogramBin> {
+    let (min, max) = (sorted[0], sorted[sorted.len() - 1]);
+    if max == min {
+        return vec![HistogramBin {
+            start: min,
+            end: max,
+            count: sorted.len(),
+        }];
+    }
+
+    let width = (max - min) / bins as f64;
+    let mut histogram: Vec<_> = (0..bins)
+        .map(|i| HistogramBin {
+            start: min + width * i as f64,
+            end: if i + 1 == bins {
+                max
+            } else {
+                min + width * (i + 1) as f64
+            },
+            count: 0,
+        })
+        .collect();
+    for value in sorted {
+        let bin = (((value - min) / width) as usize).min(bins - 1);
+        histogram[bin].count += 1;
+    }
+    histogram
+}
+
+fn distribution(
+    mut values: Vec<f64>,
+    rated: Option<f64>,
+    bins: Option<usize>,
+) -> Option<Distribution> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+
+    let n = values.len();
+    let mean = values.iter().sum::<f64>() / n as f64;
+    let std_dev = if n > 1 {
+        (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1) as f64).sqrt()
+    } else {
+        0.0
+    };
+    let median = if n.is_multiple_of(2) {
+        (values[n / 2 - 1] + values[n / 2]) / 2.0
+    } else {
+        values[n / 2]
+    };
+    // Sturges' rule unless asked for a number of bins
+    let bins = bins
+        .unwrap_or_else(|| (n as f64).log2().ceil() as usize + 1)
+        .clamp(1, 100);
+
+    Some(Distribution {
+        count: n,
+        mean,
+        std_dev,
+        min: values[0],
+        max: values[n - 1],
+        median,
+        rated,
+        mean_of_rated: rated.filter(|r| *r > 0.0).map(|r| mean / r),
+        histogram: histogram(&values, bins),
+    })
+}
+
+/// Measured capacity, internal resistance and voltage of the samples of
+/// the library cell `cell_id`, next to its datasheet values.
+pub fn statistics(
+    db: &Database,
+    cell_id: i64,
+    bins: Option<usize>,
+) -> Result<CellSampleStats, DatabaseError> {
+    let cell = db
+        .get_cell_by_id(cell_id)
+        .map_err(DatabaseError::context("Failed to read library"))?
+        .ok_or(DatabaseError::NotFound {
+            entity: "Cell",
+            id: cell_id,
+        })?;
+    let samples = db
+        .get_cell_samples(cell_id)
+        .map_err(DatabaseError::context("Failed to read samples"))?;
+    let values = |get: fn(&CellSample) -> Option<f64>| samples.iter().filter_map(get).collect();
+
+    Ok(CellSampleStats {
+        cell_id,
+        samples: samples.len(),
+        capacity_mah: distribution(
+            values(|s| s.capacity_mah),
+            Some(cell.capacity_mah as f64),
+            bins,
+        ),
+        internal_res_mohm: distribution(
+            values(|s| s.internal_res_mohm),
+            cell.internal_res_mohm,
+            bins,
+        ),
+        voltage: distribution(values(|s| s.voltage), None, bins),
+    })
+}
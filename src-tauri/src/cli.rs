@@ -0,0 +1,1017 @@
+//! `cellforge-cli`: project, analysis, export and library operations without
+//! the GUI, for scripts and CI.
+
+use crate::analysis::{self, PackLibrary};
+use crate::bundle::{self, BundleImportReport};
+use crate::catalog::{self, Catalog};
+use crate::cell_import::{self, CellImportOptions, ImportAction};
+use crate::cell_samples::{self, Distribution, SampleImportOptions};
+use crate::database::Database;
+use crate::error::{DatabaseError, ExportError, FilesystemError};
+use crate::export::{ExportFormat, ExportLibrary, Exporter};
+use crate::filesystem::{Filesystem, ProjectFile, SaveFormat};
+use crate::library_file::{self, LibraryExportOptions};
+use crate::library_keys::LibraryKeys;
+use crate::matching::{self, MatchOptions};
+use crate::validation::{self, LibraryIds};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const USAGE: &str = "\
+Usage: cellforge-cli [--db <library.db>] [--json] <command> [arguments]
+
+Commands:
+  info <project>                        Project summary
+  convert <input> <output> [--format compressed|plain]
+                                        Re-save a project in another format
+  validate <project> [--repair] [--output <project>]
+                                        Check references against the library
+  analyze <project>                     Pack configuration and ratings
+  drc <project>                         Design rule check
+  bom <project> [--output <file.csv|file.json>]
+                                        Bill of materials
+  match <project> [--output <project>] [--table <file.csv|file.json>]
+                [--serials <serial>,...] [--resistance-weight <w>]
+                                        Assign measured serials to the cells of
+                                        each parallel group, balancing capacity
+                                        and resistance; --output saves the
+                                        labelled project
+  export <project> <output> [--format stl|3mf|gltf|glb|step|obj] [--options <json>]
+                                        Export geometry; the format defaults to
+                                        the output's extension
+  library cells [--search <text>]       List library cells
+  library bms|materials|shapes          List other library entries
+  library import <library.json|table.csv>...
+                                        Add cells, BMS, materials and shapes
+                                        not in the library yet
+  library export <file.json|file.csv> [--tables cells,bms,materials,shapes]
+                [--user-added] [--search <text>] [--manufacturer <name>]
+                [--catalog-version <version>]
+                                        Export library rows; CSV writes one
+                                        file per table
+  library sync <catalog.json|url> [--approve all|<change id>,...]
+                                        Review a shared catalog against the
+                                        library, applying approved changes
+  library import-cells <file.csv|file.json> [--policy skip|merge|overwrite]
+                [--mapping <json>] [--units <json>] [--format csv|json] [--dry-run]
+                                        Import datasheet cells; fails when a
+                                        row is rejected
+  library samples import <cell id> <log.csv> [--mapping <json>] [--units <json>]
+                [--decimal ,|.]         Record measured cells from a tester log;
+                                        fails when a row is rejected
+  library samples stats <cell id> [--bins <n>]
+                                        Measured capacity and IR distributions
+
+The library database defaults to $CELLFORGE_DB. With --json, results are
+printed to stdout as JSON. The exit code is 0 on success, 1 when the command
+fails or a check does not pass, and 2 for usage errors.";
+
+const DB_ENV: &str = "CELLFORGE_DB";
+
+const EXIT_FAILED: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+
+const VALUE_OPTIONS: &[&str] = &[
+    "approve",
+    "bins",
+    "catalog-version",
+    "db",
+    "decimal",
+    "format",
+    "manufacturer",
+    "mapping",
+    "options",
+    "output",
+    "policy",
+    "resistance-weight",
+    "search",
+    "serials",
+    "table",
+    "tables",
+    "units",
+];
+const FLAGS: &[&str] = &["dry-run", "json", "repair", "user-added", "help"];
+
+enum Failure {
+    Usage(String),
+    Error(String),
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        Failure::Error(message)
+    }
+}
+
+impl From<DatabaseError> for Failure {
+    fn from(e: DatabaseError) -> Self {
+        Failure::Error(e.to_string())
+    }
+}
+
+impl From<FilesystemError> for Failure {
+    fn from(e: FilesystemError) -> Self {
+        Failure::Error(e.to_string())
+    }
+}
+
+impl From<ExportError> for Failure {
+    fn from(e: ExportError) -> Self {
+        Failure::Error(e.to_string())
+    }
+}
+
+/// What a command produced: JSON for `--json`, text otherwise.
+struct Output {
+    json: Value,
+    text: String,
+    /// False when a check ran but did not pass
+    passed: bool,
+}
+
+impl Output {
+    fn new(value: &impl Serialize, text: String) -> Result<Self, Failure> {
+        let json = serde_json::to_value(value)
+            .map_err(|e| format!("Failed to serialize output: {}", e))?;
+        Ok(Output {
+            json,
+            text,
+            passed: true,
+        })
+    }
+}
+
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+    flags: Vec<String>,
+}
+
+impl Args {
+    fn parse(args: Vec<String>) -> Result<Self, Failure> {
+        let mut parsed = Args {
+            positional: Vec::new(),
+            options: HashMap::new(),
+            flags: Vec::new(),
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                parsed.positional.push(arg);
+                continue;
+            };
+            if VALUE_OPTIONS.contains(&name) {
+                let value = args
+                    .next()
+                    .ok_or_else(|| Failure::Usage(format!("--{} needs a value", name)))?;
+                parsed.options.insert(name.to_string(), value);
+            } else if FLAGS.contains(&name) {
+                parsed.flags.push(name.to_string());
+            } else {
+                return Err(Failure::Usage(format!("Unknown option {}", arg)));
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|f| f == name)
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    /// The `index`th positional argument, counting the command itself.
+    fn arg(&self, index: usize, name: &str) -> Result<&str, Failure> {
+        self.positional
+            .get(index)
+            .map(String::as_str)
+            .ok_or_else(|| Failure::Usage(format!("Missing {}", name)))
+    }
+}
+
+/// Runs the command line `args` (without the program name) and returns the
+/// process exit code.
+pub fn run(args: Vec<String>) -> i32 {
+    let args = match Args::parse(args) {
+        Ok(args) => args,
+        Err(failure) => return report_failure(failure),
+    };
+    if args.flag("help") || args.positional.is_empty() {
+        println!("{}", USAGE);
+        return 0;
+    }
+
+    let cli = Cli {
+        filesystem: Filesystem::headless(),
+        db_path: args
+            .option("db")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os(DB_ENV).map(PathBuf::from)),
+    };
+
+    match cli.execute(&args) {
+        Ok(output) => {
+            if args.flag("json") {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&output.json).unwrap_or_default()
+                );
+            } else if !output.text.is_empty() {
+                println!("{}", output.text.trim_end());
+            }
+            if output.passed {
+                0
+            } else {
+                EXIT_FAILED
+            }
+        }
+        Err(failure) => report_failure(failure),
+    }
+}
+
+fn report_failure(failure: Failure) -> i32 {
+    match failure {
+        Failure::Usage(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            EXIT_USAGE
+        }
+        Failure::Error(message) => {
+            eprintln!("error: {}", message);
+            EXIT_FAILED
+        }
+    }
+}
+
+struct Cli {
+    filesystem: Filesystem,
+    db_path: Option<PathBuf>,
+}
+
+impl Cli {
+    fn execute(&self, args: &Args) -> Result<Output, Failure> {
+        match args.arg(0, "command")? {
+            "info" => self.info(args),
+            "convert" => self.convert(args),
+            "validate" => self.validate(args),
+            "analyze" => self.analyze(args),
+            "drc" => self.drc(args),
+            "bom" => self.bom(args),
+            "match" => self.match_cells(args),
+            "export" => self.export(args),
+            "library" => match args.arg(1, "library command")? {
+                "import" => self.library_import(args),
+                "export" => self.library_export(args),
+                "sync" => self.library_sync(args),
+                "import-cells" => self.library_import_cells(args),
+                "samples" => match args.arg(2, "samples command")? {
+                    "import" => self.samples_import(args),
+                    "stats" => self.samples_stats(args),
+                    other => Err(Failure::Usage(format!("Unknown samples command {}", other))),
+                },
+                kind => self.library_list(kind, args),
+            },
+            command => Err(Failure::Usage(format!("Unknown command {}", command))),
+        }
+    }
+
+    /// Opens the library database, which must already exist unless `create`.
+    fn database(&self, create: bool) -> Result<Database, Failure> {
+        let path = self.db_path.as_ref().ok_or_else(|| {
+            Failure::Usage(format!("No library database; pass --db or set {}", DB_ENV))
+        })?;
+        if !create && !path.exists() {
+            return Err(Failure::Error(format!(
+                "Library database {} does not exist",
+                path.display()
+            )));
+        }
+
+        let db_error = |e: rusqlite::Error| format!("Failed to open library database: {}", e);
+        let db = Database::open(path).map_err(db_error)?;
+        db.init_schema().map_err(db_error)?;
+        Ok(db)
+    }
+
+    fn load(&self, path: &str) -> Result<ProjectFile, Failure> {
+        Ok(self.filesystem.load_project(Path::new(path))?)
+    }
+
+    /// Loads a project and maps its library keys onto this library's ids.
+    fn load_resolved(&self, path: &str, db: &Database) -> Result<ProjectFile, Failure> {
+        let mut project = self.load(path)?;
+        LibraryKeys::load(db)
+            .map_err(|e| format!("Failed to load library keys: {}", e))?
+            .resolve(&mut project);
+        Ok(project)
+    }
+
+    fn pack_library(&self, db: &Database) -> Result<PackLibrary, Failure> {
+        Ok(
+            PackLibrary::load(db)
+                .map_err(|e| format!("Failed to load component library: {}", e))?,
+        )
+    }
+
+    fn info(&self, args: &Args) -> Result<Output, Failure> {
+        let path = args.arg(1, "project")?;
+        let project = self.load(path)?;
+        let format = self
+            .filesystem
+            .detect_format(Path::new(path))
+            .unwrap_or_default();
+
+        let info = json!({
+            "path": path,
+            "format": format,
+            "version": project.version,
+            "name": project.metadata.name,
+            "author": project.metadata.author,
+            "created": project.metadata.created,
+            "modified": project.metadata.modified,
+            "cells": project.scene.cells.len(),
+            "connections": project.scene.connections.len(),
+            "components": project.scene.components.len(),
+            "groups": project.scene.groups.len(),
+        });
+        let text = format!(
+            "{} (version {}, {})\nAuthor:      {}\nModified:    {}\nCells:       {}\nConnections: {}\nComponents:  {}\nGroups:      {}",
+            project.metadata.name,
+            project.version,
+            info["format"].as_str().unwrap_or_default(),
+            project.metadata.author.as_deref().unwrap_or("-"),
+            project.metadata.modified,
+            project.scene.cells.len(),
+            project.scene.connections.len(),
+            project.scene.components.len(),
+            project.scene.groups.len(),
+        );
+        Output::new(&info, text)
+    }
+
+    fn convert(&self, args: &Args) -> Result<Output, Failure> {
+        let input = args.arg(1, "input project")?;
+        let output = args.arg(2, "output project")?;
+        let format = match args.option("format") {
+            Some(name) => parse_save_format(name)?,
+            None => SaveFormat::default(),
+        };
+
+        let project = self.load(input)?;
+        self.filesystem
+            .save_project(&project, Path::new(output), format)?;
+
+        let result = json!({ "input": input, "output": output, "format": format });
+        let text = format!("Wrote {}", output);
+        Output::new(&result, text)
+    }
+
+    fn validate(&self, args: &Args) -> Result<Output, Failure> {
+        let path = args.arg(1, "project")?;
+        let repair = args.flag("repair");
+        let db = self.database(false)?;
+        let mut project = self.load_resolved(path, &db)?;
+        let library =
+            LibraryIds::load(&db).map_err(|e| format!("Failed to load library ids: {}", e))?;
+
+        let report = validation::validate(&mut project, &library, repair);
+        if let Some(output) = args.option("output") {
+            let output = Path::new(output);
+            let format = self
+                .filesystem
+                .detect_format(Path::new(path))
+                .unwrap_or_default();
+            LibraryKeys::load(&db)
+                .map_err(|e| format!("Failed to load library keys: {}", e))?
+                .stamp(&mut project);
+            self.filesystem.save_project(&project, output, format)?;
+        }
+
+        let text = report
+            .issues
+            .iter()
+            .map(|issue| {
+                let status = if issue.repaired { "repaired" } else { "issue" };
+                format!("{}: {} ({})", status, issue.message, issue.path)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut output = Output::new(
+            &report,
+            if text.is_empty() {
+                "No issues".to_string()
+            } else {
+                text
+            },
+        )?;
+        output.passed = report.issues.iter().all(|issue| issue.repaired);
+        Ok(output)
+    }
+
+    fn analyze(&self, args: &Args) -> Result<Output, Failure> {
+        let db = self.database(false)?;
+        let project = self.load_resolved(args.arg(1, "project")?, &db)?;
+        let analysis = analysis::analyze(&project, &self.pack_library(&db)?);
+
+        let text = format!(
+            "Configuration:    {} ({} cells{})\nNominal voltage:  {:.2} V ({:.2}-{:.2} V)\nCapacity:         {:.2} Ah\nEnergy:           {:.1} Wh\nMax discharge:    {:.1} A\nMax charge:       {:.1} A\nResistance:       {}\nCell weight:      {:.0} g",
+            analysis.configuration,
+            analysis.cell_count,
+            analysis.chemistry.as_ref().map(|c| format!(", {}", c)).unwrap_or_default(),
+            analysis.nominal_voltage,
+            analysis.min_voltage,
+            analysis.max_voltage,
+            analysis.capacity_ah,
+            analysis.energy_wh,
+            analysis.max_discharge_a,
+            analysis.max_charge_a,
+            analysis
+                .internal_resistance_mohm
+                .map(|r| format!("{:.1} mOhm", r))
+                .unwrap_or_else(|| "-".to_string()),
+            analysis.cell_weight_g,
+        );
+        Output::new(&analysis, text)
+    }
+
+    fn drc(&self, args: &Args) -> Result<Output, Failure> {
+        let db = self.database(false)?;
+        let project = self.load_resolved(args.arg(1, "project")?, &db)?;
+        let report = analysis::check_design_rules(&project, &self.pack_library(&db)?);
+
+        let mut lines: Vec<String> = report
+            .issues
+            .iter()
+            .map(|issue| {
+                let severity = serde_json::to_value(issue.severity).unwrap_or_default();
+                let rule = serde_json::to_value(issue.rule).unwrap_or_default();
+                format!(
+                    "{} [{}] {}",
+                    severity.as_str().unwrap_or_default(),
+                    rule.as_str().unwrap_or_default(),
+                    issue.message
+                )
+            })
+            .collect();
+        lines.push(format!(
+            "{}: {} error(s), {} warning(s)",
+            if report.passed { "Passed" } else { "Failed" },
+            report.errors,
+            report.warnings
+        ));
+
+        let mut output = Output::new(&report, lines.join("\n"))?;
+        output.passed = report.passed;
+        Ok(output)
+    }
+
+    fn bom(&self, args: &Args) -> Result<Output, Failure> {
+        let db = self.database(false)?;
+        let project = self.load_resolved(args.arg(1, "project")?, &db)?;
+        let bom = analysis::bill_of_materials(&project, &self.pack_library(&db)?);
+        for warning in &bom.warnings {
+            eprintln!("warning: {}", warning);
+        }
+
+        let Some(path) = args.option("output") else {
+            return Output::new(&bom, bom.to_csv());
+        };
+        let data = if has_extension(path, "csv") {
+            bom.to_csv().into_bytes()
+        } else {
+            serde_json::to_vec_pretty(&bom)
+                .map_err(|e| format!("Failed to serialize BOM: {}", e))?
+        };
+        self.filesystem.write_atomic(&data, Path::new(path))?;
+
+        let result = json!({ "path": path, "lines": bom.lines.len(), "warnings": bom.warnings });
+        Output::new(
+            &result,
+            format!("Wrote {} ({} lines)", path, bom.lines.len()),
+        )
+    }
+
+    fn match_cells(&self, args: &Args) -> Result<Output, Failure> {
+        let path = args.arg(1, "project")?;
+        let mut options = MatchOptions::default();
+        if let Some(serials) = args.option("serials") {
+            options.serials = Some(serials.split(',').map(|s| s.trim().to_string()).collect());
+        }
+        if let Some(weight) = args.option("resistance-weight") {
+            options.resistance_weight = weight
+                .parse()
+                .ok()
+                .filter(|w: &f64| *w >= 0.0)
+                .ok_or_else(|| Failure::Usage(format!("Invalid --resistance-weight {}", weight)))?;
+        }
+
+        let db = self.database(false)?;
+        let mut project = self.load_resolved(path, &db)?;
+        let samples = matching::measured_samples(&db, &project)
+            .map_err(|e| format!("Failed to read cell samples: {}", e))?;
+        let matching = matching::match_cells(&mut project, &samples, &options)?;
+
+        if let Some(output) = args.option("output") {
+            let format = self
+                .filesystem
+                .detect_format(Path::new(path))
+                .unwrap_or_default();
+            LibraryKeys::load(&db)
+                .map_err(|e| format!("Failed to load library keys: {}", e))?
+                .stamp(&mut project);
+            self.filesystem
+                .save_project(&project, Path::new(output), format)?;
+        }
+        if let Some(table) = args.option("table") {
+            let data = if has_extension(table, "csv") {
+                matching.to_csv().into_bytes()
+            } else {
+                serde_json::to_vec_pretty(&matching)
+                    .map_err(|e| format!("Failed to serialize placement table: {}", e))?
+            };
+            self.filesystem.write_atomic(&data, Path::new(table))?;
+        }
+
+        let mut lines = vec![format!(
+            "{:>3}  {:<12} {:>4}  {:<16} {:>10} {:>9}",
+            "S", "Group", "Slot", "Serial", "mAh", "mOhm"
+        )];
+        for p in &matching.placements {
+            lines.push(format!(
+                "{:>3}  {:<12} {:>4}  {:<16} {:>10.0} {:>9}",
+                p.series.map(|s| s.to_string()).unwrap_or_else(|| "-".to_string()),
+                p.group,
+                p.slot,
+                p.serial,
+                p.capacity_mah,
+                p.internal_res_mohm
+                    .map(|r| format!("{:.2}", r))
+                    .unwrap_or_else(|| "-".to_string())
+            ));
+        }
+        lines.push(String::new());
+        for g in &matching.groups {
+            lines.push(format!(
+                "{:<12} {} cells, {:.0} mAh, {}",
+                g.group,
+                g.cells,
+                g.capacity_mah,
+                g.internal_res_mohm
+                    .map(|r| format!("{:.3} mOhm", r))
+                    .unwrap_or_else(|| "resistance not measured".to_string())
+            ));
+        }
+        lines.push(format!(
+            "Group capacity spread {:.0} mAh ({:.2}%){}",
+            matching.capacity_spread_mah,
+            matching.capacity_spread_pct,
+            matching
+                .resistance_spread_mohm
+                .map(|r| format!(", resistance spread {:.3} mOhm", r))
+                .unwrap_or_default()
+        ));
+        if !matching.unused.is_empty() {
+            lines.push(format!("Unused: {}", matching.unused.join(", ")));
+        }
+        if !matching.ungrouped.is_empty() {
+            lines.push(format!("{} cells in no group were left unlabelled", matching.ungrouped.len()));
+        }
+        Output::new(&matching, lines.join("\n"))
+    }
+
+    fn export(&self, args: &Args) -> Result<Output, Failure> {
+        let db = self.database(false)?;
+        let project = self.load_resolved(args.arg(1, "project")?, &db)?;
+        let path = args.arg(2, "output file")?;
+        let format = export_format(path, args.option("format"), args.option("options"))?;
+
+        let library = ExportLibrary::new(
+            db.get_cells(None)
+                .map_err(|e| format!("Failed to load component library: {}", e))?,
+            db.get_bms()
+                .map_err(|e| format!("Failed to load component library: {}", e))?,
+        );
+        let result = Exporter::new().export_to_file(
+            &self.filesystem,
+            &project,
+            &library,
+            &format,
+            path,
+            &(),
+        )?;
+        for warning in &result.warnings {
+            eprintln!("warning: {}", warning);
+        }
+
+        let text = format!("Wrote {} ({} bytes)", result.path, result.file_size);
+        Output::new(&result, text)
+    }
+
+    fn library_list(&self, kind: &str, args: &Args) -> Result<Output, Failure> {
+        let db = self.database(false)?;
+        let db_error = |e: rusqlite::Error| format!("Failed to query library: {}", e);
+
+        match kind {
+            "cells" => {
+                let cells = db.get_cells(args.option("search")).map_err(db_error)?;
+                let text = cells
+                    .iter()
+                    .map(|c| {
+                        format!(
+                            "{:>5}  {} {} ({} {}, {} mAh, {} A)",
+                            c.id,
+                            c.manufacturer,
+                            c.model,
+                            c.form_factor,
+                            c.chemistry,
+                            c.capacity_mah,
+                            c.max_discharge_a
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                Output::new(&cells, text)
+            }
+            "bms" => {
+                let bms = db.get_bms().map_err(db_error)?;
+                let text = bms
+                    .iter()
+                    .map(|b| {
+                        format!(
+                            "{:>5}  {} {} ({}S, {} A)",
+                            b.id, b.manufacturer, b.model, b.series_count, b.max_current_a
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                Output::new(&bms, text)
+            }
+            "materials" => {
+                let materials = db.get_materials().map_err(db_error)?;
+                let text = materials
+                    .iter()
+                    .map(|m| {
+                        format!(
+                            "{:>5}  {} ({}, {} A)",
+                            m.id, m.name, m.material_type, m.max_current_a
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                Output::new(&materials, text)
+            }
+            "shapes" => {
+                let shapes = db.get_shapes().map_err(db_error)?;
+                let text = shapes
+                    .iter()
+                    .map(|s| format!("{:>5}  {} ({}) {}", s.id, s.name, s.category, s.file_path))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                Output::new(&shapes, text)
+            }
+            other => Err(Failure::Usage(format!("Unknown library command {}", other))),
+        }
+    }
+
+    fn library_import(&self, args: &Args) -> Result<Output, Failure> {
+        args.arg(2, "library file")?;
+        let paths: Vec<PathBuf> = args.positional[2..].iter().map(PathBuf::from).collect();
+        let library = library_file::read(&paths)?;
+
+        let db = self.database(true)?;
+        let mut report = BundleImportReport::default();
+        bundle::import_library(&library, &db, &HashMap::new(), &mut report)?;
+
+        let text = format!(
+            "Added {}, already present {}",
+            report.added.len(),
+            report.matched.len()
+        );
+        Output::new(&report, text)
+    }
+
+    fn library_export(&self, args: &Args) -> Result<Output, Failure> {
+        let path = args.arg(2, "output file")?;
+        let mut options = LibraryExportOptions::default();
+        if let Some(format) = args.option("format") {
+            options.format = Some(parse_option("format", format)?);
+        }
+        if let Some(tables) = args.option("tables") {
+            options.tables = tables
+                .split(',')
+                .map(|table| parse_option("tables", table.trim()))
+                .collect::<Result<_, _>>()?;
+        }
+        options.filter.user_added_only = args.flag("user-added");
+        options.filter.search = args.option("search").map(str::to_string);
+        options.filter.manufacturer = args.option("manufacturer").map(str::to_string);
+        options.catalog_version = args.option("catalog-version").map(str::to_string);
+
+        let db = self.database(false)?;
+        let result = library_file::export(&self.filesystem, &db, Path::new(path), &options)?;
+
+        let text = format!(
+            "Wrote {} ({} cells, {} BMS, {} materials, {} shapes)",
+            result.paths.join(", "),
+            result.cells,
+            result.bms,
+            result.materials,
+            result.shapes
+        );
+        Output::new(&result, text)
+    }
+
+    fn library_sync(&self, args: &Args) -> Result<Output, Failure> {
+        let catalog = Catalog::fetch(args.arg(2, "catalog")?)?;
+        let Some(approve) = args.option("approve") else {
+            let db = self.database(false)?;
+            let changeset = catalog::review(&db, &catalog).map_err(FilesystemError::from)?;
+
+            let mut lines: Vec<String> = changeset
+                .changes
+                .iter()
+                .map(|change| {
+                    let fields = change
+                        .changes
+                        .iter()
+                        .map(|c| format!("{} {} -> {}", c.field, c.current, c.imported))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let kept = if change.protected { " (user-added, kept)" } else { "" };
+                    format!("{:<9} {}{}  {}", format!("{:?}", change.kind), change.id, kept, fields)
+                })
+                .collect();
+            lines.push(format!(
+                "Catalog {} (library at {}): {} changes, {} unchanged",
+                changeset.version,
+                changeset.local_version.as_deref().unwrap_or("no catalog"),
+                changeset.changes.len(),
+                changeset.unchanged
+            ));
+            return Output::new(&changeset, lines.join("\n"));
+        };
+
+        let db = self.database(true)?;
+        let approved: Vec<String> = if approve == "all" {
+            catalog::review(&db, &catalog)
+                .map_err(FilesystemError::from)?
+                .changes
+                .into_iter()
+                .map(|change| change.id)
+                .collect()
+        } else {
+            approve.split(',').map(|id| id.trim().to_string()).collect()
+        };
+        let result = catalog::apply(&db, &catalog, &approved).map_err(FilesystemError::from)?;
+
+        let text = format!(
+            "Synced to catalog {}: {} added, {} updated, {} deprecated, {} skipped",
+            result.version, result.added, result.updated, result.deprecated, result.skipped
+        );
+        Output::new(&result, text)
+    }
+
+    fn library_import_cells(&self, args: &Args) -> Result<Output, Failure> {
+        let path = args.arg(2, "cell file")?;
+        let mut options = CellImportOptions::default();
+        if let Some(format) = args.option("format") {
+            options.format = Some(parse_option("format", format)?);
+        }
+        if let Some(policy) = args.option("policy") {
+            options.policy = parse_option("policy", policy)?;
+        }
+        if let Some(mapping) = args.option("mapping") {
+            options.mapping = serde_json::from_str(mapping)
+                .map_err(|e| Failure::Usage(format!("Invalid --mapping: {}", e)))?;
+        }
+        if let Some(units) = args.option("units") {
+            options.units = serde_json::from_str(units)
+                .map_err(|e| Failure::Usage(format!("Invalid --units: {}", e)))?;
+        }
+
+        let dry_run = args.flag("dry-run");
+        let db = self.database(!dry_run)?;
+        let report = cell_import::import_cells(Path::new(path), &options, &db, !dry_run)?;
+
+        let mut lines: Vec<String> = report
+            .rows
+            .iter()
+            .filter(|row| row.action != ImportAction::Add)
+            .map(|row| {
+                let detail = match row.action {
+                    ImportAction::Reject => row.errors.join("; "),
+                    _ => row
+                        .changes
+                        .iter()
+                        .map(|c| format!("{} {} -> {}", c.field, c.current, c.imported))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                };
+                format!("row {:>4}  {:?}  {}  {}", row.row, row.action, row.name, detail)
+            })
+            .collect();
+        lines.push(format!(
+            "{}{} added, {} updated, {} skipped, {} rejected",
+            if dry_run { "Would import: " } else { "" },
+            report.added,
+            report.updated,
+            report.skipped,
+            report.rejected
+        ));
+        let mut output = Output::new(&report, lines.join("\n"))?;
+        output.passed = report.rejected == 0;
+        Ok(output)
+    }
+
+    fn samples_import(&self, args: &Args) -> Result<Output, Failure> {
+        let cell_id = parse_id(args.arg(3, "cell id")?)?;
+        let path = args.arg(4, "tester log")?;
+        let mut options = SampleImportOptions::default();
+        if let Some(mapping) = args.option("mapping") {
+            options.mapping = serde_json::from_str(mapping)
+                .map_err(|e| Failure::Usage(format!("Invalid --mapping: {}", e)))?;
+        }
+        if let Some(units) = args.option("units") {
+            options.units = serde_json::from_str(units)
+                .map_err(|e| Failure::Usage(format!("Invalid --units: {}", e)))?;
+        }
+        if let Some(decimal) = args.option("decimal") {
+            let mut chars = decimal.chars();
+            options.decimal = match (chars.next(), chars.next()) {
+                (Some(c @ ('.' | ',')), None) => Some(c),
+                _ => return Err(Failure::Usage(format!("Invalid --decimal: {}", decimal))),
+            };
+        }
+
+        let db = self.database(false)?;
+        let report = cell_samples::import_samples(&db, cell_id, Path::new(path), &options)?;
+
+        let mut lines: Vec<String> = report
+            .rejected
+            .iter()
+            .map(|r| format!("row {:>4}  {}  {}", r.row, r.serial, r.errors.join("; ")))
+            .collect();
+        lines.push(format!(
+            "{} added, {} updated, {} rejected",
+            report.added,
+            report.updated,
+            report.rejected.len()
+        ));
+        let mut output = Output::new(&report, lines.join("\n"))?;
+        output.passed = report.rejected.is_empty();
+        Ok(output)
+    }
+
+    fn samples_stats(&self, args: &Args) -> Result<Output, Failure> {
+        let cell_id = parse_id(args.arg(3, "cell id")?)?;
+        let bins = match args.option("bins") {
+            Some(bins) => Some(
+                bins.parse::<usize>()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| Failure::Usage(format!("Invalid --bins {}", bins)))?,
+            ),
+            None => None,
+        };
+
+        let db = self.database(false)?;
+        let stats = cell_samples::statistics(&db, cell_id, bins).map_err(FilesystemError::from)?;
+
+        let mut sections = vec![format!("{} samples of cell {}", stats.samples, cell_id)];
+        for (name, unit, distribution) in [
+            ("Capacity", "mAh", &stats.capacity_mah),
+            ("Internal resistance", "mOhm", &stats.internal_res_mohm),
+            ("Voltage", "V", &stats.voltage),
+        ] {
+            if let Some(distribution) = distribution {
+                sections.push(describe_distribution(name, unit, distribution));
+            }
+        }
+        Output::new(&stats, sections.join("\n\n"))
+    }
+}
+
+fn parse_save_format(name: &str) -> Result<SaveFormat, Failure> {
+    serde_json::from_value(Value::String(name.to_string()))
+        .map_err(|_| Failure::Usage(format!("Unknown project format {}", name)))
+}
+
+/// Parses a lowercase enum option such as `--policy merge`.
+fn parse_option<T: serde::de::DeserializeOwned>(name: &str, value: &str) -> Result<T, Failure> {
+    serde_json::from_value(Value::String(value.to_lowercase()))
+        .map_err(|_| Failure::Usage(format!("Unknown --{} {}", name, value)))
+}
+
+fn parse_id(value: &str) -> Result<i64, Failure> {
+    value
+        .parse()
+        .map_err(|_| Failure::Usage(format!("Invalid id {}", value)))
+}
+
+const HISTOGRAM_WIDTH: usize = 40;
+
+/// Summary line and a text histogram of one measured value.
+fn describe_distribution(name: &str, unit: &str, d: &Distribution) -> String {
+    let mut lines = vec![format!(
+        "{} ({}): n={} mean={:.3} sd={:.3} min={:.3} median={:.3} max={:.3}",
+        name, unit, d.count, d.mean, d.std_dev, d.min, d.median, d.max
+    )];
+    if let (Some(rated), Some(ratio)) = (d.rated, d.mean_of_rated) {
+        lines.push(format!("  rated {} {}, mean {:.1}% of rated", rated, unit, ratio * 100.0));
+    }
+    let peak = d.histogram.iter().map(|b| b.count).max().unwrap_or(0).max(1);
+    for bin in &d.histogram {
+        let bar = "#".repeat((bin.count * HISTOGRAM_WIDTH).div_ceil(peak));
+        lines.push(format!(
+            "  {:>10.3} - {:<10.3} {:>5} {}",
+            bin.start, bin.end, bin.count, bar
+        ));
+    }
+    lines.join("\n")
+}
+
+fn has_extension(path: &str, extension: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case(extension))
+}
+
+/// Export settings for `path`: the editor's defaults for the format, with
+/// the fields in `overrides` (a JSON object) replaced.
+fn export_format(
+    path: &str,
+    format: Option<&str>,
+    overrides: Option<&str>,
+) -> Result<ExportFormat, Failure> {
+    let name = match format {
+        Some(name) => name.to_lowercase(),
+        None => Path::new(path)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .ok_or_else(|| {
+                Failure::Usage("Pass --format or give the output file an extension".to_string())
+            })?,
+    };
+    let file_name = Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "export".to_string());
+
+    let selection = json!({ "mode": "all" });
+    let (format, mut options) = match name.as_str() {
+        "stl" => (
+            "stl",
+            json!({ "selection": selection, "merge_geometries": false, "apply_transforms": true, "scale": 1.0 }),
+        ),
+        "3mf" => (
+            "3mf",
+            json!({
+                "selection": selection,
+                "include_colors": true,
+                "include_materials": true,
+                "separate_objects": true,
+                "build_plate_origin": false,
+            }),
+        ),
+        "gltf" | "glb" => (
+            "gltf",
+            json!({ "selection": selection, "binary": name == "glb", "include_connections": true, "segments": 32 }),
+        ),
+        "step" | "stp" => (
+            "step",
+            json!({ "selection": selection, "group_assemblies": true }),
+        ),
+        "obj" => (
+            "obj",
+            json!({
+                "selection": selection,
+                "merge_geometries": false,
+                "apply_transforms": true,
+                "scale": 1.0,
+                "include_materials": true,
+            }),
+        ),
+        other => return Err(Failure::Usage(format!("Unknown export format {}", other))),
+    };
+    options["file_name"] = Value::String(file_name);
+
+    if let Some(overrides) = overrides {
+        let overrides: Value = serde_json::from_str(overrides)
+            .map_err(|e| Failure::Usage(format!("Invalid --options: {}", e)))?;
+        let Value::Object(fields) = overrides else {
+            return Err(Failure::Usage(
+                "--options must be a JSON object".to_string(),
+            ));
+        };
+        for (key, value) in fields {
+            options[key] = value;
+        }
+    }
+
+    serde_json::from_value(json!({ "format": format, "options": options }))
+        .map_err(|e| Failure::Usage(format!("Invalid export options: {}", e)))
+}
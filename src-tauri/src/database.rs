@@ -1,7 +1,9 @@
-use rusqlite::{Connection, Result};
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, OptionalExtension, Result, Transaction};
 use serde::{Deserialize, Serialize};
+use std::ops::Deref;
 use std::path::Path;
-use tauri::{AppHandle, Manager};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Cell {
@@ -25,6 +27,13 @@ pub struct Cell {
     pub datasheet_url: Option<String>,
     pub thermal_limit_c: Option<f64>,
     pub cycle_life: Option<i32>,
+    /// Added on this install, e.g. imported, rather than shipped with the
+    /// library
+    #[serde(default)]
+    pub user_added: bool,
+    /// Withdrawn from the catalog; kept so existing projects still resolve
+    #[serde(default)]
+    pub deprecated: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -39,6 +48,13 @@ pub struct Bms {
     pub width_mm: f64,
     pub height_mm: f64,
     pub pinout_json: Option<String>,
+    /// Added on this install, e.g. imported, rather than shipped with the
+    /// library
+    #[serde(default)]
+    pub user_added: bool,
+    /// Withdrawn from the catalog; kept so existing projects still resolve
+    #[serde(default)]
+    pub deprecated: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -50,6 +66,13 @@ pub struct Material {
     pub width_mm: Option<f64>,
     pub resistance_mohm_per_m: f64,
     pub max_current_a: f64,
+    /// Added on this install, e.g. imported, rather than shipped with the
+    /// library
+    #[serde(default)]
+    pub user_added: bool,
+    /// Withdrawn from the catalog; kept so existing projects still resolve
+    #[serde(default)]
+    pub deprecated: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -59,47 +82,154 @@ pub struct Shape {
     pub category: String,
     pub file_path: String,
     pub default_scale: String,
+    /// Added on this install, e.g. imported, rather than shipped with the
+    /// library
+    #[serde(default)]
+    pub user_added: bool,
+    /// Withdrawn from the catalog; kept so existing projects still resolve
+    #[serde(default)]
+    pub deprecated: bool,
+}
+
+/// One measured physical cell of a library cell model, e.g. from incoming
+/// inspection.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CellSample {
+    pub id: i64,
+    pub cell_id: i64,
+    pub serial: String,
+    pub capacity_mah: Option<f64>,
+    pub internal_res_mohm: Option<f64>,
+    pub voltage: Option<f64>,
+    /// ISO 8601 date or date and time of the measurement
+    pub measured_at: Option<String>,
+}
+
+/// Builds an install-independent identifier from a row's natural key, e.g.
+/// `samsung/30q` for a cell. Case and surrounding or repeated whitespace
+/// are ignored so cosmetic edits to the library don't break projects.
+pub fn library_key(parts: &[&str]) -> String {
+    parts
+        .iter()
+        .map(|part| part.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl Cell {
+    pub fn library_key(&self) -> String {
+        library_key(&[&self.manufacturer, &self.model])
+    }
+}
+
+impl Bms {
+    pub fn library_key(&self) -> String {
+        library_key(&[&self.manufacturer, &self.model])
+    }
+}
+
+impl Material {
+    pub fn library_key(&self) -> String {
+        library_key(&[&self.name, &self.material_type])
+    }
+}
+
+impl Shape {
+    pub fn library_key(&self) -> String {
+        library_key(&[&self.name])
+    }
 }
 
 pub struct Database {
-    conn: Connection,
+    conn: Handle,
 }
 
-impl Database {
-    pub fn new(app: &AppHandle) -> Result<Self> {
-        let db_path = app
-            .path()
-            .app_data_dir()
-            .map_err(|_| rusqlite::Error::InvalidPath("App data directory not found".into()))?
-            .join("library.db");
+/// A connection of its own, or one borrowed from a `DatabasePool` and
+/// returned to it on drop.
+enum Handle {
+    Owned(Connection),
+    Pooled(PooledConnection<SqliteConnectionManager>),
+}
 
+impl Deref for Handle {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        match self {
+            Handle::Owned(conn) => conn,
+            Handle::Pooled(conn) => conn,
+        }
+    }
+}
+
+/// Settings every connection to the library needs. SQLite leaves foreign
+/// keys off by default, and with them `ON DELETE CASCADE`.
+pub(crate) fn configure(conn: &Connection) -> Result<()> {
+    conn.pragma_update(None, "foreign_keys", true)
+}
+
+impl Database {
+    /// Opens the library at `path`, first copying `bundled` there when
+    /// there is no library yet.
+    pub fn install(path: &Path, bundled: Option<&Path>) -> Result<Self> {
         // Ensure directory exists
-        if let Some(parent) = db_path.parent() {
+        if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent).map_err(|e| {
                 rusqlite::Error::InvalidPath(format!("Failed to create database directory: {}", e).into())
             })?;
         }
 
-        let conn = if db_path.exists() {
-            Connection::open(&db_path)?
-        } else {
+        if !path.exists() {
             // Copy bundled database
-            let bundled_db = app
-                .path()
-                .resource_dir()
-                .map_err(|_| rusqlite::Error::InvalidPath("Resource directory not found".into()))?
-                .join("assets/library.db");
-
-            if bundled_db.exists() {
-                std::fs::copy(&bundled_db, &db_path).map_err(|e| {
+            if let Some(bundled_db) = bundled.filter(|p| p.exists()) {
+                std::fs::copy(bundled_db, path).map_err(|e| {
                     rusqlite::Error::InvalidPath(format!("Failed to copy bundled database: {}", e).into())
                 })?;
             }
+        }
 
-            Connection::open(&db_path)?
-        };
+        Self::open(path)
+    }
+
+    /// Opens the library database at `path`, creating an empty one if it
+    /// doesn't exist. Call `init_schema` before querying a new file.
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        configure(&conn)?;
+        Ok(Database::from_connection(conn))
+    }
 
-        Ok(Database { conn })
+    /// Library that lives only as long as this value, for tests and
+    /// throwaway tooling. Call `init_schema` before use.
+    pub fn open_in_memory() -> Result<Self> {
+        let conn = Connection::open_in_memory()?;
+        configure(&conn)?;
+        Ok(Database::from_connection(conn))
+    }
+
+    pub(crate) fn from_connection(conn: Connection) -> Self {
+        Database {
+            conn: Handle::Owned(conn),
+        }
+    }
+
+    pub(crate) fn from_pooled(conn: PooledConnection<SqliteConnectionManager>) -> Self {
+        Database {
+            conn: Handle::Pooled(conn),
+        }
+    }
+
+    /// Starts a transaction; it rolls back when dropped, including while
+    /// unwinding from a panic, unless committed.
+    pub fn transaction(&self) -> Result<Transaction<'_>> {
+        self.conn.unchecked_transaction()
+    }
+
+    /// Rolls back a transaction left open on this connection, if any.
+    pub(crate) fn rollback_open_transaction(&self) {
+        if !self.conn.is_autocommit() {
+            let _ = self.conn.execute_batch("ROLLBACK");
+        }
     }
 
     pub fn init_schema(&self) -> Result<()> {
@@ -126,6 +256,8 @@ impl Database {
                 datasheet_url TEXT,
                 thermal_limit_c REAL DEFAULT 60,
                 cycle_life INTEGER,
+                user_added INTEGER NOT NULL DEFAULT 0,
+                deprecated INTEGER NOT NULL DEFAULT 0,
                 UNIQUE(manufacturer, model)
             )",
             [],
@@ -144,6 +276,8 @@ impl Database {
                 width_mm REAL NOT NULL,
                 height_mm REAL NOT NULL,
                 pinout_json TEXT,
+                user_added INTEGER NOT NULL DEFAULT 0,
+                deprecated INTEGER NOT NULL DEFAULT 0,
                 UNIQUE(manufacturer, model)
             )",
             [],
@@ -159,6 +293,8 @@ impl Database {
                 width_mm REAL,
                 resistance_mohm_per_m REAL NOT NULL,
                 max_current_a REAL NOT NULL,
+                user_added INTEGER NOT NULL DEFAULT 0,
+                deprecated INTEGER NOT NULL DEFAULT 0,
                 UNIQUE(name, type)
             )",
             [],
@@ -172,11 +308,44 @@ impl Database {
                 category TEXT NOT NULL CHECK(category IN ('enclosure','bracket','spacer','vent','terminal')),
                 file_path TEXT NOT NULL,
                 default_scale TEXT DEFAULT '1,1,1',
+                user_added INTEGER NOT NULL DEFAULT 0,
+                deprecated INTEGER NOT NULL DEFAULT 0,
                 UNIQUE(name)
             )",
             [],
         )?;
 
+        // Libraries created before a column existed get it added
+        for table in ["cells", "bms", "materials", "shapes"] {
+            self.add_column_if_missing(table, "user_added", "INTEGER NOT NULL DEFAULT 0")?;
+            self.add_column_if_missing(table, "deprecated", "INTEGER NOT NULL DEFAULT 0")?;
+        }
+
+        // Measured samples, see `cell_samples`. A serial is measured once
+        // per cell model; measuring it again replaces the values.
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS cell_samples (
+                id INTEGER PRIMARY KEY,
+                cell_id INTEGER NOT NULL REFERENCES cells(id) ON DELETE CASCADE,
+                serial TEXT NOT NULL,
+                capacity_mah REAL,
+                internal_res_mohm REAL,
+                voltage REAL,
+                measured_at TEXT,
+                UNIQUE(cell_id, serial)
+            )",
+            [],
+        )?;
+
+        // Which catalog the library was last synced from, see `catalog`
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS library_meta (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )",
+            [],
+        )?;
+
         // Create FTS table for cells
         self.conn.execute(
             "CREATE VIRTUAL TABLE IF NOT EXISTS cells_fts USING fts5(
@@ -187,6 +356,25 @@ impl Database {
             [],
         )?;
 
+        // cells_fts is an external-content table: rows are removed with the
+        // special 'delete' command and the values they were indexed with.
+        // Earlier versions updated and deleted its rows in place, which
+        // corrupts the index; those triggers are replaced and the index
+        // rebuilt from the cells table.
+        let stale_triggers: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM sqlite_master
+             WHERE type = 'trigger' AND name IN ('cells_fts_update', 'cells_fts_delete')
+               AND sql NOT LIKE '%''delete''%'",
+            [],
+            |row| row.get(0),
+        )?;
+        if stale_triggers > 0 {
+            self.conn.execute_batch(
+                "DROP TRIGGER IF EXISTS cells_fts_update;
+                 DROP TRIGGER IF EXISTS cells_fts_delete;",
+            )?;
+        }
+
         // Create triggers to keep FTS table in sync
         self.conn.execute(
             "CREATE TRIGGER IF NOT EXISTS cells_fts_insert AFTER INSERT ON cells
@@ -200,21 +388,45 @@ impl Database {
         self.conn.execute(
             "CREATE TRIGGER IF NOT EXISTS cells_fts_delete AFTER DELETE ON cells
              BEGIN
-               DELETE FROM cells_fts WHERE rowid = old.id;
+               INSERT INTO cells_fts(cells_fts, rowid, manufacturer, model, form_factor, chemistry)
+               VALUES ('delete', old.id, old.manufacturer, old.model, old.form_factor, old.chemistry);
              END",
             [],
         )?;
 
         self.conn.execute(
-            "CREATE TRIGGER IF NOT EXISTS cells_fts_update AFTER UPDATE ON cells
+            "CREATE TRIGGER IF NOT EXISTS cells_fts_update
+             AFTER UPDATE OF manufacturer, model, form_factor, chemistry ON cells
              BEGIN
-               UPDATE cells_fts SET manufacturer = new.manufacturer, model = new.model,
-                                   form_factor = new.form_factor, chemistry = new.chemistry
-               WHERE rowid = new.id;
+               INSERT INTO cells_fts(cells_fts, rowid, manufacturer, model, form_factor, chemistry)
+               VALUES ('delete', old.id, old.manufacturer, old.model, old.form_factor, old.chemistry);
+               INSERT INTO cells_fts(rowid, manufacturer, model, form_factor, chemistry)
+               VALUES (new.id, new.manufacturer, new.model, new.form_factor, new.chemistry);
              END",
             [],
         )?;
 
+        if stale_triggers > 0 {
+            self.conn
+                .execute("INSERT INTO cells_fts(cells_fts) VALUES ('rebuild')", [])?;
+        }
+
+        Ok(())
+    }
+
+    fn add_column_if_missing(&self, table: &str, column: &str, definition: &str) -> Result<()> {
+        let mut stmt = self.conn.prepare(&format!("PRAGMA table_info({})", table))?;
+        let exists = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .collect::<Result<Vec<_>>>()?
+            .iter()
+            .any(|name| name == column);
+        if !exists {
+            self.conn.execute(
+                &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+                [],
+            )?;
+        }
         Ok(())
     }
 
@@ -235,6 +447,22 @@ impl Database {
         Ok(())
     }
 
+    /// A value from `library_meta`, e.g. the synced catalog version.
+    pub fn get_meta(&self, key: &str) -> Result<Option<String>> {
+        let mut stmt = self.conn.prepare("SELECT value FROM library_meta WHERE key = ?")?;
+        let mut rows = stmt.query_map([key], |row| row.get(0))?;
+        rows.next().transpose()
+    }
+
+    pub fn set_meta(&self, key: &str, value: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO library_meta (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            [key, value],
+        )?;
+        Ok(())
+    }
+
     pub fn get_cells(&self, search: Option<&str>) -> Result<Vec<Cell>> {
         let mut cells = Vec::new();
 
@@ -281,6 +509,8 @@ impl Database {
             datasheet_url: row.get(17)?,
             thermal_limit_c: row.get(18)?,
             cycle_life: row.get(19)?,
+            user_added: row.get("user_added")?,
+            deprecated: row.get("deprecated")?,
         })
     }
 
@@ -295,6 +525,219 @@ impl Database {
         }
     }
 
+    /// Adds a cell under a fresh id and returns that id.
+    pub fn insert_cell(&self, cell: &Cell) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO cells (manufacturer, model, form_factor, chemistry, nominal_voltage,
+                max_voltage, min_voltage, capacity_mah, max_discharge_a, max_charge_a,
+                internal_res_mohm, weight_g, diameter_mm, length_mm, width_mm, height_mm,
+                datasheet_url, thermal_limit_c, cycle_life, user_added, deprecated)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)",
+            rusqlite::params![
+                cell.manufacturer,
+                cell.model,
+                cell.form_factor,
+                cell.chemistry,
+                cell.nominal_voltage,
+                cell.max_voltage,
+                cell.min_voltage,
+                cell.capacity_mah,
+                cell.max_discharge_a,
+                cell.max_charge_a,
+                cell.internal_res_mohm,
+                cell.weight_g,
+                cell.diameter_mm,
+                cell.length_mm,
+                cell.width_mm,
+                cell.height_mm,
+                cell.datasheet_url,
+                cell.thermal_limit_c,
+                cell.cycle_life,
+                cell.user_added,
+                cell.deprecated,
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Replaces every column of the cell with row id `id`; `cell.id` is
+    /// ignored. Returns whether the row existed.
+    pub fn update_cell(&self, id: i64, cell: &Cell) -> Result<bool> {
+        let changed = self.conn.execute(
+            "UPDATE cells SET manufacturer = ?2, model = ?3, form_factor = ?4, chemistry = ?5,
+                nominal_voltage = ?6, max_voltage = ?7, min_voltage = ?8, capacity_mah = ?9,
+                max_discharge_a = ?10, max_charge_a = ?11, internal_res_mohm = ?12,
+                weight_g = ?13, diameter_mm = ?14, length_mm = ?15, width_mm = ?16,
+                height_mm = ?17, datasheet_url = ?18, thermal_limit_c = ?19, cycle_life = ?20,
+                user_added = ?21, deprecated = ?22
+             WHERE id = ?1",
+            rusqlite::params![
+                id,
+                cell.manufacturer,
+                cell.model,
+                cell.form_factor,
+                cell.chemistry,
+                cell.nominal_voltage,
+                cell.max_voltage,
+                cell.min_voltage,
+                cell.capacity_mah,
+                cell.max_discharge_a,
+                cell.max_charge_a,
+                cell.internal_res_mohm,
+                cell.weight_g,
+                cell.diameter_mm,
+                cell.length_mm,
+                cell.width_mm,
+                cell.height_mm,
+                cell.datasheet_url,
+                cell.thermal_limit_c,
+                cell.cycle_life,
+                cell.user_added,
+                cell.deprecated,
+            ],
+        )?;
+        Ok(changed > 0)
+    }
+
+    pub fn get_cell_samples(&self, cell_id: i64) -> Result<Vec<CellSample>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT * FROM cell_samples WHERE cell_id = ? ORDER BY serial")?;
+        let rows = stmt.query_map([cell_id], Self::row_to_cell_sample)?;
+        rows.collect()
+    }
+
+    /// Adds the sample, or replaces the values measured earlier for the
+    /// same serial. Returns whether the serial is new.
+    pub fn upsert_cell_sample(&self, sample: &CellSample) -> Result<bool> {
+        let existing: Option<i64> = self
+            .conn
+            .query_row(
+                "SELECT id FROM cell_samples WHERE cell_id = ?1 AND serial = ?2",
+                rusqlite::params![sample.cell_id, sample.serial],
+                |row| row.get(0),
+            )
+            .optional()?;
+        self.conn.execute(
+            "INSERT INTO cell_samples (cell_id, serial, capacity_mah, internal_res_mohm, voltage, measured_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(cell_id, serial) DO UPDATE SET
+                capacity_mah = excluded.capacity_mah,
+                internal_res_mohm = excluded.internal_res_mohm,
+                voltage = excluded.voltage,
+                measured_at = excluded.measured_at",
+            rusqlite::params![
+                sample.cell_id,
+                sample.serial,
+                sample.capacity_mah,
+                sample.internal_res_mohm,
+                sample.voltage,
+                sample.measured_at,
+            ],
+        )?;
+        Ok(existing.is_none())
+    }
+
+    /// Returns whether the sample existed.
+    pub fn delete_cell_sample(&self, id: i64) -> Result<bool> {
+        Ok(self.conn.execute("DELETE FROM cell_samples WHERE id = ?", [id])? > 0)
+    }
+
+    fn row_to_cell_sample(row: &rusqlite::Row) -> Result<CellSample> {
+        Ok(CellSample {
+            id: row.get(0)?,
+            cell_id: row.get(1)?,
+            serial: row.get(2)?,
+            capacity_mah: row.get(3)?,
+            internal_res_mohm: row.get(4)?,
+            voltage: row.get(5)?,
+            measured_at: row.get(6)?,
+        })
+    }
+
+    pub fn get_bms(&self) -> Result<Vec<Bms>> {
+        let mut stmt = self.conn.prepare("SELECT * FROM bms ORDER BY manufacturer, model")?;
+        let rows = stmt.query_map([], Self::row_to_bms)?;
+
+        let mut bms = Vec::new();
+        for row in rows {
+            bms.push(row?);
+        }
+
+        Ok(bms)
+    }
+
+    pub fn get_bms_by_id(&self, id: i64) -> Result<Option<Bms>> {
+        let mut stmt = self.conn.prepare("SELECT * FROM bms WHERE id = ?")?;
+        let mut rows = stmt.query_map([id], Self::row_to_bms)?;
+        rows.next().transpose()
+    }
+
+    pub fn insert_bms(&self, bms: &Bms) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO bms (manufacturer, model, series_count, max_current_a, balance_current_ma,
+                length_mm, width_mm, height_mm, pinout_json, user_added, deprecated)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            rusqlite::params![
+                bms.manufacturer,
+                bms.model,
+                bms.series_count,
+                bms.max_current_a,
+                bms.balance_current_ma,
+                bms.length_mm,
+                bms.width_mm,
+                bms.height_mm,
+                bms.pinout_json,
+                bms.user_added,
+                bms.deprecated,
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Replaces every column of the BMS with row id `id`; `bms.id` is
+    /// ignored. Returns whether the row existed.
+    pub fn update_bms(&self, id: i64, bms: &Bms) -> Result<bool> {
+        let changed = self.conn.execute(
+            "UPDATE bms SET manufacturer = ?2, model = ?3, series_count = ?4, max_current_a = ?5,
+                balance_current_ma = ?6, length_mm = ?7, width_mm = ?8, height_mm = ?9,
+                pinout_json = ?10, user_added = ?11, deprecated = ?12
+             WHERE id = ?1",
+            rusqlite::params![
+                id,
+                bms.manufacturer,
+                bms.model,
+                bms.series_count,
+                bms.max_current_a,
+                bms.balance_current_ma,
+                bms.length_mm,
+                bms.width_mm,
+                bms.height_mm,
+                bms.pinout_json,
+                bms.user_added,
+                bms.deprecated,
+            ],
+        )?;
+        Ok(changed > 0)
+    }
+
+    fn row_to_bms(row: &rusqlite::Row) -> Result<Bms> {
+        Ok(Bms {
+            id: row.get(0)?,
+            manufacturer: row.get(1)?,
+            model: row.get(2)?,
+            series_count: row.get(3)?,
+            max_current_a: row.get(4)?,
+            balance_current_ma: row.get(5)?,
+            length_mm: row.get(6)?,
+            width_mm: row.get(7)?,
+            height_mm: row.get(8)?,
+            pinout_json: row.get(9)?,
+            user_added: row.get("user_added")?,
+            deprecated: row.get("deprecated")?,
+        })
+    }
+
     pub fn get_materials(&self) -> Result<Vec<Material>> {
         let mut stmt = self.conn.prepare("SELECT * FROM materials ORDER BY name")?;
         let rows = stmt.query_map([], Self::row_to_material)?;
@@ -307,6 +750,53 @@ impl Database {
         Ok(materials)
     }
 
+    pub fn get_material_by_id(&self, id: i64) -> Result<Option<Material>> {
+        let mut stmt = self.conn.prepare("SELECT * FROM materials WHERE id = ?")?;
+        let mut rows = stmt.query_map([id], Self::row_to_material)?;
+        rows.next().transpose()
+    }
+
+    pub fn insert_material(&self, material: &Material) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO materials (name, type, thickness_mm, width_mm, resistance_mohm_per_m, max_current_a,
+                user_added, deprecated)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            rusqlite::params![
+                material.name,
+                material.material_type,
+                material.thickness_mm,
+                material.width_mm,
+                material.resistance_mohm_per_m,
+                material.max_current_a,
+                material.user_added,
+                material.deprecated,
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Replaces every column of the material with row id `id`; `material.id`
+    /// is ignored. Returns whether the row existed.
+    pub fn update_material(&self, id: i64, material: &Material) -> Result<bool> {
+        let changed = self.conn.execute(
+            "UPDATE materials SET name = ?2, type = ?3, thickness_mm = ?4, width_mm = ?5,
+                resistance_mohm_per_m = ?6, max_current_a = ?7, user_added = ?8, deprecated = ?9
+             WHERE id = ?1",
+            rusqlite::params![
+                id,
+                material.name,
+                material.material_type,
+                material.thickness_mm,
+                material.width_mm,
+                material.resistance_mohm_per_m,
+                material.max_current_a,
+                material.user_added,
+                material.deprecated,
+            ],
+        )?;
+        Ok(changed > 0)
+    }
+
     fn row_to_material(row: &rusqlite::Row) -> Result<Material> {
         Ok(Material {
             id: row.get(0)?,
@@ -316,6 +806,8 @@ impl Database {
             width_mm: row.get(4)?,
             resistance_mohm_per_m: row.get(5)?,
             max_current_a: row.get(6)?,
+            user_added: row.get("user_added")?,
+            deprecated: row.get("deprecated")?,
         })
     }
 
@@ -331,6 +823,48 @@ impl Database {
         Ok(shapes)
     }
 
+    pub fn get_shape_by_id(&self, id: i64) -> Result<Option<Shape>> {
+        let mut stmt = self.conn.prepare("SELECT * FROM shapes WHERE id = ?")?;
+        let mut rows = stmt.query_map([id], Self::row_to_shape)?;
+        rows.next().transpose()
+    }
+
+    pub fn insert_shape(&self, shape: &Shape) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO shapes (name, category, file_path, default_scale, user_added, deprecated)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            rusqlite::params![
+                shape.name,
+                shape.category,
+                shape.file_path,
+                shape.default_scale,
+                shape.user_added,
+                shape.deprecated,
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Replaces every column of the shape with row id `id`; `shape.id` is
+    /// ignored. Returns whether the row existed.
+    pub fn update_shape(&self, id: i64, shape: &Shape) -> Result<bool> {
+        let changed = self.conn.execute(
+            "UPDATE shapes SET name = ?2, category = ?3, file_path = ?4, default_scale = ?5,
+                user_added = ?6, deprecated = ?7
+             WHERE id = ?1",
+            rusqlite::params![
+                id,
+                shape.name,
+                shape.category,
+                shape.file_path,
+                shape.default_scale,
+                shape.user_added,
+                shape.deprecated,
+            ],
+        )?;
+        Ok(changed > 0)
+    }
+
     fn row_to_shape(row: &rusqlite::Row) -> Result<Shape> {
         Ok(Shape {
             id: row.get(0)?,
@@ -338,6 +872,8 @@ impl Database {
             category: row.get(2)?,
             file_path: row.get(3)?,
             default_scale: row.get(4)?,
+            user_added: row.get("user_added")?,
+            deprecated: row.get("deprecated")?,
         })
     }
 }
\ No newline at end of file
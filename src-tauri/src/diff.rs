@@ -0,0 +1,392 @@
+use crate::error::FilesystemError;
+use crate::filesystem::ProjectFile;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+
+/// Scene sections, all keyed by uuid.
+const SECTIONS: [&str; 4] = ["cells", "connections", "components", "groups"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+/// An entity that differs between two projects. `fields` lists what
+/// changed for modified entities, e.g. `position` for a moved cell.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityChange {
+    pub uuid: String,
+    pub kind: ChangeKind,
+    pub fields: Vec<FieldChange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MembershipChange {
+    pub group: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// Differences between two projects, matched by uuid. Camera position is
+/// view state and not compared.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProjectDiff {
+    pub metadata: Vec<FieldChange>,
+    pub settings: Vec<FieldChange>,
+    pub cells: Vec<EntityChange>,
+    pub connections: Vec<EntityChange>,
+    pub components: Vec<EntityChange>,
+    pub groups: Vec<EntityChange>,
+    pub memberships: Vec<MembershipChange>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SectionSummary {
+    pub added: usize,
+    pub removed: usize,
+    pub modified: usize,
+}
+
+/// Counts of a `ProjectDiff`, for history listings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiffSummary {
+    pub cells: SectionSummary,
+    /// Modified cells whose position or rotation changed
+    pub cells_moved: usize,
+    pub connections: SectionSummary,
+    pub components: SectionSummary,
+    pub groups: SectionSummary,
+    /// Names of changed settings fields
+    pub settings: Vec<String>,
+}
+
+impl ProjectDiff {
+    pub fn summary(&self) -> DiffSummary {
+        let section = |changes: &[EntityChange]| SectionSummary {
+            added: changes.iter().filter(|c| c.kind == ChangeKind::Added).count(),
+            removed: changes.iter().filter(|c| c.kind == ChangeKind::Removed).count(),
+            modified: changes.iter().filter(|c| c.kind == ChangeKind::Modified).count(),
+        };
+        let cells_moved = self
+            .cells
+            .iter()
+            .filter(|c| c.fields.iter().any(|f| f.field == "position" || f.field == "rotation"))
+            .count();
+
+        DiffSummary {
+            cells: section(&self.cells),
+            cells_moved,
+            connections: section(&self.connections),
+            components: section(&self.components),
+            groups: section(&self.groups),
+            settings: self.settings.iter().map(|f| f.field.clone()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictKind {
+    /// Both sides changed the same field differently
+    Modified,
+    /// One side removed an entity the other changed
+    ModifyDelete,
+    /// Both sides added an entity under the same uuid with different content
+    AddAdd,
+}
+
+/// A conflict the merge settled in favour of one side; `path` is the JSON
+/// path, e.g. `scene.cells.<uuid>.position`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergeConflict {
+    pub kind: ConflictKind,
+    pub path: String,
+    pub base: Value,
+    pub ours: Value,
+    pub theirs: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergeResult {
+    pub project: ProjectFile,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+/// Compares `after` against `before`.
+pub fn diff(before: &ProjectFile, after: &ProjectFile) -> Result<ProjectDiff, FilesystemError> {
+    let before = to_value(before)?;
+    let after = to_value(after)?;
+
+    let mut diff = ProjectDiff {
+        metadata: diff_fields(object(&before, &["metadata"]), object(&after, &["metadata"])),
+        settings: diff_fields(object(&before, &["settings"]), object(&after, &["settings"])),
+        ..Default::default()
+    };
+
+    for section in SECTIONS {
+        let old = object(&before, &["scene", section]);
+        let new = object(&after, &["scene", section]);
+        let mut changes = Vec::new();
+
+        for uuid in union_keys(&[old, new]) {
+            let change = match (old.get(&uuid), new.get(&uuid)) {
+                (None, Some(_)) => (ChangeKind::Added, Vec::new()),
+                (Some(_), None) => (ChangeKind::Removed, Vec::new()),
+                (Some(old), Some(new)) if old != new => {
+                    (ChangeKind::Modified, diff_fields(as_object(old), as_object(new)))
+                }
+                _ => continue,
+            };
+
+            if section == "groups" {
+                let members = |entity: Option<&Value>| -> BTreeSet<String> {
+                    entity
+                        .and_then(|group| group.get("member_uuids"))
+                        .and_then(Value::as_array)
+                        .map(|members| members.iter().filter_map(Value::as_str).map(String::from).collect())
+                        .unwrap_or_default()
+                };
+                let (old_members, new_members) = (members(old.get(&uuid)), members(new.get(&uuid)));
+                if old_members != new_members {
+                    diff.memberships.push(MembershipChange {
+                        group: uuid.clone(),
+                        added: new_members.difference(&old_members).cloned().collect(),
+                        removed: old_members.difference(&new_members).cloned().collect(),
+                    });
+                }
+            }
+
+            changes.push(EntityChange {
+                uuid,
+                kind: change.0,
+                fields: change.1,
+            });
+        }
+
+        match section {
+            "cells" => diff.cells = changes,
+            "connections" => diff.connections = changes,
+            "components" => diff.components = changes,
+            _ => diff.groups = changes,
+        }
+    }
+
+    Ok(diff)
+}
+
+/// Merges the changes `ours` and `theirs` each made to `base`.
+///
+/// Non-overlapping changes combine field by field, and group members added
+/// or removed on either side are all applied. Where both sides changed the
+/// same thing differently, `ours` wins and a conflict is reported; an entity
+/// one side removed and the other modified is kept. The merged scene may
+/// still reference removed entities and should be validated.
+pub fn merge(base: &ProjectFile, ours: &ProjectFile, theirs: &ProjectFile) -> Result<MergeResult, FilesystemError> {
+    let base = to_value(base)?;
+    let ours = to_value(ours)?;
+    let theirs = to_value(theirs)?;
+    let mut conflicts = Vec::new();
+
+    let mut merged = ours.clone();
+
+    let mut metadata = merge_fields(
+        "metadata",
+        object(&base, &["metadata"]),
+        object(&ours, &["metadata"]),
+        object(&theirs, &["metadata"]),
+        &mut conflicts,
+    );
+    // Both sides touch the timestamp on every save; keep the later one
+    // instead of reporting it
+    conflicts.retain(|c| c.path != "metadata.modified");
+    let modified = [&ours, &theirs]
+        .iter()
+        .filter_map(|project| project.pointer("/metadata/modified").and_then(Value::as_str))
+        .max()
+        .map(|modified| Value::String(modified.to_string()));
+    if let Some(modified) = modified {
+        metadata.insert("modified".to_string(), modified);
+    }
+    merged["metadata"] = Value::Object(metadata);
+
+    merged["settings"] = Value::Object(merge_fields(
+        "settings",
+        object(&base, &["settings"]),
+        object(&ours, &["settings"]),
+        object(&theirs, &["settings"]),
+        &mut conflicts,
+    ));
+
+    for section in SECTIONS {
+        let base_map = object(&base, &["scene", section]);
+        let ours_map = object(&ours, &["scene", section]);
+        let theirs_map = object(&theirs, &["scene", section]);
+        let mut result = Map::new();
+
+        for uuid in union_keys(&[base_map, ours_map, theirs_map]) {
+            let path = format!("scene.{}.{}", section, uuid);
+            let entity = merge_entity(
+                &path,
+                base_map.get(&uuid),
+                ours_map.get(&uuid),
+                theirs_map.get(&uuid),
+                &mut conflicts,
+            );
+            if let Some(entity) = entity {
+                result.insert(uuid, entity);
+            }
+        }
+
+        merged["scene"][section] = Value::Object(result);
+    }
+
+    let project = serde_json::from_value(merged)
+        .map_err(|e| FilesystemError::Serialization(format!("Failed to build merged project: {}", e)))?;
+    Ok(MergeResult { project, conflicts })
+}
+
+fn merge_entity(
+    path: &str,
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+    conflicts: &mut Vec<MergeConflict>,
+) -> Option<Value> {
+    if ours == theirs {
+        return ours.cloned();
+    }
+    if ours == base {
+        return theirs.cloned();
+    }
+    if theirs == base {
+        return ours.cloned();
+    }
+
+    match (ours, theirs) {
+        (Some(ours), Some(theirs)) => {
+            let before = conflicts.len();
+            let base = as_object(base.unwrap_or(&Value::Null));
+            let fields = merge_fields(path, base, as_object(ours), as_object(theirs), conflicts);
+            if base.is_empty() {
+                for conflict in &mut conflicts[before..] {
+                    conflict.kind = ConflictKind::AddAdd;
+                }
+            }
+            Some(Value::Object(fields))
+        }
+        // Removed on one side, modified on the other
+        (kept, _) => {
+            conflicts.push(MergeConflict {
+                kind: ConflictKind::ModifyDelete,
+                path: path.to_string(),
+                base: base.cloned().unwrap_or(Value::Null),
+                ours: ours.cloned().unwrap_or(Value::Null),
+                theirs: theirs.cloned().unwrap_or(Value::Null),
+            });
+            kept.or(theirs).cloned()
+        }
+    }
+}
+
+fn merge_fields(
+    path: &str,
+    base: &Map<String, Value>,
+    ours: &Map<String, Value>,
+    theirs: &Map<String, Value>,
+    conflicts: &mut Vec<MergeConflict>,
+) -> Map<String, Value> {
+    let mut merged = Map::new();
+
+    for field in union_keys(&[base, ours, theirs]) {
+        let (b, o, t) = (base.get(&field), ours.get(&field), theirs.get(&field));
+        let value = if o == t || t == b {
+            o
+        } else if o == b {
+            t
+        } else if field == "member_uuids" {
+            merged.insert(field, merge_members(b, o, t));
+            continue;
+        } else {
+            conflicts.push(MergeConflict {
+                kind: ConflictKind::Modified,
+                path: format!("{}.{}", path, field),
+                base: b.cloned().unwrap_or(Value::Null),
+                ours: o.cloned().unwrap_or(Value::Null),
+                theirs: t.cloned().unwrap_or(Value::Null),
+            });
+            o.or(t)
+        };
+
+        if let Some(value) = value {
+            merged.insert(field, value.clone());
+        }
+    }
+
+    merged
+}
+
+/// Applies membership changes from both sides: members either side removed
+/// are dropped, members either side added are appended after ours.
+fn merge_members(base: Option<&Value>, ours: Option<&Value>, theirs: Option<&Value>) -> Value {
+    let list = |value: Option<&Value>| -> Vec<Value> {
+        value.and_then(Value::as_array).cloned().unwrap_or_default()
+    };
+    let (base, ours, theirs) = (list(base), list(ours), list(theirs));
+
+    let mut merged: Vec<Value> = ours
+        .iter()
+        .filter(|member| !base.contains(member) || theirs.contains(member))
+        .cloned()
+        .collect();
+    for member in theirs {
+        if !base.contains(&member) && !merged.contains(&member) {
+            merged.push(member);
+        }
+    }
+
+    Value::Array(merged)
+}
+
+fn diff_fields(before: &Map<String, Value>, after: &Map<String, Value>) -> Vec<FieldChange> {
+    union_keys(&[before, after])
+        .into_iter()
+        .filter_map(|field| {
+            let (old, new) = (before.get(&field), after.get(&field));
+            (old != new).then(|| FieldChange {
+                before: old.cloned().unwrap_or(Value::Null),
+                after: new.cloned().unwrap_or(Value::Null),
+                field,
+            })
+        })
+        .collect()
+}
+
+fn to_value(project: &ProjectFile) -> Result<Value, FilesystemError> {
+    serde_json::to_value(project)
+        .map_err(|e| FilesystemError::Serialization(format!("Failed to serialize project: {}", e)))
+}
+
+fn object<'a>(value: &'a Value, path: &[&str]) -> &'a Map<String, Value> {
+    as_object(path.iter().fold(value, |value, key| &value[*key]))
+}
+
+fn as_object(value: &Value) -> &Map<String, Value> {
+    static EMPTY: std::sync::OnceLock<Map<String, Value>> = std::sync::OnceLock::new();
+    value.as_object().unwrap_or_else(|| EMPTY.get_or_init(Map::new))
+}
+
+/// Keys of all maps, sorted so results are deterministic.
+fn union_keys(maps: &[&Map<String, Value>]) -> BTreeSet<String> {
+    maps.iter().flat_map(|map| map.keys().cloned()).collect()
+}
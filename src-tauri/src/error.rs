@@ -0,0 +1,277 @@
+use serde::ser::{Serialize, Serializer};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Machine-readable kind of a failure, stable across releases so the
+/// frontend can react to it without parsing messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    NotFound,
+    /// The data exists but can't be read back, e.g. a checksum mismatch
+    Corrupt,
+    /// Written by a newer version of CellForge
+    VersionTooNew,
+    PermissionDenied,
+    ConstraintViolation,
+    /// The database is locked by another writer
+    Busy,
+    /// The request itself is wrong, e.g. invalid export options
+    InvalidInput,
+    /// Not possible in this context, e.g. app data outside the app
+    Unavailable,
+    Io,
+    Internal,
+}
+
+/// A failure in the component library database.
+#[derive(Debug, thiserror::Error)]
+pub enum DatabaseError {
+    #[error("{entity} {id} not found")]
+    NotFound { entity: &'static str, id: i64 },
+    #[error("{context}: {source}")]
+    Query {
+        context: String,
+        #[source]
+        source: rusqlite::Error,
+    },
+    /// No read connection became free in time
+    #[error("Library database is busy: {0}")]
+    Pool(#[from] r2d2::Error),
+    /// The blocking task running a command died before returning
+    #[error("Background task failed: {0}")]
+    Task(String),
+}
+
+impl DatabaseError {
+    /// Wraps a rusqlite error with what was being done, for `map_err`.
+    pub fn context(context: &str) -> impl FnOnce(rusqlite::Error) -> DatabaseError + '_ {
+        move |source| DatabaseError::Query {
+            context: context.to_string(),
+            source,
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        use rusqlite::ErrorCode as Sqlite;
+
+        match self {
+            DatabaseError::NotFound { .. } => ErrorCode::NotFound,
+            DatabaseError::Query { source, .. } => match source {
+                rusqlite::Error::QueryReturnedNoRows => ErrorCode::NotFound,
+                rusqlite::Error::SqliteFailure(e, _) => match e.code {
+                    Sqlite::ConstraintViolation => ErrorCode::ConstraintViolation,
+                    Sqlite::DatabaseCorrupt | Sqlite::NotADatabase => ErrorCode::Corrupt,
+                    Sqlite::PermissionDenied | Sqlite::ReadOnly | Sqlite::CannotOpen => {
+                        ErrorCode::PermissionDenied
+                    }
+                    Sqlite::DatabaseBusy | Sqlite::DatabaseLocked => ErrorCode::Busy,
+                    Sqlite::SystemIoFailure | Sqlite::DiskFull => ErrorCode::Io,
+                    _ => ErrorCode::Internal,
+                },
+                _ => ErrorCode::Internal,
+            },
+            DatabaseError::Pool(_) => ErrorCode::Busy,
+            DatabaseError::Task(_) => ErrorCode::Internal,
+        }
+    }
+
+    fn id(&self) -> Option<String> {
+        match self {
+            DatabaseError::NotFound { id, .. } => Some(id.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// A failure reading or writing files: projects, snapshots, bundles and the
+/// app's own data.
+#[derive(Debug, thiserror::Error)]
+pub enum FilesystemError {
+    #[error("{} not found", path.display())]
+    NotFound { path: PathBuf },
+    #[error("Permission denied: {}", path.display())]
+    PermissionDenied { path: PathBuf },
+    #[error("{} is damaged: {reason}", path.display())]
+    Corrupt { path: PathBuf, reason: String },
+    #[error("{} was written by a newer version of CellForge ({version}; this version reads up to {supported})", path.display())]
+    VersionTooNew {
+        path: PathBuf,
+        version: String,
+        supported: String,
+    },
+    /// Something identified by id, like a snapshot, doesn't exist
+    #[error("{kind} {id} not found")]
+    UnknownId { kind: &'static str, id: String },
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        path: Option<PathBuf>,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("{0}")]
+    InvalidInput(String),
+    #[error("{0}")]
+    Unavailable(String),
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+    /// Serializing, compressing or rebuilding data in memory failed
+    #[error("{0}")]
+    Serialization(String),
+}
+
+impl FilesystemError {
+    /// Wraps an I/O error on `path`; missing files and denied access get
+    /// their own variants.
+    pub fn io<'a>(
+        context: &str,
+        path: &'a Path,
+    ) -> impl FnOnce(std::io::Error) -> FilesystemError + 'a {
+        let context = context.to_string();
+        move |source| match source.kind() {
+            std::io::ErrorKind::NotFound => FilesystemError::NotFound {
+                path: path.to_path_buf(),
+            },
+            std::io::ErrorKind::PermissionDenied => FilesystemError::PermissionDenied {
+                path: path.to_path_buf(),
+            },
+            _ => FilesystemError::Io {
+                context,
+                path: Some(path.to_path_buf()),
+                source,
+            },
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            FilesystemError::NotFound { .. } | FilesystemError::UnknownId { .. } => {
+                ErrorCode::NotFound
+            }
+            FilesystemError::PermissionDenied { .. } => ErrorCode::PermissionDenied,
+            FilesystemError::Corrupt { .. } => ErrorCode::Corrupt,
+            FilesystemError::VersionTooNew { .. } => ErrorCode::VersionTooNew,
+            FilesystemError::Io { .. } => ErrorCode::Io,
+            FilesystemError::InvalidInput(_) => ErrorCode::InvalidInput,
+            FilesystemError::Unavailable(_) => ErrorCode::Unavailable,
+            FilesystemError::Database(e) => e.code(),
+            FilesystemError::Serialization(_) => ErrorCode::Internal,
+        }
+    }
+
+    fn path(&self) -> Option<&Path> {
+        match self {
+            FilesystemError::NotFound { path }
+            | FilesystemError::PermissionDenied { path }
+            | FilesystemError::Corrupt { path, .. }
+            | FilesystemError::VersionTooNew { path, .. } => Some(path),
+            FilesystemError::Io { path, .. } => path.as_deref(),
+            _ => None,
+        }
+    }
+
+    fn id(&self) -> Option<String> {
+        match self {
+            FilesystemError::UnknownId { id, .. } => Some(id.clone()),
+            FilesystemError::Database(e) => e.id(),
+            _ => None,
+        }
+    }
+}
+
+/// A failure exporting a project.
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error("{0}")]
+    InvalidOptions(String),
+    #[error("Export job {id} not found")]
+    UnknownJob { id: String },
+    #[error("Export job {id} has already finished")]
+    JobFinished { id: String },
+    /// The writer for the format failed, or the export was cancelled
+    #[error("{0}")]
+    Failed(String),
+    #[error(transparent)]
+    Write(#[from] FilesystemError),
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+}
+
+impl ExportError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ExportError::InvalidOptions(_) | ExportError::JobFinished { .. } => {
+                ErrorCode::InvalidInput
+            }
+            ExportError::UnknownJob { .. } => ErrorCode::NotFound,
+            ExportError::Failed(_) => ErrorCode::Internal,
+            ExportError::Write(e) => e.code(),
+            ExportError::Database(e) => e.code(),
+        }
+    }
+
+    fn path(&self) -> Option<&Path> {
+        match self {
+            ExportError::Write(e) => e.path(),
+            _ => None,
+        }
+    }
+
+    fn id(&self) -> Option<String> {
+        match self {
+            ExportError::UnknownJob { id } | ExportError::JobFinished { id } => Some(id.clone()),
+            ExportError::Write(e) => e.id(),
+            ExportError::Database(e) => e.id(),
+            _ => None,
+        }
+    }
+}
+
+/// What the frontend receives for a failed command:
+/// `{ code, message, path?, id? }`.
+#[derive(serde::Serialize)]
+struct Payload<'a> {
+    code: ErrorCode,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<&'a Path>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+}
+
+impl Serialize for DatabaseError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Payload {
+            code: self.code(),
+            message: self.to_string(),
+            path: None,
+            id: self.id(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl Serialize for FilesystemError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Payload {
+            code: self.code(),
+            message: self.to_string(),
+            path: self.path(),
+            id: self.id(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl Serialize for ExportError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Payload {
+            code: self.code(),
+            message: self.to_string(),
+            path: self.path(),
+            id: self.id(),
+        }
+        .serialize(serializer)
+    }
+}
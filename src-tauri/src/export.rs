@@ -1,18 +1,80 @@
+mod geometry;
+mod gltf;
+mod obj;
+mod parts;
+mod step;
+mod stl;
+mod threemf;
+mod thumbnail;
+
+use crate::database::{Bms, Cell};
+use crate::error::ExportError;
+use crate::filesystem::{Connection, Filesystem, ProjectFile, Scene};
+use geometry::CellShape;
 use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Which part of the scene an export covers.
+///
+/// Serialized as `{ "mode": "selected", "uuids": [...] }`; the other modes
+/// carry no payload. STL and OBJ accept every mode, glTF all but
+/// `holders-only`, and 3MF and STEP only `all` and `selected`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", content = "uuids", rename_all = "kebab-case")]
+pub enum ExportSelection {
+    /// The whole scene
+    All,
+    /// Only the entities with these uuids
+    Selected(Vec<String>),
+    /// Components without the cells, e.g. to print holders
+    HoldersOnly,
+    /// Cells without components or connections
+    CellsOnly,
+}
+
+impl ExportSelection {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportSelection::All => "all",
+            ExportSelection::Selected(_) => "selected",
+            ExportSelection::HoldersOnly => "holders-only",
+            ExportSelection::CellsOnly => "cells-only",
+        }
+    }
+
+    /// Whether an entity passes an explicit selection; other modes filter by kind.
+    pub fn includes(&self, uuid: &str) -> bool {
+        match self {
+            ExportSelection::Selected(uuids) => uuids.iter().any(|u| u == uuid),
+            _ => true,
+        }
+    }
+
+    pub fn includes_cells(&self) -> bool {
+        !matches!(self, ExportSelection::HoldersOnly)
+    }
+
+    pub fn includes_components(&self) -> bool {
+        !matches!(self, ExportSelection::CellsOnly)
+    }
+
+    pub fn includes_connections(&self) -> bool {
+        matches!(self, ExportSelection::All | ExportSelection::Selected(_))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct STLExportOptions {
-    pub selection: String, // "all" | "selected" | "holders-only" | "cells-only"
+    pub selection: ExportSelection,
     pub merge_geometries: bool,
     pub apply_transforms: bool,
     pub scale: f64,
     pub file_name: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ThreeMFExportOptions {
-    pub selection: String, // "all" | "selected"
+    pub selection: ExportSelection,
     pub include_colors: bool,
     pub include_materials: bool,
     pub separate_objects: bool,
@@ -20,13 +82,228 @@ pub struct ThreeMFExportOptions {
     pub file_name: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GLTFExportOptions {
+    pub selection: ExportSelection,
+    pub binary: bool, // GLB when true, .gltf with an embedded buffer otherwise
+    pub include_connections: bool,
+    pub segments: u32,
+    pub file_name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct STEPExportOptions {
+    pub selection: ExportSelection,
+    pub group_assemblies: bool, // one sub-assembly per scene group
+    pub file_name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OBJExportOptions {
+    pub selection: ExportSelection,
+    pub merge_geometries: bool,
+    pub apply_transforms: bool,
+    pub scale: f64,
+    pub include_materials: bool, // write a companion .mtl file
+    pub file_name: String,
+}
+
+/// Export format with its options, as sent by the frontend:
+/// `{ "format": "stl", "options": { ... } }`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "format", content = "options", rename_all = "lowercase")]
+pub enum ExportFormat {
+    Stl(STLExportOptions),
+    #[serde(rename = "3mf")]
+    ThreeMf(ThreeMFExportOptions),
+    Gltf(GLTFExportOptions),
+    Step(STEPExportOptions),
+    Obj(OBJExportOptions),
+}
+
+impl ExportFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportFormat::Stl(_) => "stl",
+            ExportFormat::ThreeMf(_) => "3mf",
+            ExportFormat::Gltf(_) => "gltf",
+            ExportFormat::Step(_) => "step",
+            ExportFormat::Obj(_) => "obj",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Stl(_) => "stl",
+            ExportFormat::ThreeMf(_) => "3mf",
+            ExportFormat::Gltf(options) if options.binary => "glb",
+            ExportFormat::Gltf(_) => "gltf",
+            ExportFormat::Step(_) => "step",
+            ExportFormat::Obj(_) => "obj",
+        }
+    }
+}
+
+/// Outcome of a completed export, returned to the frontend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExportResult {
-    pub success: bool,
-    pub data: Option<Vec<u8>>,
-    pub error: Option<String>,
+    pub path: String,
+    pub file_size: u64,
+    pub companion_files: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Bytes produced by a backend exporter, plus anything the user should know
+/// about entities that could not be exported.
+#[derive(Debug, Default)]
+pub struct ExportOutput {
+    pub data: Vec<u8>,
+    pub companions: Vec<CompanionFile>,
+    pub warnings: Vec<String>,
+}
+
+/// Extra file written next to the main export, such as an OBJ's `.mtl`.
+#[derive(Debug)]
+pub struct CompanionFile {
+    pub file_name: String,
+    pub data: Vec<u8>,
+}
+
+/// Receives progress from a running export.
+///
+/// `fraction` runs from 0.0 to 1.0 across the whole export. Returning an
+/// error stops the export; writers propagate it with `?` between entities.
+pub trait ExportProgress {
+    fn report(&self, stage: &str, fraction: f64) -> Result<(), String>;
+}
+
+/// Synchronous exports nobody is watching.
+impl ExportProgress for () {
+    fn report(&self, _stage: &str, _fraction: f64) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+pub const STAGE_GEOMETRY: &str = "geometry";
+pub const STAGE_ENCODING: &str = "encoding";
+pub const STAGE_WRITING: &str = "writing";
+
+// Share of the progress bar spent tessellating; encoding and writing the
+// file take the rest.
+const GEOMETRY_SHARE: f64 = 0.8;
+
+/// Reports `done` of `total` entities within the geometry stage.
+fn report_geometry(progress: &dyn ExportProgress, done: usize, total: usize) -> Result<(), String> {
+    progress.report(STAGE_GEOMETRY, GEOMETRY_SHARE * done as f64 / total.max(1) as f64)
+}
+
+fn report_encoding(progress: &dyn ExportProgress) -> Result<(), String> {
+    progress.report(STAGE_ENCODING, GEOMETRY_SHARE)
+}
+
+/// Library rows referenced by a project, keyed by database id.
+#[derive(Debug, Default)]
+pub struct ExportLibrary {
+    pub cells: HashMap<i64, Cell>,
+    pub bms: HashMap<i64, Bms>,
+}
+
+impl ExportLibrary {
+    pub fn new(cells: Vec<Cell>, bms: Vec<Bms>) -> Self {
+        ExportLibrary {
+            cells: cells.into_iter().map(|cell| (cell.id, cell)).collect(),
+            bms: bms.into_iter().map(|bms| (bms.id, bms)).collect(),
+        }
+    }
+
+    fn cell_shape(&self, cell_id: i64) -> Option<CellShape> {
+        self.cells.get(&cell_id).and_then(CellShape::from_cell)
+    }
+}
+
+/// Default display color for a cell chemistry, used when a cell has no group color.
+pub fn chemistry_color(chemistry: &str) -> &'static str {
+    match chemistry {
+        "NMC" => "#1e40af",
+        "NCA" => "#7c3aed",
+        "LFP" => "#15803d",
+        "LTO" => "#b45309",
+        "LCO" => "#b91c1c",
+        _ => "#6b7280",
+    }
+}
+
+/// Parses `#rrggbb` (or `rrggbb`) into sRGB components in `0.0..=1.0`.
+pub fn parse_hex_color(color: &str) -> Option<[f64; 3]> {
+    let hex = color.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16)
+            .ok()
+            .map(|v| v as f64 / 255.0)
+    };
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Maps each cell uuid to the key of the group it belongs to.
+///
+/// `Group.member_uuids` wins over `CellInstance.group_id`; groups are visited
+/// in key order so a cell listed in several groups resolves deterministically.
+pub fn resolve_cell_groups(scene: &Scene) -> HashMap<&str, &str> {
+    let mut cell_group: HashMap<&str, &str> = HashMap::new();
+
+    let mut group_keys: Vec<&String> = scene.groups.keys().collect();
+    group_keys.sort();
+    for key in group_keys {
+        for member in &scene.groups[key].member_uuids {
+            cell_group.entry(member.as_str()).or_insert(key.as_str());
+        }
+    }
+
+    for cell in scene.cells.values() {
+        if let Some((key, _)) = cell
+            .group_id
+            .as_deref()
+            .and_then(|id| scene.groups.get_key_value(id))
+        {
+            cell_group.entry(cell.uuid.as_str()).or_insert(key.as_str());
+        }
+    }
+
+    cell_group
+}
+
+/// World-space polyline of a connection in millimetres.
+///
+/// Uses the routed `path` when present, otherwise a straight run between the
+/// source and target cells or components.
+pub fn connection_path(scene: &Scene, connection: &Connection) -> Vec<[f64; 3]> {
+    if let Some(path) = &connection.path {
+        if path.len() >= 2 {
+            return path.iter().map(|p| p.0).collect();
+        }
+    }
+
+    let position_of = |uuid: &str| {
+        scene
+            .cells
+            .get(uuid)
+            .map(|c| c.position.0)
+            .or_else(|| scene.components.get(uuid).map(|c| c.position.0))
+    };
+
+    match (
+        position_of(&connection.source_uuid),
+        position_of(&connection.target_uuid),
+    ) {
+        (Some(source), Some(target)) => vec![source, target],
+        _ => Vec::new(),
+    }
 }
 
+#[derive(Default)]
 pub struct Exporter;
 
 impl Exporter {
@@ -34,38 +311,160 @@ impl Exporter {
         Exporter
     }
 
-    // These functions are primarily handled on the frontend with Three.js
-    // The backend just provides file I/O operations
     pub fn validate_stl_options(&self, options: &STLExportOptions) -> Result<(), String> {
-        if options.file_name.is_empty() {
-            return Err("File name cannot be empty".to_string());
-        }
-
-        match options.selection.as_str() {
-            "all" | "selected" | "holders-only" | "cells-only" => Ok(()),
-            _ => Err("Invalid selection option".to_string()),
-        }
+        validate_file_name(&options.file_name)?;
+        validate_scale(options.scale)?;
+        validate_selection(&options.selection, &["all", "selected", "holders-only", "cells-only"])
     }
 
     pub fn validate_three_mf_options(&self, options: &ThreeMFExportOptions) -> Result<(), String> {
-        if options.file_name.is_empty() {
-            return Err("File name cannot be empty".to_string());
+        validate_file_name(&options.file_name)?;
+        validate_selection(&options.selection, &["all", "selected"])
+    }
+
+    pub fn validate_gltf_options(&self, options: &GLTFExportOptions) -> Result<(), String> {
+        validate_file_name(&options.file_name)?;
+
+        if options.segments < 3 {
+            return Err("Segments must be at least 3".to_string());
         }
 
-        match options.selection.as_str() {
-            "all" | "selected" => Ok(()),
-            _ => Err("Invalid selection option".to_string()),
+        validate_selection(&options.selection, &["all", "selected", "cells-only"])
+    }
+
+    pub fn validate_step_options(&self, options: &STEPExportOptions) -> Result<(), String> {
+        validate_file_name(&options.file_name)?;
+        validate_selection(&options.selection, &["all", "selected"])
+    }
+
+    pub fn validate_obj_options(&self, options: &OBJExportOptions) -> Result<(), String> {
+        validate_file_name(&options.file_name)?;
+        validate_scale(options.scale)?;
+        validate_selection(&options.selection, &["all", "selected", "holders-only", "cells-only"])
+    }
+
+    pub fn validate(&self, format: &ExportFormat) -> Result<(), ExportError> {
+        let valid = match format {
+            ExportFormat::Stl(options) => self.validate_stl_options(options),
+            ExportFormat::ThreeMf(options) => self.validate_three_mf_options(options),
+            ExportFormat::Gltf(options) => self.validate_gltf_options(options),
+            ExportFormat::Step(options) => self.validate_step_options(options),
+            ExportFormat::Obj(options) => self.validate_obj_options(options),
+        };
+        valid.map_err(ExportError::InvalidOptions)
+    }
+
+    /// Validates the options and generates the file contents for `format`.
+    pub fn export(
+        &self,
+        project: &ProjectFile,
+        library: &ExportLibrary,
+        format: &ExportFormat,
+        progress: &dyn ExportProgress,
+    ) -> Result<ExportOutput, ExportError> {
+        self.validate(format)?;
+
+        let output = match format {
+            ExportFormat::Stl(options) => stl::write(project, library, options, progress),
+            ExportFormat::ThreeMf(options) => threemf::write(project, library, options, progress),
+            ExportFormat::Gltf(options) => gltf::write(project, library, options, progress),
+            ExportFormat::Step(options) => step::write(project, library, options, progress),
+            ExportFormat::Obj(options) => obj::write(project, library, options, progress),
+        };
+        output.map_err(ExportError::Failed)
+    }
+
+    /// Generates `format` and writes it, with any companion files, to `path`.
+    pub fn export_to_file(
+        &self,
+        filesystem: &Filesystem,
+        project: &ProjectFile,
+        library: &ExportLibrary,
+        format: &ExportFormat,
+        path: &str,
+        progress: &dyn ExportProgress,
+    ) -> Result<ExportResult, ExportError> {
+        progress.report(STAGE_GEOMETRY, 0.0).map_err(ExportError::Failed)?;
+        let output = self.export(project, library, format, progress)?;
+
+        let companions: Vec<(String, Vec<u8>)> = output
+            .companions
+            .into_iter()
+            .map(|file| (file.file_name, file.data))
+            .collect();
+
+        let mut file_path = std::path::PathBuf::from(path);
+        if file_path.extension().is_none() {
+            file_path.set_extension(format.extension());
         }
+
+        // Last chance to cancel; the write itself is atomic
+        progress.report(STAGE_WRITING, 0.9).map_err(ExportError::Failed)?;
+        let file_size = filesystem.write_export(&output.data, &companions, &file_path)?;
+
+        Ok(ExportResult {
+            path: file_path.to_string_lossy().into_owned(),
+            file_size,
+            companion_files: companions.into_iter().map(|(name, _)| name).collect(),
+            warnings: output.warnings,
+        })
+    }
+
+    /// Top-down PNG preview of the scene, `size` pixels square.
+    pub fn thumbnail(&self, project: &ProjectFile, library: &ExportLibrary, size: u32) -> Result<Vec<u8>, String> {
+        thumbnail::render(project, library, size)
     }
 
     pub fn get_supported_formats(&self) -> Vec<String> {
-        vec!["stl".to_string(), "3mf".to_string()]
+        vec![
+            "stl".to_string(),
+            "3mf".to_string(),
+            "gltf".to_string(),
+            "glb".to_string(),
+            "step".to_string(),
+            "obj".to_string(),
+        ]
     }
 
     pub fn get_format_extensions(&self) -> HashMap<String, String> {
         let mut extensions = HashMap::new();
         extensions.insert("stl".to_string(), "stl".to_string());
         extensions.insert("3mf".to_string(), "3mf".to_string());
+        extensions.insert("gltf".to_string(), "gltf".to_string());
+        extensions.insert("glb".to_string(), "glb".to_string());
+        extensions.insert("step".to_string(), "step".to_string());
+        extensions.insert("obj".to_string(), "obj".to_string());
         extensions
     }
-}
\ No newline at end of file
+}
+
+fn validate_file_name(file_name: &str) -> Result<(), String> {
+    if file_name.is_empty() {
+        return Err("File name cannot be empty".to_string());
+    }
+    Ok(())
+}
+
+fn validate_scale(scale: f64) -> Result<(), String> {
+    if !(scale.is_finite() && scale > 0.0) {
+        return Err("Scale must be a positive number".to_string());
+    }
+    Ok(())
+}
+
+fn validate_selection(selection: &ExportSelection, allowed: &[&str]) -> Result<(), String> {
+    if !allowed.contains(&selection.name()) {
+        return Err(format!(
+            "Selection '{}' is not supported for this format",
+            selection.name()
+        ));
+    }
+
+    if let ExportSelection::Selected(uuids) = selection {
+        if uuids.is_empty() {
+            return Err("No entities selected".to_string());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,388 @@
+use crate::database::{Bms, Cell};
+use crate::filesystem::{Euler, Vector3};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+// Vertices closer than this are the same point once welded
+const WELD_TOLERANCE_MM: f64 = 1e-4;
+
+/// Triangle mesh shared by the backend exporters.
+///
+/// Geometry is built in millimetres with the same conventions as the
+/// Three.js viewport: cylinders run along +Y and boxes are
+/// width (X) x height (Y) x length (Z), all centred on the origin.
+#[derive(Debug, Clone, Default)]
+pub struct Mesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn new() -> Self {
+        Mesh::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    fn push_vertex(&mut self, position: [f64; 3], normal: [f64; 3]) -> u32 {
+        let index = self.positions.len() as u32;
+        self.positions.push(to_f32(position));
+        self.normals.push(to_f32(normal));
+        index
+    }
+
+    /// Returns a copy of the mesh with `placement` and a uniform `scale` applied.
+    pub fn transformed(&self, placement: &Placement, scale: f64) -> Mesh {
+        Mesh {
+            positions: self
+                .positions
+                .iter()
+                .map(|p| to_f32(scale_vec(placement.apply_point(to_f64(*p)), scale)))
+                .collect(),
+            normals: self
+                .normals
+                .iter()
+                .map(|n| to_f32(placement.apply_vector(to_f64(*n))))
+                .collect(),
+            indices: self.indices.clone(),
+        }
+    }
+
+    /// Axis-aligned bounds as `(min, max)`, or `None` for an empty mesh.
+    pub fn bounds(&self) -> Option<([f32; 3], [f32; 3])> {
+        let first = *self.positions.first()?;
+        let mut min = first;
+        let mut max = first;
+        for p in &self.positions {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(p[axis]);
+                max[axis] = max[axis].max(p[axis]);
+            }
+        }
+        Some((min, max))
+    }
+
+    /// Returns a copy with vertices at the same position merged and the
+    /// triangles that collapse dropped.
+    ///
+    /// The generators split vertices along seams and hard edges so normals
+    /// stay right for shading. Formats without normals, like 3MF and STL,
+    /// want each corner once, so the surface is closed by vertex index.
+    /// Merged vertices keep the first normal.
+    pub fn welded(&self) -> Mesh {
+        let mut welded = Mesh::new();
+        let mut index_of: HashMap<[i64; 3], u32> = HashMap::new();
+        let remap: Vec<u32> = self
+            .positions
+            .iter()
+            .zip(&self.normals)
+            .map(|(p, n)| {
+                let key = p.map(|v| (v as f64 / WELD_TOLERANCE_MM).round() as i64);
+                *index_of.entry(key).or_insert_with(|| {
+                    welded.positions.push(*p);
+                    welded.normals.push(*n);
+                    welded.positions.len() as u32 - 1
+                })
+            })
+            .collect();
+        for tri in self.indices.chunks(3) {
+            let [a, b, c] = [remap[tri[0] as usize], remap[tri[1] as usize], remap[tri[2] as usize]];
+            if a != b && b != c && a != c {
+                welded.indices.extend_from_slice(&[a, b, c]);
+            }
+        }
+        welded
+    }
+
+    /// Closed cylinder of `radius` and `height` along the Y axis.
+    pub fn cylinder(radius: f64, height: f64, segments: u32) -> Mesh {
+        let segments = segments.max(3);
+        let half = height / 2.0;
+        let mut mesh = Mesh::new();
+
+        // Side wall, with a duplicated seam so normals stay smooth
+        for i in 0..=segments {
+            let theta = 2.0 * PI * i as f64 / segments as f64;
+            let (sin, cos) = theta.sin_cos();
+            let normal = [sin, 0.0, cos];
+            mesh.push_vertex([radius * sin, half, radius * cos], normal);
+            mesh.push_vertex([radius * sin, -half, radius * cos], normal);
+        }
+        for i in 0..segments {
+            let top = 2 * i;
+            let bottom = top + 1;
+            let next_top = top + 2;
+            let next_bottom = top + 3;
+            mesh.indices.extend_from_slice(&[top, bottom, next_top]);
+            mesh.indices.extend_from_slice(&[bottom, next_bottom, next_top]);
+        }
+
+        // End caps
+        for (y, normal_y) in [(half, 1.0), (-half, -1.0)] {
+            let normal = [0.0, normal_y, 0.0];
+            let center = mesh.push_vertex([0.0, y, 0.0], normal);
+            let ring_start = mesh.positions.len() as u32;
+            for i in 0..segments {
+                let theta = 2.0 * PI * i as f64 / segments as f64;
+                mesh.push_vertex([radius * theta.sin(), y, radius * theta.cos()], normal);
+            }
+            for i in 0..segments {
+                let a = ring_start + i;
+                let b = ring_start + (i + 1) % segments;
+                if normal_y > 0.0 {
+                    mesh.indices.extend_from_slice(&[center, a, b]);
+                } else {
+                    mesh.indices.extend_from_slice(&[center, b, a]);
+                }
+            }
+        }
+
+        mesh
+    }
+
+    /// Box of `width` (X), `height` (Y) and `depth` (Z).
+    pub fn cuboid(width: f64, height: f64, depth: f64) -> Mesh {
+        let (hx, hy, hz) = (width / 2.0, height / 2.0, depth / 2.0);
+        let mut mesh = Mesh::new();
+
+        // Each face: normal, then two in-plane axes scaled to the half extents
+        let faces: [([f64; 3], [f64; 3], [f64; 3]); 6] = [
+            ([1.0, 0.0, 0.0], [0.0, 0.0, -hz], [0.0, hy, 0.0]),
+            ([-1.0, 0.0, 0.0], [0.0, 0.0, hz], [0.0, hy, 0.0]),
+            ([0.0, 1.0, 0.0], [hx, 0.0, 0.0], [0.0, 0.0, -hz]),
+            ([0.0, -1.0, 0.0], [hx, 0.0, 0.0], [0.0, 0.0, hz]),
+            ([0.0, 0.0, 1.0], [hx, 0.0, 0.0], [0.0, hy, 0.0]),
+            ([0.0, 0.0, -1.0], [-hx, 0.0, 0.0], [0.0, hy, 0.0]),
+        ];
+
+        for (normal, u, v) in faces {
+            let center = [normal[0] * hx, normal[1] * hy, normal[2] * hz];
+            let start = mesh.positions.len() as u32;
+            for (su, sv) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                let p = [
+                    center[0] + su * u[0] + sv * v[0],
+                    center[1] + su * u[1] + sv * v[1],
+                    center[2] + su * u[2] + sv * v[2],
+                ];
+                mesh.push_vertex(p, normal);
+            }
+            mesh.indices
+                .extend_from_slice(&[start, start + 1, start + 2, start, start + 2, start + 3]);
+        }
+
+        mesh
+    }
+
+    /// Round tube of `radius` following a polyline, one open cylinder per segment.
+    pub fn tube(path: &[[f64; 3]], radius: f64, segments: u32) -> Mesh {
+        let segments = segments.max(3);
+        let mut mesh = Mesh::new();
+
+        for pair in path.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            let axis = sub(b, a);
+            if length(axis) < f64::EPSILON {
+                continue;
+            }
+            let axis = normalize(axis);
+            let (u, v) = orthonormal_basis(axis);
+
+            let start = mesh.positions.len() as u32;
+            for i in 0..=segments {
+                let theta = 2.0 * PI * i as f64 / segments as f64;
+                let (sin, cos) = theta.sin_cos();
+                let normal = add(scale_vec(u, cos), scale_vec(v, sin));
+                let offset = scale_vec(normal, radius);
+                mesh.push_vertex(add(a, offset), normal);
+                mesh.push_vertex(add(b, offset), normal);
+            }
+            for i in 0..segments {
+                let s = start + 2 * i;
+                mesh.indices.extend_from_slice(&[s, s + 2, s + 1]);
+                mesh.indices.extend_from_slice(&[s + 1, s + 2, s + 3]);
+            }
+        }
+
+        mesh
+    }
+}
+
+/// Outer shape of a cell, derived from its datasheet dimensions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellShape {
+    Cylinder { diameter: f64, length: f64 },
+    Box { width: f64, height: f64, length: f64 },
+}
+
+impl CellShape {
+    pub fn from_cell(cell: &Cell) -> Option<CellShape> {
+        match (cell.diameter_mm, cell.width_mm, cell.height_mm) {
+            (Some(diameter), _, _) if diameter > 0.0 => Some(CellShape::Cylinder {
+                diameter,
+                length: cell.length_mm,
+            }),
+            (_, Some(width), Some(height)) => Some(CellShape::Box {
+                width,
+                height,
+                length: cell.length_mm,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn mesh(&self, segments: u32) -> Mesh {
+        match *self {
+            CellShape::Cylinder { diameter, length } => {
+                Mesh::cylinder(diameter / 2.0, length, segments)
+            }
+            CellShape::Box {
+                width,
+                height,
+                length,
+            } => Mesh::cuboid(width, height, length),
+        }
+    }
+}
+
+/// Size of the stand-in box the viewport draws for a component.
+///
+/// BMS boards use their library footprint (length along X, height along Y);
+/// shapes and custom meshes fall back to the viewport's placeholder cubes.
+pub fn component_box(component_type: &str, bms: Option<&Bms>) -> [f64; 3] {
+    match (component_type, bms) {
+        ("bms", Some(bms)) => [bms.length_mm, bms.height_mm, bms.width_mm],
+        ("bms", None) => [50.0, 10.0, 30.0],
+        ("shape", _) => [20.0, 20.0, 20.0],
+        _ => [10.0, 10.0, 10.0],
+    }
+}
+
+/// Rigid transform built from the project's position + XYZ Euler pairs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    pub rotation: [[f64; 3]; 3],
+    pub translation: [f64; 3],
+}
+
+impl Placement {
+    pub fn identity() -> Self {
+        Placement {
+            rotation: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            translation: [0.0; 3],
+        }
+    }
+
+    pub fn new(position: &Vector3, rotation: &Euler) -> Self {
+        Placement {
+            rotation: euler_to_matrix(rotation.0),
+            translation: position.0,
+        }
+    }
+
+    pub fn apply_vector(&self, v: [f64; 3]) -> [f64; 3] {
+        let r = &self.rotation;
+        [
+            r[0][0] * v[0] + r[0][1] * v[1] + r[0][2] * v[2],
+            r[1][0] * v[0] + r[1][1] * v[1] + r[1][2] * v[2],
+            r[2][0] * v[0] + r[2][1] * v[1] + r[2][2] * v[2],
+        ]
+    }
+
+    pub fn apply_point(&self, p: [f64; 3]) -> [f64; 3] {
+        add(self.apply_vector(p), self.translation)
+    }
+
+    /// Column of the rotation matrix for `axis` (0 = X, 1 = Y, 2 = Z).
+    pub fn axis(&self, axis: usize) -> [f64; 3] {
+        [
+            self.rotation[0][axis],
+            self.rotation[1][axis],
+            self.rotation[2][axis],
+        ]
+    }
+}
+
+/// Rotation matrix for Three.js "XYZ" Euler angles in radians.
+pub fn euler_to_matrix(angles: [f64; 3]) -> [[f64; 3]; 3] {
+    let (a, b) = (angles[0].cos(), angles[0].sin());
+    let (c, d) = (angles[1].cos(), angles[1].sin());
+    let (e, f) = (angles[2].cos(), angles[2].sin());
+    let (ae, af, be, bf) = (a * e, a * f, b * e, b * f);
+
+    [
+        [c * e, -c * f, d],
+        [af + be * d, ae - bf * d, -b * c],
+        [bf - ae * d, be + af * d, a * c],
+    ]
+}
+
+/// Unit quaternion `[x, y, z, w]` for Three.js "XYZ" Euler angles in radians.
+pub fn euler_to_quaternion(angles: [f64; 3]) -> [f64; 4] {
+    let (c1, s1) = ((angles[0] / 2.0).cos(), (angles[0] / 2.0).sin());
+    let (c2, s2) = ((angles[1] / 2.0).cos(), (angles[1] / 2.0).sin());
+    let (c3, s3) = ((angles[2] / 2.0).cos(), (angles[2] / 2.0).sin());
+
+    [
+        s1 * c2 * c3 + c1 * s2 * s3,
+        c1 * s2 * c3 - s1 * c2 * s3,
+        c1 * c2 * s3 + s1 * s2 * c3,
+        c1 * c2 * c3 - s1 * s2 * s3,
+    ]
+}
+
+pub fn add(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+pub fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub fn scale_vec(a: [f64; 3], s: f64) -> [f64; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+pub fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+pub fn length(a: [f64; 3]) -> f64 {
+    (a[0] * a[0] + a[1] * a[1] + a[2] * a[2]).sqrt()
+}
+
+pub fn normalize(a: [f64; 3]) -> [f64; 3] {
+    let len = length(a);
+    if len < f64::EPSILON {
+        a
+    } else {
+        scale_vec(a, 1.0 / len)
+    }
+}
+
+/// Two unit vectors perpendicular to `axis` and to each other.
+pub fn orthonormal_basis(axis: [f64; 3]) -> ([f64; 3], [f64; 3]) {
+    let helper = if axis[1].abs() < 0.9 {
+        [0.0, 1.0, 0.0]
+    } else {
+        [1.0, 0.0, 0.0]
+    };
+    let u = normalize(cross(helper, axis));
+    let v = cross(axis, u);
+    (u, v)
+}
+
+fn to_f32(v: [f64; 3]) -> [f32; 3] {
+    [v[0] as f32, v[1] as f32, v[2] as f32]
+}
+
+fn to_f64(v: [f32; 3]) -> [f64; 3] {
+    [v[0] as f64, v[1] as f64, v[2] as f64]
+}
+
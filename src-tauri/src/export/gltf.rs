@@ -0,0 +1,517 @@
+use super::geometry::{component_box, euler_to_quaternion, Mesh};
+use super::parts::{BMS_COLOR, COMPONENT_COLOR};
+use super::{ExportLibrary, ExportOutput, ExportProgress, GLTFExportOptions};
+use crate::filesystem::{CellInstance, ProjectFile};
+use base64::Engine;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+const GLB_MAGIC: u32 = 0x4654_6C67; // "glTF"
+const GLB_VERSION: u32 = 2;
+const CHUNK_JSON: u32 = 0x4E4F_534A;
+const CHUNK_BIN: u32 = 0x004E_4942;
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const COMPONENT_FLOAT: u32 = 5126;
+const COMPONENT_UNSIGNED_INT: u32 = 5125;
+
+/// Project geometry is in millimetres, glTF is in metres.
+const MM_TO_M: f64 = 0.001;
+const CONNECTION_RADIUS_MM: f64 = 1.0;
+const CONNECTION_COLOR: &str = "#b87333";
+
+/// Mesh accessors written once into the binary buffer.
+struct GeometryRef {
+    position: usize,
+    normal: usize,
+    indices: usize,
+}
+
+#[derive(Default)]
+struct Builder {
+    buffer: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+    materials: Vec<Value>,
+    material_lookup: HashMap<String, usize>,
+    meshes: Vec<Value>,
+    nodes: Vec<Value>,
+}
+
+impl Builder {
+    fn push_view(&mut self, bytes: &[u8], target: u32) -> usize {
+        while !self.buffer.len().is_multiple_of(4) {
+            self.buffer.push(0);
+        }
+        let offset = self.buffer.len();
+        self.buffer.extend_from_slice(bytes);
+        self.buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": offset,
+            "byteLength": bytes.len(),
+            "target": target,
+        }));
+        self.buffer_views.len() - 1
+    }
+
+    fn push_vec3_accessor(&mut self, data: &[[f32; 3]], with_bounds: bool) -> usize {
+        let bytes: Vec<u8> = data
+            .iter()
+            .flat_map(|v| v.iter().flat_map(|c| c.to_le_bytes()))
+            .collect();
+        let view = self.push_view(&bytes, ARRAY_BUFFER);
+
+        let mut accessor = json!({
+            "bufferView": view,
+            "componentType": COMPONENT_FLOAT,
+            "count": data.len(),
+            "type": "VEC3",
+        });
+        if with_bounds {
+            let mesh = Mesh {
+                positions: data.to_vec(),
+                ..Mesh::default()
+            };
+            if let Some((min, max)) = mesh.bounds() {
+                accessor["min"] = json!(min);
+                accessor["max"] = json!(max);
+            }
+        }
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    fn push_index_accessor(&mut self, indices: &[u32]) -> usize {
+        let bytes: Vec<u8> = indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+        let view = self.push_view(&bytes, ELEMENT_ARRAY_BUFFER);
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": COMPONENT_UNSIGNED_INT,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+        self.accessors.len() - 1
+    }
+
+    fn push_geometry(&mut self, mesh: &Mesh) -> GeometryRef {
+        GeometryRef {
+            position: self.push_vec3_accessor(&mesh.positions, true),
+            normal: self.push_vec3_accessor(&mesh.normals, false),
+            indices: self.push_index_accessor(&mesh.indices),
+        }
+    }
+
+    fn material(&mut self, name: &str, color: &str) -> usize {
+        let key = format!("{}|{}", name, color);
+        if let Some(&index) = self.material_lookup.get(&key) {
+            return index;
+        }
+
+        let [r, g, b] = super::parse_hex_color(color).unwrap_or([0.5, 0.5, 0.5]);
+        self.materials.push(json!({
+            "name": name,
+            "pbrMetallicRoughness": {
+                "baseColorFactor": [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), 1.0],
+                "metallicFactor": 0.5,
+                "roughnessFactor": 0.4,
+            },
+            "extras": { "color": color },
+        }));
+        let index = self.materials.len() - 1;
+        self.material_lookup.insert(key, index);
+        index
+    }
+
+    fn push_mesh(&mut self, name: &str, geometry: &GeometryRef, material: usize, extras: Value) -> usize {
+        self.meshes.push(json!({
+            "name": name,
+            "primitives": [{
+                "attributes": {
+                    "POSITION": geometry.position,
+                    "NORMAL": geometry.normal,
+                },
+                "indices": geometry.indices,
+                "material": material,
+            }],
+            "extras": extras,
+        }));
+        self.meshes.len() - 1
+    }
+
+    fn push_node(&mut self, node: Value) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+}
+
+/// Builds a glTF 2.0 document for the project.
+///
+/// The node tree is `project -> group -> cell`, with ungrouped cells and
+/// `Components` and `Connections` nodes directly under the project root. Cell
+/// geometry is written once per `cell_id` and shared by every instance;
+/// meshes carry the library cell spec in their `extras`. Components are
+/// written as the boxes the viewport draws for them.
+pub fn write(
+    project: &ProjectFile,
+    library: &ExportLibrary,
+    options: &GLTFExportOptions,
+    progress: &dyn ExportProgress,
+) -> Result<ExportOutput, String> {
+    let mut builder = Builder::default();
+    let mut warnings = Vec::new();
+    let scene = &project.scene;
+
+    let is_selected = |uuid: &str| options.selection.includes(uuid);
+
+    // Sorted so repeated exports of the same project are byte-identical
+    let mut cells: Vec<&CellInstance> = scene
+        .cells
+        .values()
+        .filter(|cell| is_selected(&cell.uuid))
+        .collect();
+    cells.sort_by(|a, b| a.uuid.cmp(&b.uuid));
+
+    // Shared geometry per library cell
+    let mut geometries: BTreeMap<i64, GeometryRef> = BTreeMap::new();
+    let mut missing_specs: HashSet<i64> = HashSet::new();
+    for cell in &cells {
+        if geometries.contains_key(&cell.cell_id) || missing_specs.contains(&cell.cell_id) {
+            continue;
+        }
+        match library.cell_shape(cell.cell_id) {
+            Some(shape) => {
+                let mesh = shape.mesh(options.segments);
+                geometries.insert(cell.cell_id, builder.push_geometry(&mesh));
+            }
+            None => {
+                warnings.push(format!(
+                    "Cell {} has no usable dimensions in the library and was skipped",
+                    cell.cell_id
+                ));
+                missing_specs.insert(cell.cell_id);
+            }
+        }
+    }
+
+    let cell_group = super::resolve_cell_groups(scene);
+
+    let mut mesh_lookup: HashMap<(i64, usize), usize> = HashMap::new();
+    let mut group_children: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    let mut root_children = Vec::new();
+
+    for (index, cell) in cells.iter().enumerate() {
+        super::report_geometry(progress, index, cells.len())?;
+        let Some(geometry) = geometries.get(&cell.cell_id) else {
+            continue;
+        };
+        let spec = library.cells.get(&cell.cell_id);
+        let group_key = cell_group.get(cell.uuid.as_str()).copied();
+        let group = group_key.and_then(|key| scene.groups.get(key));
+
+        let material = match group.and_then(|g| g.color.as_deref()) {
+            Some(color) => {
+                let name = group.map(|g| g.name.as_str()).unwrap_or("Group");
+                builder.material(name, color)
+            }
+            None => {
+                let chemistry = spec.map(|c| c.chemistry.as_str()).unwrap_or("");
+                builder.material(chemistry, super::chemistry_color(chemistry))
+            }
+        };
+
+        let mesh = *mesh_lookup
+            .entry((cell.cell_id, material))
+            .or_insert_with(|| {
+                let name = spec
+                    .map(|c| format!("{} {}", c.manufacturer, c.model))
+                    .unwrap_or_else(|| format!("Cell {}", cell.cell_id));
+                let extras = spec
+                    .and_then(|c| serde_json::to_value(c).ok())
+                    .map(|c| json!({ "cell": c }))
+                    .unwrap_or(Value::Null);
+                builder.push_mesh(&name, geometry, material, extras)
+            });
+
+        let name = cell
+            .custom_label
+            .clone()
+            .unwrap_or_else(|| format!("Cell {}", short_uuid(&cell.uuid)));
+        let node = builder.push_node(json!({
+            "name": name,
+            "mesh": mesh,
+            "translation": cell.position.0,
+            "rotation": euler_to_quaternion(cell.rotation.0),
+            "extras": {
+                "uuid": cell.uuid,
+                "cell_id": cell.cell_id,
+                "group_id": cell.group_id,
+            },
+        }));
+
+        match group_key {
+            Some(key) => group_children.entry(key).or_default().push(node),
+            None => root_children.push(node),
+        }
+    }
+
+    let mut group_nodes = Vec::new();
+    for (group_id, children) in group_children {
+        let group = &scene.groups[group_id];
+        group_nodes.push(builder.push_node(json!({
+            "name": group.name,
+            "children": children,
+            "extras": {
+                "uuid": group.uuid,
+                "color": group.color,
+                "locked": group.locked,
+                "visible": group.visible,
+            },
+        })));
+    }
+    group_nodes.sort_by_key(|&index| builder.nodes[index]["name"].as_str().map(str::to_owned));
+    root_children.splice(0..0, group_nodes);
+
+    if options.selection.includes_components() {
+        let components_node =
+            write_components(project, library, &mut builder, &is_selected, &mut warnings);
+        if let Some(node) = components_node {
+            root_children.push(node);
+        }
+    }
+
+    if options.include_connections && options.selection.includes_connections() {
+        let connections_node = write_connections(project, options, &mut builder, &is_selected);
+        if let Some(node) = connections_node {
+            root_children.push(node);
+        }
+    }
+
+    let mut root = json!({
+        "name": project.metadata.name,
+        "scale": [MM_TO_M, MM_TO_M, MM_TO_M],
+        "extras": {
+            "version": project.version,
+            "author": project.metadata.author,
+            "modified": project.metadata.modified,
+        },
+    });
+    if !root_children.is_empty() {
+        root["children"] = json!(root_children);
+    }
+    let root = builder.push_node(root);
+
+    super::report_encoding(progress)?;
+    let document = assemble(builder, root, options.binary);
+    Ok(ExportOutput {
+        data: document?,
+        warnings,
+        ..ExportOutput::default()
+    })
+}
+
+fn write_components(
+    project: &ProjectFile,
+    library: &ExportLibrary,
+    builder: &mut Builder,
+    is_selected: &dyn Fn(&str) -> bool,
+    warnings: &mut Vec<String>,
+) -> Option<usize> {
+    let mut components: Vec<_> = project
+        .scene
+        .components
+        .values()
+        .filter(|c| is_selected(&c.uuid))
+        .collect();
+    components.sort_by(|a, b| a.uuid.cmp(&b.uuid));
+
+    let mut children = Vec::new();
+    for component in components {
+        let bms = match component.component_type.as_str() {
+            "bms" => component.reference_id.and_then(|id| library.bms.get(&id)),
+            _ => None,
+        };
+        if bms.is_none() {
+            warnings.push(format!(
+                "Component {} was exported as a placeholder box",
+                component.uuid
+            ));
+        }
+
+        let size = component_box(&component.component_type, bms);
+        let s = component.scale.0;
+        let mesh = Mesh::cuboid(size[0] * s[0], size[1] * s[1], size[2] * s[2]);
+        let geometry = builder.push_geometry(&mesh);
+        let material = match bms {
+            Some(_) => builder.material("BMS", BMS_COLOR),
+            None => builder.material("Component", COMPONENT_COLOR),
+        };
+
+        let name = match bms {
+            Some(bms) => format!("{} {}", bms.manufacturer, bms.model),
+            None => format!("{} {}", component.component_type, short_uuid(&component.uuid)),
+        };
+        let extras = bms
+            .and_then(|b| serde_json::to_value(b).ok())
+            .map(|b| json!({ "bms": b }))
+            .unwrap_or(Value::Null);
+        let mesh = builder.push_mesh(&name, &geometry, material, extras);
+        children.push(builder.push_node(json!({
+            "name": name,
+            "mesh": mesh,
+            "translation": component.position.0,
+            "rotation": euler_to_quaternion(component.rotation.0),
+            "extras": {
+                "uuid": component.uuid,
+                "component_type": component.component_type,
+                "reference_id": component.reference_id,
+                "custom_mesh_path": component.custom_mesh_path,
+            },
+        })));
+    }
+
+    if children.is_empty() {
+        return None;
+    }
+    Some(builder.push_node(json!({
+        "name": "Components",
+        "children": children,
+    })))
+}
+
+fn write_connections(
+    project: &ProjectFile,
+    options: &GLTFExportOptions,
+    builder: &mut Builder,
+    is_selected: &dyn Fn(&str) -> bool,
+) -> Option<usize> {
+    let scene = &project.scene;
+    let mut connections: Vec<_> = scene
+        .connections
+        .values()
+        .filter(|c| is_selected(&c.uuid))
+        .collect();
+    connections.sort_by(|a, b| a.uuid.cmp(&b.uuid));
+
+    let mut children = Vec::new();
+
+    for connection in connections {
+        let path = super::connection_path(scene, connection);
+        let mesh = Mesh::tube(&path, CONNECTION_RADIUS_MM, options.segments.min(12));
+        if mesh.is_empty() {
+            continue;
+        }
+
+        let geometry = builder.push_geometry(&mesh);
+        let material = builder.material("Connection", CONNECTION_COLOR);
+        let name = format!("{} {}", connection.connection_type, short_uuid(&connection.uuid));
+        let mesh = builder.push_mesh(&name, &geometry, material, Value::Null);
+        children.push(builder.push_node(json!({
+            "name": name,
+            "mesh": mesh,
+            "extras": {
+                "uuid": connection.uuid,
+                "connection_type": connection.connection_type,
+                "source_uuid": connection.source_uuid,
+                "source_terminal": connection.source_terminal,
+                "target_uuid": connection.target_uuid,
+                "target_terminal": connection.target_terminal,
+                "material_id": connection.material_id,
+            },
+        })));
+    }
+
+    if children.is_empty() {
+        return None;
+    }
+    Some(builder.push_node(json!({
+        "name": "Connections",
+        "children": children,
+    })))
+}
+
+fn assemble(builder: Builder, root: usize, binary: bool) -> Result<Vec<u8>, String> {
+    let Builder {
+        mut buffer,
+        buffer_views,
+        accessors,
+        materials,
+        meshes,
+        nodes,
+        ..
+    } = builder;
+
+    while !buffer.len().is_multiple_of(4) {
+        buffer.push(0);
+    }
+
+    let mut buffer_desc = json!({ "byteLength": buffer.len() });
+    if !binary {
+        buffer_desc["uri"] = json!(format!(
+            "data:application/octet-stream;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(&buffer)
+        ));
+    }
+
+    let mut document = json!({
+        "asset": { "version": "2.0", "generator": "CellForge" },
+        "scene": 0,
+        "scenes": [{ "nodes": [root] }],
+        "nodes": nodes,
+    });
+    // glTF doesn't allow empty top-level arrays, e.g. for an empty scene
+    for (key, items) in [
+        ("meshes", meshes),
+        ("materials", materials),
+        ("accessors", accessors),
+        ("bufferViews", buffer_views),
+    ] {
+        if !items.is_empty() {
+            document[key] = Value::Array(items);
+        }
+    }
+    if !buffer.is_empty() {
+        document["buffers"] = json!([buffer_desc]);
+    }
+
+    if !binary {
+        return serde_json::to_vec_pretty(&document)
+            .map_err(|e| format!("Failed to serialize glTF: {}", e));
+    }
+
+    let mut json = serde_json::to_vec(&document)
+        .map_err(|e| format!("Failed to serialize glTF: {}", e))?;
+    while !json.len().is_multiple_of(4) {
+        json.push(b' ');
+    }
+
+    let bin_chunk_len = if buffer.is_empty() { 0 } else { 8 + buffer.len() };
+    let total = 12 + 8 + json.len() + bin_chunk_len;
+
+    let mut glb = Vec::with_capacity(total);
+    glb.extend_from_slice(&GLB_MAGIC.to_le_bytes());
+    glb.extend_from_slice(&GLB_VERSION.to_le_bytes());
+    glb.extend_from_slice(&(total as u32).to_le_bytes());
+    glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    glb.extend_from_slice(&CHUNK_JSON.to_le_bytes());
+    glb.extend_from_slice(&json);
+    if !buffer.is_empty() {
+        glb.extend_from_slice(&(buffer.len() as u32).to_le_bytes());
+        glb.extend_from_slice(&CHUNK_BIN.to_le_bytes());
+        glb.extend_from_slice(&buffer);
+    }
+
+    Ok(glb)
+}
+
+fn short_uuid(uuid: &str) -> &str {
+    uuid.get(..8).unwrap_or(uuid)
+}
+
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
@@ -0,0 +1,121 @@
+use super::{parts, CompanionFile, ExportLibrary, ExportOutput, ExportProgress, OBJExportOptions};
+use crate::filesystem::ProjectFile;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Builds an OBJ file with one object per cell instance and component,
+/// plus a companion MTL file when materials are requested.
+pub fn write(
+    project: &ProjectFile,
+    library: &ExportLibrary,
+    options: &OBJExportOptions,
+    progress: &dyn ExportProgress,
+) -> Result<ExportOutput, String> {
+    let mut warnings = Vec::new();
+    let parts = parts::collect(
+        project,
+        library,
+        &options.selection,
+        options.apply_transforms,
+        options.scale,
+        &mut warnings,
+        progress,
+    )?;
+
+    super::report_encoding(progress)?;
+    let mtl_name = format!(
+        "{}.mtl",
+        Path::new(&options.file_name)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("export")
+    );
+
+    let mut obj = String::new();
+    let _ = writeln!(obj, "# CellForge OBJ export: {}", project.metadata.name);
+    if options.include_materials {
+        let _ = writeln!(obj, "mtllib {}", mtl_name);
+    }
+    if options.merge_geometries {
+        let _ = writeln!(obj, "o {}", sanitize(&project.metadata.name));
+    }
+
+    let mut materials: BTreeMap<String, &str> = BTreeMap::new();
+    let mut material_names: HashMap<(&str, &str), String> = HashMap::new();
+    let mut offset = 1usize;
+    for part in &parts {
+        if options.merge_geometries {
+            let _ = writeln!(obj, "g {}", sanitize(&part.name));
+        } else {
+            let _ = writeln!(obj, "o {}", sanitize(&part.name));
+        }
+        if options.include_materials {
+            let material = material_names
+                .entry((&part.material, &part.color))
+                .or_insert_with(|| {
+                    // Names that only differ in whitespace collide once sanitized
+                    let name = sanitize(&part.material);
+                    parts::unique_material_name(&materials, name, &sanitize(&part.color))
+                })
+                .clone();
+            let _ = writeln!(obj, "usemtl {}", material);
+            materials.insert(material, &part.color);
+        }
+        for p in &part.mesh.positions {
+            let _ = writeln!(obj, "v {} {} {}", p[0], p[1], p[2]);
+        }
+        for n in &part.mesh.normals {
+            let _ = writeln!(obj, "vn {} {} {}", n[0], n[1], n[2]);
+        }
+        for tri in part.mesh.indices.chunks(3) {
+            let (a, b, c) = (
+                tri[0] as usize + offset,
+                tri[1] as usize + offset,
+                tri[2] as usize + offset,
+            );
+            let _ = writeln!(obj, "f {a}//{a} {b}//{b} {c}//{c}");
+        }
+        offset += part.mesh.positions.len();
+    }
+
+    let mut companions = Vec::new();
+    if options.include_materials {
+        let mut mtl = String::new();
+        let _ = writeln!(mtl, "# CellForge materials: {}", project.metadata.name);
+        for (name, color) in &materials {
+            let [r, g, b] = super::parse_hex_color(color).unwrap_or([0.5, 0.5, 0.5]);
+            let _ = writeln!(mtl, "\nnewmtl {}", name);
+            let _ = writeln!(mtl, "Ka 0 0 0");
+            let _ = writeln!(mtl, "Kd {:.4} {:.4} {:.4}", r, g, b);
+            let _ = writeln!(mtl, "Ks 0.2 0.2 0.2");
+            let _ = writeln!(mtl, "Ns 50");
+            let _ = writeln!(mtl, "d 1");
+            let _ = writeln!(mtl, "illum 2");
+        }
+        companions.push(CompanionFile {
+            file_name: mtl_name,
+            data: mtl.into_bytes(),
+        });
+    }
+
+    Ok(ExportOutput {
+        data: obj.into_bytes(),
+        companions,
+        warnings,
+    })
+}
+
+/// OBJ/MTL names are whitespace-delimited.
+fn sanitize(name: &str) -> String {
+    let cleaned: String = name
+        .trim()
+        .chars()
+        .map(|c| if c.is_whitespace() { '_' } else { c })
+        .collect();
+    if cleaned.is_empty() {
+        "unnamed".to_string()
+    } else {
+        cleaned
+    }
+}
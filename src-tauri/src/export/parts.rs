@@ -0,0 +1,158 @@
+use super::geometry::{component_box, Mesh, Placement};
+use super::{ExportLibrary, ExportProgress, ExportSelection};
+use std::collections::BTreeMap;
+use crate::filesystem::ProjectFile;
+
+const CELL_SEGMENTS: u32 = 32;
+pub const COMPONENT_COLOR: &str = "#9ca3af";
+pub const BMS_COLOR: &str = "#166534";
+
+/// A cell or component tessellated for the mesh-based exporters.
+pub struct ScenePart {
+    pub name: String,
+    pub material: String,
+    pub color: String,
+    pub mesh: Mesh,
+}
+
+/// `name`, unless a material of another colour already has it; then
+/// `name_<color>`, numbered if that is taken too. Groups can share a name
+/// but not a colour, and each needs its own material.
+pub fn unique_material_name(taken: &BTreeMap<String, &str>, name: String, color: &str) -> String {
+    if !taken.contains_key(&name) {
+        return name;
+    }
+    let base = format!("{}_{}", name, color.trim_start_matches('#'));
+    let mut name = base.clone();
+    let mut n = 1;
+    while taken.contains_key(&name) {
+        n += 1;
+        name = format!("{}_{}", base, n);
+    }
+    name
+}
+
+/// Tessellates the selected cells and components in uuid order.
+///
+/// With `apply_transforms` each mesh is placed in world space, otherwise it
+/// stays at its local origin. `scale` is applied last, for unit conversion.
+pub fn collect(
+    project: &ProjectFile,
+    library: &ExportLibrary,
+    selection: &ExportSelection,
+    apply_transforms: bool,
+    scale: f64,
+    warnings: &mut Vec<String>,
+    progress: &dyn ExportProgress,
+) -> Result<Vec<ScenePart>, String> {
+    let scene = &project.scene;
+    let mut parts = Vec::new();
+
+    let mut cells: Vec<_> = scene
+        .cells
+        .values()
+        .filter(|c| selection.includes_cells() && selection.includes(&c.uuid))
+        .collect();
+    cells.sort_by(|a, b| a.uuid.cmp(&b.uuid));
+
+    let mut components: Vec<_> = scene
+        .components
+        .values()
+        .filter(|c| selection.includes_components() && selection.includes(&c.uuid))
+        .collect();
+    components.sort_by(|a, b| a.uuid.cmp(&b.uuid));
+
+    let total = cells.len() + components.len();
+    let mut done = 0;
+
+    if !cells.is_empty() {
+        let cell_group = super::resolve_cell_groups(scene);
+
+        for cell in cells {
+            super::report_geometry(progress, done, total)?;
+            done += 1;
+
+            let Some(shape) = library.cell_shape(cell.cell_id) else {
+                warnings.push(format!(
+                    "Cell {} has no usable dimensions in the library and was skipped",
+                    cell.cell_id
+                ));
+                continue;
+            };
+
+            let group = cell_group
+                .get(cell.uuid.as_str())
+                .and_then(|key| scene.groups.get(*key));
+            let (material, color) = match group.and_then(|g| g.color.as_deref().map(|c| (g, c))) {
+                Some((group, color)) => (format!("group_{}", group.name), color.to_string()),
+                None => {
+                    let chemistry = library
+                        .cells
+                        .get(&cell.cell_id)
+                        .map(|c| c.chemistry.as_str())
+                        .unwrap_or("unknown");
+                    (
+                        format!("cell_{}", chemistry),
+                        super::chemistry_color(chemistry).to_string(),
+                    )
+                }
+            };
+
+            let placement = if apply_transforms {
+                Placement::new(&cell.position, &cell.rotation)
+            } else {
+                Placement::identity()
+            };
+
+            parts.push(ScenePart {
+                name: cell
+                    .custom_label
+                    .clone()
+                    .unwrap_or_else(|| format!("cell_{}", cell.uuid)),
+                material,
+                color,
+                mesh: shape.mesh(CELL_SEGMENTS).transformed(&placement, scale),
+            });
+        }
+    }
+
+    for component in components {
+        super::report_geometry(progress, done, total)?;
+        done += 1;
+
+        let bms = match component.component_type.as_str() {
+            "bms" => component.reference_id.and_then(|id| library.bms.get(&id)),
+            _ => None,
+        };
+        if bms.is_none() {
+            warnings.push(format!(
+                "Component {} was exported as a placeholder box",
+                component.uuid
+            ));
+        }
+
+        let size = component_box(&component.component_type, bms);
+        let s = component.scale.0;
+        let mesh = Mesh::cuboid(size[0] * s[0], size[1] * s[1], size[2] * s[2]);
+
+        let (material, color) = match bms {
+            Some(_) => ("bms", BMS_COLOR),
+            None => ("component", COMPONENT_COLOR),
+        };
+
+        let placement = if apply_transforms {
+            Placement::new(&component.position, &component.rotation)
+        } else {
+            Placement::identity()
+        };
+
+        parts.push(ScenePart {
+            name: format!("{}_{}", component.component_type, component.uuid),
+            material: material.to_string(),
+            color: color.to_string(),
+            mesh: mesh.transformed(&placement, scale),
+        });
+    }
+
+    Ok(parts)
+}
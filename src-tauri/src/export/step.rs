@@ -0,0 +1,575 @@
+use super::geometry::{component_box, CellShape, Placement};
+use super::{ExportLibrary, ExportOutput, ExportProgress, STEPExportOptions};
+use crate::filesystem::{CellInstance, Component, ProjectFile};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+
+/// Entity ids of the shared AP214 contexts every product refers to.
+struct Contexts {
+    product: usize,
+    definition: usize,
+    geometry: usize,
+}
+
+/// A product with a shape, ready to be placed inside an assembly.
+#[derive(Clone, Copy)]
+struct Part {
+    definition: usize,
+    representation: usize,
+    origin: usize,
+}
+
+#[derive(Default)]
+struct StepWriter {
+    entities: Vec<String>,
+}
+
+impl StepWriter {
+    fn add(&mut self, entity: String) -> usize {
+        self.entities.push(entity);
+        self.entities.len()
+    }
+
+    fn point(&mut self, p: [f64; 3]) -> usize {
+        self.add(format!(
+            "CARTESIAN_POINT('',({},{},{}))",
+            real(p[0]),
+            real(p[1]),
+            real(p[2])
+        ))
+    }
+
+    fn direction(&mut self, d: [f64; 3]) -> usize {
+        self.add(format!(
+            "DIRECTION('',({},{},{}))",
+            real(d[0]),
+            real(d[1]),
+            real(d[2])
+        ))
+    }
+
+    fn axis_placement(&mut self, origin: [f64; 3], axis: [f64; 3], reference: [f64; 3]) -> usize {
+        let origin = self.point(origin);
+        let axis = self.direction(axis);
+        let reference = self.direction(reference);
+        self.add(format!(
+            "AXIS2_PLACEMENT_3D('',#{},#{},#{})",
+            origin, axis, reference
+        ))
+    }
+
+    fn vertex(&mut self, p: [f64; 3]) -> usize {
+        let point = self.point(p);
+        self.add(format!("VERTEX_POINT('',#{})", point))
+    }
+
+    fn line(&mut self, from: [f64; 3], to: [f64; 3]) -> usize {
+        let delta = [to[0] - from[0], to[1] - from[1], to[2] - from[2]];
+        let length = (delta[0] * delta[0] + delta[1] * delta[1] + delta[2] * delta[2]).sqrt();
+        let start = self.point(from);
+        let direction = self.direction([delta[0] / length, delta[1] / length, delta[2] / length]);
+        let vector = self.add(format!("VECTOR('',#{},{})", direction, real(length)));
+        self.add(format!("LINE('',#{},#{})", start, vector))
+    }
+
+    fn face(&mut self, surface: usize, edges: &[(usize, bool)]) -> usize {
+        let oriented: Vec<String> = edges
+            .iter()
+            .map(|&(edge, sense)| {
+                let id = self.add(format!("ORIENTED_EDGE('',*,*,#{},{})", edge, logical(sense)));
+                format!("#{}", id)
+            })
+            .collect();
+        let edge_loop = self.add(format!("EDGE_LOOP('',({}))", oriented.join(",")));
+        let bound = self.add(format!("FACE_OUTER_BOUND('',#{},.T.)", edge_loop));
+        self.add(format!("ADVANCED_FACE('',(#{}),#{},.T.)", bound, surface))
+    }
+
+    fn solid(&mut self, name: &str, faces: &[usize]) -> usize {
+        let refs: Vec<String> = faces.iter().map(|f| format!("#{}", f)).collect();
+        let shell = self.add(format!("CLOSED_SHELL('',({}))", refs.join(",")));
+        self.add(format!("MANIFOLD_SOLID_BREP({},#{})", string(name), shell))
+    }
+
+    /// Cylinder along +Y, centred on the origin like the viewport mesh.
+    fn cylinder(&mut self, name: &str, radius: f64, height: f64) -> usize {
+        let half = height / 2.0;
+        let up = [0.0, 1.0, 0.0];
+        let down = [0.0, -1.0, 0.0];
+        let x = [1.0, 0.0, 0.0];
+
+        let bottom_vertex = self.vertex([radius, -half, 0.0]);
+        let top_vertex = self.vertex([radius, half, 0.0]);
+
+        let bottom_axis = self.axis_placement([0.0, -half, 0.0], up, x);
+        let bottom_circle = self.add(format!("CIRCLE('',#{},{})", bottom_axis, real(radius)));
+        let bottom_edge = self.add(format!(
+            "EDGE_CURVE('',#{},#{},#{},.T.)",
+            bottom_vertex, bottom_vertex, bottom_circle
+        ));
+
+        let top_axis = self.axis_placement([0.0, half, 0.0], up, x);
+        let top_circle = self.add(format!("CIRCLE('',#{},{})", top_axis, real(radius)));
+        let top_edge = self.add(format!(
+            "EDGE_CURVE('',#{},#{},#{},.T.)",
+            top_vertex, top_vertex, top_circle
+        ));
+
+        let seam_line = self.line([radius, -half, 0.0], [radius, half, 0.0]);
+        let seam_edge = self.add(format!(
+            "EDGE_CURVE('',#{},#{},#{},.T.)",
+            bottom_vertex, top_vertex, seam_line
+        ));
+
+        let side_axis = self.axis_placement([0.0, -half, 0.0], up, x);
+        let side_surface = self.add(format!(
+            "CYLINDRICAL_SURFACE('',#{},{})",
+            side_axis,
+            real(radius)
+        ));
+        let side = self.face(
+            side_surface,
+            &[
+                (bottom_edge, true),
+                (seam_edge, true),
+                (top_edge, false),
+                (seam_edge, false),
+            ],
+        );
+
+        let top_plane_axis = self.axis_placement([0.0, half, 0.0], up, x);
+        let top_plane = self.add(format!("PLANE('',#{})", top_plane_axis));
+        let top = self.face(top_plane, &[(top_edge, true)]);
+
+        let bottom_plane_axis = self.axis_placement([0.0, -half, 0.0], down, x);
+        let bottom_plane = self.add(format!("PLANE('',#{})", bottom_plane_axis));
+        let bottom = self.face(bottom_plane, &[(bottom_edge, false)]);
+
+        self.solid(name, &[side, top, bottom])
+    }
+
+    /// Box of `width` (X) x `height` (Y) x `depth` (Z), centred on the origin.
+    fn cuboid(&mut self, name: &str, width: f64, height: f64, depth: f64) -> usize {
+        let half = [width / 2.0, height / 2.0, depth / 2.0];
+        let corner = |i: usize| {
+            [
+                if i & 4 != 0 { half[0] } else { -half[0] },
+                if i & 2 != 0 { half[1] } else { -half[1] },
+                if i & 1 != 0 { half[2] } else { -half[2] },
+            ]
+        };
+
+        // Corners counter-clockwise seen from outside, with the outward normal
+        let quads: [([usize; 4], [f64; 3]); 6] = [
+            ([4, 6, 7, 5], [1.0, 0.0, 0.0]),
+            ([0, 1, 3, 2], [-1.0, 0.0, 0.0]),
+            ([2, 3, 7, 6], [0.0, 1.0, 0.0]),
+            ([0, 4, 5, 1], [0.0, -1.0, 0.0]),
+            ([1, 5, 7, 3], [0.0, 0.0, 1.0]),
+            ([0, 2, 6, 4], [0.0, 0.0, -1.0]),
+        ];
+
+        let vertices: Vec<usize> = (0..8).map(|i| self.vertex(corner(i))).collect();
+        let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut faces = Vec::new();
+
+        for (quad, normal) in quads {
+            let mut loop_edges = Vec::new();
+            for k in 0..4 {
+                let (a, b) = (quad[k], quad[(k + 1) % 4]);
+                let key = (a.min(b), a.max(b));
+                let edge = match edges.get(&key) {
+                    Some(&edge) => edge,
+                    None => {
+                        let line = self.line(corner(key.0), corner(key.1));
+                        let edge = self.add(format!(
+                            "EDGE_CURVE('',#{},#{},#{},.T.)",
+                            vertices[key.0], vertices[key.1], line
+                        ));
+                        edges.insert(key, edge);
+                        edge
+                    }
+                };
+                loop_edges.push((edge, a < b));
+            }
+
+            let center = [
+                normal[0] * half[0],
+                normal[1] * half[1],
+                normal[2] * half[2],
+            ];
+            let p0 = corner(quad[0]);
+            let p1 = corner(quad[1]);
+            let reference = super::geometry::normalize(super::geometry::sub(p1, p0));
+            let plane_axis = self.axis_placement(center, normal, reference);
+            let plane = self.add(format!("PLANE('',#{})", plane_axis));
+            faces.push(self.face(plane, &loop_edges));
+        }
+
+        self.solid(name, &faces)
+    }
+
+    /// PRODUCT through SHAPE_DEFINITION_REPRESENTATION for one part or assembly.
+    fn product(
+        &mut self,
+        contexts: &Contexts,
+        id: &str,
+        name: &str,
+        items: &[usize],
+        brep: bool,
+    ) -> Part {
+        let product = self.add(format!(
+            "PRODUCT({},{},'',(#{}))",
+            string(id),
+            string(name),
+            contexts.product
+        ));
+        self.add(format!(
+            "PRODUCT_RELATED_PRODUCT_CATEGORY('part',$,(#{}))",
+            product
+        ));
+        let formation = self.add(format!(
+            "PRODUCT_DEFINITION_FORMATION('','',#{})",
+            product
+        ));
+        let definition = self.add(format!(
+            "PRODUCT_DEFINITION('design','',#{},#{})",
+            formation, contexts.definition
+        ));
+        let definition_shape = self.add(format!(
+            "PRODUCT_DEFINITION_SHAPE('','',#{})",
+            definition
+        ));
+
+        let origin = self.axis_placement([0.0; 3], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0]);
+        let mut refs = vec![format!("#{}", origin)];
+        refs.extend(items.iter().map(|i| format!("#{}", i)));
+        let representation = self.add(format!(
+            "{}({},({}),#{})",
+            if brep {
+                "ADVANCED_BREP_SHAPE_REPRESENTATION"
+            } else {
+                "SHAPE_REPRESENTATION"
+            },
+            string(name),
+            refs.join(","),
+            contexts.geometry
+        ));
+        self.add(format!(
+            "SHAPE_DEFINITION_REPRESENTATION(#{},#{})",
+            definition_shape, representation
+        ));
+
+        Part {
+            definition,
+            representation,
+            origin,
+        }
+    }
+
+    /// Places `child` inside `parent` at `placement` (an axis in the parent's representation).
+    fn occurrence(&mut self, id: &str, name: &str, parent: Part, child: Part, placement: usize) {
+        let usage = self.add(format!(
+            "NEXT_ASSEMBLY_USAGE_OCCURRENCE({},{},'',#{},#{},$)",
+            string(id),
+            string(name),
+            parent.definition,
+            child.definition
+        ));
+        let usage_shape = self.add(format!("PRODUCT_DEFINITION_SHAPE('','',#{})", usage));
+        let transformation = self.add(format!(
+            "ITEM_DEFINED_TRANSFORMATION('','',#{},#{})",
+            child.origin, placement
+        ));
+        let relationship = self.add(format!(
+            "(REPRESENTATION_RELATIONSHIP('','',#{},#{})REPRESENTATION_RELATIONSHIP_WITH_TRANSFORMATION(#{})SHAPE_REPRESENTATION_RELATIONSHIP())",
+            child.representation, parent.representation, transformation
+        ));
+        self.add(format!(
+            "CONTEXT_DEPENDENT_SHAPE_REPRESENTATION(#{},#{})",
+            relationship, usage_shape
+        ));
+    }
+
+    fn contexts(&mut self) -> Contexts {
+        let application = self.add("APPLICATION_CONTEXT('automotive design')".to_string());
+        self.add(format!(
+            "APPLICATION_PROTOCOL_DEFINITION('international standard','automotive_design',2000,#{})",
+            application
+        ));
+        let product = self.add(format!(
+            "PRODUCT_CONTEXT('',#{},'mechanical')",
+            application
+        ));
+        let definition = self.add(format!(
+            "PRODUCT_DEFINITION_CONTEXT('part definition',#{},'design')",
+            application
+        ));
+
+        let length = self.add("(LENGTH_UNIT()NAMED_UNIT(*)SI_UNIT(.MILLI.,.METRE.))".to_string());
+        let angle = self.add("(NAMED_UNIT(*)PLANE_ANGLE_UNIT()SI_UNIT($,.RADIAN.))".to_string());
+        let solid_angle = self.add("(NAMED_UNIT(*)SI_UNIT($,.STERADIAN.)SOLID_ANGLE_UNIT())".to_string());
+        let uncertainty = self.add(format!(
+            "UNCERTAINTY_MEASURE_WITH_UNIT(LENGTH_MEASURE(1.E-06),#{},'distance_accuracy_value','confusion accuracy')",
+            length
+        ));
+        let geometry = self.add(format!(
+            "(GEOMETRIC_REPRESENTATION_CONTEXT(3)GLOBAL_UNCERTAINTY_ASSIGNED_CONTEXT((#{}))GLOBAL_UNIT_ASSIGNED_CONTEXT((#{},#{},#{}))REPRESENTATION_CONTEXT('Context #1','3D Context with UNIT and UNCERTAINTY'))",
+            uncertainty, length, angle, solid_angle
+        ));
+
+        Contexts {
+            product,
+            definition,
+            geometry,
+        }
+    }
+}
+
+/// Builds an ISO 10303-21 file using the AP214 (automotive_design) schema.
+///
+/// Every distinct `cell_id` becomes one part with an exact B-rep solid, and
+/// each `CellInstance` is an occurrence of that part. With
+/// `group_assemblies`, `Scene.groups` become sub-assemblies of the pack.
+/// Components are written as their bounding boxes, placed in the pack root.
+pub fn write(
+    project: &ProjectFile,
+    library: &ExportLibrary,
+    options: &STEPExportOptions,
+    progress: &dyn ExportProgress,
+) -> Result<ExportOutput, String> {
+    let scene = &project.scene;
+    let mut writer = StepWriter::default();
+    let mut warnings = Vec::new();
+    let contexts = writer.contexts();
+
+    let mut cells: Vec<&CellInstance> = scene
+        .cells
+        .values()
+        .filter(|cell| options.selection.includes(&cell.uuid))
+        .collect();
+    cells.sort_by(|a, b| a.uuid.cmp(&b.uuid));
+
+    // One part per library cell
+    let mut parts: BTreeMap<i64, Option<Part>> = BTreeMap::new();
+    for cell in &cells {
+        if parts.contains_key(&cell.cell_id) {
+            continue;
+        }
+        let Some(spec) = library.cells.get(&cell.cell_id) else {
+            warnings.push(format!("Cell {} is not in the library and was skipped", cell.cell_id));
+            parts.insert(cell.cell_id, None);
+            continue;
+        };
+
+        let name = format!("{} {}", spec.manufacturer, spec.model);
+        let solid = match CellShape::from_cell(spec) {
+            Some(CellShape::Cylinder { diameter, length }) => {
+                writer.cylinder(&name, diameter / 2.0, length)
+            }
+            Some(CellShape::Box {
+                width,
+                height,
+                length,
+            }) => writer.cuboid(&name, width, height, length),
+            None => {
+                warnings.push(format!(
+                    "Cell {} has no usable dimensions in the library and was skipped",
+                    name
+                ));
+                parts.insert(cell.cell_id, None);
+                continue;
+            }
+        };
+        let part = writer.product(&contexts, &spec.model, &name, &[solid], true);
+        parts.insert(cell.cell_id, Some(part));
+    }
+
+    // Placements are items of the parent representation, so they are written
+    // before the parent product and referenced from its item list.
+    let cell_group = super::resolve_cell_groups(scene);
+    let mut placements: BTreeMap<Option<&str>, Vec<(&CellInstance, Part, usize)>> =
+        BTreeMap::new();
+    for (index, cell) in cells.iter().enumerate() {
+        super::report_geometry(progress, index, cells.len())?;
+        let Some(part) = parts[&cell.cell_id] else {
+            continue;
+        };
+        let placement = Placement::new(&cell.position, &cell.rotation);
+        let axis = writer.axis_placement(placement.translation, placement.axis(2), placement.axis(0));
+        let group = if options.group_assemblies {
+            cell_group.get(cell.uuid.as_str()).copied()
+        } else {
+            None
+        };
+        placements.entry(group).or_default().push((cell, part, axis));
+    }
+
+    let components =
+        write_components(project, library, options, &contexts, &mut writer, &mut warnings);
+
+    let mut root_items: Vec<usize> = components.iter().map(|(_, _, axis)| *axis).collect();
+    let mut groups = Vec::new();
+    for (group_key, members) in &placements {
+        let Some(group_key) = group_key else {
+            root_items.extend(members.iter().map(|(_, _, axis)| *axis));
+            continue;
+        };
+        let group = &scene.groups[*group_key];
+        let items: Vec<usize> = members.iter().map(|(_, _, axis)| *axis).collect();
+        let assembly = writer.product(&contexts, &group.uuid, &group.name, &items, false);
+        let placement = writer.axis_placement([0.0; 3], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0]);
+        root_items.push(placement);
+        groups.push((group, assembly, placement, members));
+    }
+
+    let root = writer.product(
+        &contexts,
+        &project.metadata.name,
+        &project.metadata.name,
+        &root_items,
+        false,
+    );
+
+    for (group, assembly, placement, members) in groups {
+        writer.occurrence(&group.uuid, &group.name, root, assembly, placement);
+        for (cell, part, axis) in members {
+            writer.occurrence(&cell.uuid, &occurrence_name(cell), assembly, *part, *axis);
+        }
+    }
+    if let Some(members) = placements.get(&None) {
+        for (cell, part, axis) in members {
+            writer.occurrence(&cell.uuid, &occurrence_name(cell), root, *part, *axis);
+        }
+    }
+    for (component, part, axis) in components {
+        let name = format!("{} {}", component.component_type, component.uuid);
+        writer.occurrence(&component.uuid, &name, root, part, axis);
+    }
+
+    super::report_encoding(progress)?;
+    Ok(ExportOutput {
+        data: render(project, options, &writer).into_bytes(),
+        warnings,
+        ..ExportOutput::default()
+    })
+}
+
+/// One part per selected component, a box the size the viewport draws, with
+/// its placement in the pack root.
+fn write_components<'a>(
+    project: &'a ProjectFile,
+    library: &ExportLibrary,
+    options: &STEPExportOptions,
+    contexts: &Contexts,
+    writer: &mut StepWriter,
+    warnings: &mut Vec<String>,
+) -> Vec<(&'a Component, Part, usize)> {
+    if !options.selection.includes_components() {
+        return Vec::new();
+    }
+    let mut components: Vec<&Component> = project
+        .scene
+        .components
+        .values()
+        .filter(|c| options.selection.includes(&c.uuid))
+        .collect();
+    components.sort_by(|a, b| a.uuid.cmp(&b.uuid));
+
+    let mut placed = Vec::new();
+    for component in components {
+        let bms = match component.component_type.as_str() {
+            "bms" => component.reference_id.and_then(|id| library.bms.get(&id)),
+            _ => None,
+        };
+        if bms.is_none() {
+            warnings.push(format!(
+                "Component {} was exported as a placeholder box",
+                component.uuid
+            ));
+        }
+
+        let name = match bms {
+            Some(bms) => format!("{} {}", bms.manufacturer, bms.model),
+            None => component.component_type.clone(),
+        };
+        let size = component_box(&component.component_type, bms);
+        let s = component.scale.0;
+        let solid = writer.cuboid(&name, size[0] * s[0], size[1] * s[1], size[2] * s[2]);
+        let part = writer.product(contexts, &component.uuid, &name, &[solid], true);
+
+        let placement = Placement::new(&component.position, &component.rotation);
+        let axis = writer.axis_placement(placement.translation, placement.axis(2), placement.axis(0));
+        placed.push((component, part, axis));
+    }
+    placed
+}
+
+fn occurrence_name(cell: &CellInstance) -> String {
+    cell.custom_label.clone().unwrap_or_else(|| cell.uuid.clone())
+}
+
+fn render(project: &ProjectFile, options: &STEPExportOptions, writer: &StepWriter) -> String {
+    let mut out = String::new();
+    let author = project.metadata.author.as_deref().unwrap_or("");
+
+    out.push_str("ISO-10303-21;\nHEADER;\n");
+    let _ = writeln!(
+        out,
+        "FILE_DESCRIPTION(({}),'2;1');",
+        string(&format!("CellForge battery pack: {}", project.metadata.name))
+    );
+    let _ = writeln!(
+        out,
+        "FILE_NAME({},{},({}),(''),'CellForge','CellForge','');",
+        string(&options.file_name),
+        string(&project.metadata.modified),
+        string(author)
+    );
+    out.push_str("FILE_SCHEMA(('AUTOMOTIVE_DESIGN { 1 0 10303 214 1 1 1 1 }'));\nENDSEC;\nDATA;\n");
+    for (index, entity) in writer.entities.iter().enumerate() {
+        let _ = writeln!(out, "#{}={};", index + 1, entity);
+    }
+    out.push_str("ENDSEC;\nEND-ISO-10303-21;\n");
+    out
+}
+
+/// STEP real: always has a decimal point and never uses Rust's `e` notation.
+fn real(value: f64) -> String {
+    let value = if value.abs() < 1e-12 { 0.0 } else { value };
+    let mut text = format!("{:.10}", value);
+    while text.ends_with('0') {
+        text.pop();
+    }
+    text
+}
+
+fn logical(value: bool) -> &'static str {
+    if value {
+        ".T."
+    } else {
+        ".F."
+    }
+}
+
+/// Quoted STEP string, with non-ASCII characters in `\X2\` form.
+fn string(value: &str) -> String {
+    let mut out = String::from("'");
+    for c in value.chars() {
+        match c {
+            '\'' => out.push_str("''"),
+            '\\' => out.push_str("\\\\"),
+            ' '..='~' => out.push(c),
+            _ => {
+                let mut units = [0u16; 2];
+                out.push_str("\\X2\\");
+                for unit in c.encode_utf16(&mut units) {
+                    let _ = write!(out, "{:04X}", unit);
+                }
+                out.push_str("\\X0\\");
+            }
+        }
+    }
+    out.push('\'');
+    out
+}
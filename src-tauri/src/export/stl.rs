@@ -0,0 +1,112 @@
+use super::geometry::{cross, normalize, sub};
+use super::parts::{self, ScenePart};
+use super::{ExportLibrary, ExportOutput, ExportProgress, STLExportOptions};
+use crate::filesystem::ProjectFile;
+use std::fmt::Write as _;
+
+/// Builds an STL file from the selected cells and components.
+///
+/// Merged geometry is written as a single binary STL body. Binary STL has no
+/// notion of separate objects, so unmerged exports use ASCII STL with one
+/// `solid` block per cell or component.
+pub fn write(
+    project: &ProjectFile,
+    library: &ExportLibrary,
+    options: &STLExportOptions,
+    progress: &dyn ExportProgress,
+) -> Result<ExportOutput, String> {
+    let mut warnings = Vec::new();
+    let mut parts = parts::collect(
+        project,
+        library,
+        &options.selection,
+        options.apply_transforms,
+        options.scale,
+        &mut warnings,
+        progress,
+    )?;
+
+    if parts.is_empty() {
+        return Err("Nothing to export for the current selection".to_string());
+    }
+    // Shared corners come out with identical coordinates, so slicers see
+    // closed bodies instead of hairline cracks along seams
+    for part in &mut parts {
+        part.mesh = part.mesh.welded();
+    }
+
+    super::report_encoding(progress)?;
+    let data = if options.merge_geometries {
+        binary(&project.metadata.name, &parts)
+    } else {
+        ascii(&parts).into_bytes()
+    };
+
+    Ok(ExportOutput {
+        data,
+        warnings,
+        ..ExportOutput::default()
+    })
+}
+
+fn triangles(part: &ScenePart) -> impl Iterator<Item = ([f32; 3], [[f32; 3]; 3])> + '_ {
+    let mesh = &part.mesh;
+    mesh.indices.chunks(3).map(move |tri| {
+        let v = [
+            mesh.positions[tri[0] as usize],
+            mesh.positions[tri[1] as usize],
+            mesh.positions[tri[2] as usize],
+        ];
+        let to64 = |p: [f32; 3]| [p[0] as f64, p[1] as f64, p[2] as f64];
+        let n = normalize(cross(
+            sub(to64(v[1]), to64(v[0])),
+            sub(to64(v[2]), to64(v[0])),
+        ));
+        ([n[0] as f32, n[1] as f32, n[2] as f32], v)
+    })
+}
+
+fn binary(name: &str, parts: &[ScenePart]) -> Vec<u8> {
+    let count: usize = parts.iter().map(|p| p.mesh.indices.len() / 3).sum();
+    let mut data = Vec::with_capacity(84 + count * 50);
+
+    // 80-byte header; must not start with "solid" or readers take it for ASCII
+    let mut header = format!("CellForge binary STL: {}", name).into_bytes();
+    header.resize(80, 0);
+    data.extend_from_slice(&header);
+    data.extend_from_slice(&(count as u32).to_le_bytes());
+
+    for part in parts {
+        for (normal, vertices) in triangles(part) {
+            for value in normal.iter().chain(vertices.iter().flatten()) {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            data.extend_from_slice(&0u16.to_le_bytes());
+        }
+    }
+
+    data
+}
+
+fn ascii(parts: &[ScenePart]) -> String {
+    let mut out = String::new();
+    for part in parts {
+        let name: String = part
+            .name
+            .chars()
+            .map(|c| if c.is_whitespace() { '_' } else { c })
+            .collect();
+        let _ = writeln!(out, "solid {}", name);
+        for (n, v) in triangles(part) {
+            let _ = writeln!(out, "  facet normal {:e} {:e} {:e}", n[0], n[1], n[2]);
+            let _ = writeln!(out, "    outer loop");
+            for p in v {
+                let _ = writeln!(out, "      vertex {:e} {:e} {:e}", p[0], p[1], p[2]);
+            }
+            let _ = writeln!(out, "    endloop");
+            let _ = writeln!(out, "  endfacet");
+        }
+        let _ = writeln!(out, "endsolid {}", name);
+    }
+    out
+}
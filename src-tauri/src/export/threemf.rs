@@ -0,0 +1,216 @@
+use super::parts::{self, ScenePart};
+use super::{ExportLibrary, ExportOutput, ExportProgress, ThreeMFExportOptions};
+use crate::filesystem::ProjectFile;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::io::{Cursor, Write};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+  <Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+  <Default Extension="model" ContentType="application/vnd.ms-package.3dmanufacturing-3dmodel+xml"/>
+</Types>
+"#;
+
+const RELATIONSHIPS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+  <Relationship Target="/3D/3dmodel.model" Id="rel0" Type="http://schemas.microsoft.com/3dmanufacturing/2013/01/3dmodel"/>
+</Relationships>
+"#;
+
+const NEUTRAL_COLOR: &str = "#808080";
+
+/// Builds a 3MF package (zip) from the selected cells and components.
+///
+/// The scene is Y-up like the viewport; 3MF build plates are Z-up, so
+/// vertices are rotated into the printer's frame on the way out.
+pub fn write(
+    project: &ProjectFile,
+    library: &ExportLibrary,
+    options: &ThreeMFExportOptions,
+    progress: &dyn ExportProgress,
+) -> Result<ExportOutput, String> {
+    let mut warnings = Vec::new();
+    let mut parts = parts::collect(project, library, &options.selection, true, 1.0, &mut warnings, progress)?;
+    if parts.is_empty() {
+        return Err("Nothing to export for the current selection".to_string());
+    }
+    // The 3MF core spec requires every object to be manifold by vertex index
+    for part in &mut parts {
+        part.mesh = part.mesh.welded();
+    }
+
+    super::report_encoding(progress)?;
+    let model = model_xml(project, options, &parts);
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let file_options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, contents) in [
+        ("[Content_Types].xml", CONTENT_TYPES.as_bytes()),
+        ("_rels/.rels", RELATIONSHIPS.as_bytes()),
+        ("3D/3dmodel.model", model.as_bytes()),
+    ] {
+        zip.start_file(name, file_options)
+            .map_err(|e| format!("Failed to write 3MF package: {}", e))?;
+        zip.write_all(contents)
+            .map_err(|e| format!("Failed to write 3MF package: {}", e))?;
+    }
+    let data = zip
+        .finish()
+        .map_err(|e| format!("Failed to finish 3MF package: {}", e))?
+        .into_inner();
+
+    Ok(ExportOutput {
+        data,
+        warnings,
+        ..ExportOutput::default()
+    })
+}
+
+fn model_xml(project: &ProjectFile, options: &ThreeMFExportOptions, parts: &[ScenePart]) -> String {
+    let with_materials = options.include_colors || options.include_materials;
+
+    // One base material per material name and colour
+    let mut materials: BTreeMap<String, &str> = BTreeMap::new();
+    let mut material_names: HashMap<(&str, &str), String> = HashMap::new();
+    for part in parts {
+        material_names
+            .entry((&part.material, &part.color))
+            .or_insert_with(|| {
+                let name =
+                    parts::unique_material_name(&materials, part.material.clone(), &part.color);
+                materials.insert(name.clone(), &part.color);
+                name
+            });
+    }
+    let material_position: BTreeMap<&str, usize> = materials
+        .keys()
+        .enumerate()
+        .map(|(i, name)| (name.as_str(), i))
+        .collect();
+    let material_index = |part: &ScenePart| {
+        material_position[material_names[&(part.material.as_str(), part.color.as_str())].as_str()]
+    };
+
+    // Y-up scene to Z-up build plate, optionally dropped onto the origin
+    let to_plate = |p: [f32; 3]| [p[0] as f64, -(p[2] as f64), p[1] as f64];
+    let mut offset = [0.0f64; 3];
+    if options.build_plate_origin {
+        let mut min = [f64::INFINITY; 3];
+        for p in parts.iter().flat_map(|part| part.mesh.positions.iter()) {
+            let p = to_plate(*p);
+            for axis in 0..3 {
+                min[axis] = min[axis].min(p[axis]);
+            }
+        }
+        offset = [-min[0], -min[1], -min[2]];
+    }
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<model unit=\"millimeter\" xml:lang=\"en-US\" xmlns=\"http://schemas.microsoft.com/3dmanufacturing/core/2015/02\">\n");
+    let _ = writeln!(xml, "  <metadata name=\"Title\">{}</metadata>", escape(&project.metadata.name));
+    if let Some(author) = &project.metadata.author {
+        let _ = writeln!(xml, "  <metadata name=\"Designer\">{}</metadata>", escape(author));
+    }
+    let _ = writeln!(xml, "  <metadata name=\"Application\">CellForge</metadata>");
+    xml.push_str("  <resources>\n");
+
+    if with_materials {
+        xml.push_str("    <basematerials id=\"1\">\n");
+        for (name, color) in &materials {
+            let color = if options.include_colors { color } else { NEUTRAL_COLOR };
+            let _ = writeln!(
+                xml,
+                "      <base name=\"{}\" displaycolor=\"{}\"/>",
+                escape(name),
+                color.to_uppercase()
+            );
+        }
+        xml.push_str("    </basematerials>\n");
+    }
+
+    // (object id, name, parts) — one object per part or one merged object
+    let groups: Vec<(String, Vec<&ScenePart>)> = if options.separate_objects {
+        parts.iter().map(|p| (p.name.clone(), vec![p])).collect()
+    } else {
+        vec![(project.metadata.name.clone(), parts.iter().collect())]
+    };
+
+    let mut object_ids = Vec::new();
+    for (index, (name, members)) in groups.iter().enumerate() {
+        let id = index + 2;
+        object_ids.push(id);
+
+        let first_material = members
+            .first()
+            .map(|p| material_index(p))
+            .unwrap_or(0);
+        if with_materials {
+            let _ = writeln!(
+                xml,
+                "    <object id=\"{}\" type=\"model\" name=\"{}\" pid=\"1\" pindex=\"{}\">",
+                id,
+                escape(name),
+                first_material
+            );
+        } else {
+            let _ = writeln!(xml, "    <object id=\"{}\" type=\"model\" name=\"{}\">", id, escape(name));
+        }
+
+        xml.push_str("      <mesh>\n        <vertices>\n");
+        for part in members {
+            for p in &part.mesh.positions {
+                let p = to_plate(*p);
+                let _ = writeln!(
+                    xml,
+                    "          <vertex x=\"{}\" y=\"{}\" z=\"{}\"/>",
+                    (p[0] + offset[0]) as f32,
+                    (p[1] + offset[1]) as f32,
+                    (p[2] + offset[2]) as f32
+                );
+            }
+        }
+        xml.push_str("        </vertices>\n        <triangles>\n");
+        let mut base = 0usize;
+        for part in members {
+            let material = material_index(part);
+            for tri in part.mesh.indices.chunks(3) {
+                let (a, b, c) = (
+                    tri[0] as usize + base,
+                    tri[1] as usize + base,
+                    tri[2] as usize + base,
+                );
+                if with_materials && material != first_material {
+                    let _ = writeln!(
+                        xml,
+                        "          <triangle v1=\"{}\" v2=\"{}\" v3=\"{}\" pid=\"1\" p1=\"{}\"/>",
+                        a, b, c, material
+                    );
+                } else {
+                    let _ = writeln!(xml, "          <triangle v1=\"{}\" v2=\"{}\" v3=\"{}\"/>", a, b, c);
+                }
+            }
+            base += part.mesh.positions.len();
+        }
+        xml.push_str("        </triangles>\n      </mesh>\n    </object>\n");
+    }
+
+    xml.push_str("  </resources>\n  <build>\n");
+    for id in object_ids {
+        let _ = writeln!(xml, "    <item objectid=\"{}\"/>", id);
+    }
+    xml.push_str("  </build>\n</model>\n");
+    xml
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
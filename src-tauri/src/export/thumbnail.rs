@@ -0,0 +1,121 @@
+use super::parts::{self, ScenePart};
+use super::{parse_hex_color, ExportLibrary, ExportSelection};
+use crate::filesystem::ProjectFile;
+
+const BACKGROUND: [u8; 3] = [0xf3, 0xf4, 0xf6];
+const PADDING: f64 = 8.0;
+
+/// Renders a top-down (looking along -Y) view of the scene as a square PNG,
+/// +X to the right and +Z downwards, lit from above.
+pub fn render(project: &ProjectFile, library: &ExportLibrary, size: u32) -> Result<Vec<u8>, String> {
+    let mut warnings = Vec::new();
+    let parts = parts::collect(project, library, &ExportSelection::All, true, 1.0, &mut warnings, &())?;
+
+    let size = size.max(16) as usize;
+    let mut pixels = vec![BACKGROUND; size * size];
+    let mut depth = vec![f32::NEG_INFINITY; size * size];
+
+    if let Some(bounds) = bounds(&parts) {
+        let [min_x, min_z, max_x, max_z] = bounds;
+        let extent = (max_x - min_x).max(max_z - min_z).max(1e-6);
+        let scale = (size as f64 - 2.0 * PADDING) / extent;
+        // Centre the drawing within the square
+        let offset_x = (size as f64 - (max_x - min_x) * scale) / 2.0;
+        let offset_z = (size as f64 - (max_z - min_z) * scale) / 2.0;
+        let project_point = |p: [f32; 3]| {
+            (
+                (p[0] as f64 - min_x) * scale + offset_x,
+                (p[2] as f64 - min_z) * scale + offset_z,
+                p[1],
+            )
+        };
+
+        for part in &parts {
+            let color = parse_hex_color(&part.color).unwrap_or([0.6, 0.6, 0.6]);
+            for triangle in part.mesh.indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize);
+                let normal_y = (part.mesh.normals[a][1] + part.mesh.normals[b][1] + part.mesh.normals[c][1]) / 3.0;
+                let shade = 0.45 + 0.55 * normal_y.abs() as f64;
+                let rgb = color.map(|channel| (channel * shade * 255.0).round().clamp(0.0, 255.0) as u8);
+
+                let vertices = [a, b, c].map(|i| project_point(part.mesh.positions[i]));
+                fill_triangle(&mut pixels, &mut depth, size, vertices, rgb);
+            }
+        }
+    }
+
+    encode_png(&pixels, size as u32)
+}
+
+/// X/Z extent of all geometry: `[min_x, min_z, max_x, max_z]`.
+fn bounds(parts: &[ScenePart]) -> Option<[f64; 4]> {
+    parts
+        .iter()
+        .flat_map(|part| part.mesh.positions.iter())
+        .fold(None, |bounds: Option<[f64; 4]>, p| {
+            let (x, z) = (p[0] as f64, p[2] as f64);
+            Some(match bounds {
+                None => [x, z, x, z],
+                Some([min_x, min_z, max_x, max_z]) => [min_x.min(x), min_z.min(z), max_x.max(x), max_z.max(z)],
+            })
+        })
+}
+
+/// Fills the pixels whose centres fall inside the triangle, keeping the
+/// highest surface per pixel.
+fn fill_triangle(
+    pixels: &mut [[u8; 3]],
+    depth: &mut [f32],
+    size: usize,
+    [(x0, y0, h0), (x1, y1, h1), (x2, y2, h2)]: [(f64, f64, f32); 3],
+    rgb: [u8; 3],
+) {
+    let area = (x1 - x0) * (y2 - y0) - (x2 - x0) * (y1 - y0);
+    if area.abs() < 1e-12 {
+        return;
+    }
+
+    let last = size as f64 - 1.0;
+    let min_x = x0.min(x1).min(x2).floor().clamp(0.0, last) as usize;
+    let max_x = x0.max(x1).max(x2).ceil().clamp(0.0, last) as usize;
+    let min_y = y0.min(y1).min(y2).floor().clamp(0.0, last) as usize;
+    let max_y = y0.max(y1).max(y2).ceil().clamp(0.0, last) as usize;
+
+    for py in min_y..=max_y {
+        for px in min_x..=max_x {
+            let (x, y) = (px as f64 + 0.5, py as f64 + 0.5);
+            let w0 = ((x1 - x) * (y2 - y) - (x2 - x) * (y1 - y)) / area;
+            let w1 = ((x2 - x) * (y0 - y) - (x0 - x) * (y2 - y)) / area;
+            let w2 = 1.0 - w0 - w1;
+            if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                continue;
+            }
+
+            let height = (w0 * h0 as f64 + w1 * h1 as f64 + w2 * h2 as f64) as f32;
+            let index = py * size + px;
+            if height > depth[index] {
+                depth[index] = height;
+                pixels[index] = rgb;
+            }
+        }
+    }
+}
+
+fn encode_png(pixels: &[[u8; 3]], size: u32) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    let mut encoder = png::Encoder::new(&mut data, size, size);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder
+        .write_header()
+        .map_err(|e| format!("Failed to encode thumbnail: {}", e))?;
+    writer
+        .write_image_data(pixels.as_flattened())
+        .map_err(|e| format!("Failed to encode thumbnail: {}", e))?;
+    writer
+        .finish()
+        .map_err(|e| format!("Failed to encode thumbnail: {}", e))?;
+
+    Ok(data)
+}
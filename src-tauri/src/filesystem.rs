@@ -1,17 +1,54 @@
+use crate::error::FilesystemError;
+use crate::paths::AppPaths;
+use crate::recovery::{self, RecoveredProject};
 use flate2::{write::GzEncoder, read::GzDecoder, Compression};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::io::{Read, Write};
-use std::path::Path;
-use tauri::{AppHandle, Manager};
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
+// Previous versions kept next to a project as `<file>.bak.1` (newest) to `.bak.N`
+const BACKUP_COUNT: usize = 3;
+
+// Integrity trailer appended after the gzip stream: magic, payload length
+// (u64 LE) and CRC32 of the payload (u32 LE). Gzip readers ignore it.
+const TRAILER_MAGIC: &[u8; 4] = b"CFCK";
+const TRAILER_LEN: usize = 16;
+
+const GZIP_MAGIC: &[u8; 2] = &[0x1f, 0x8b];
+
+/// Project format written by this version. Files with a newer major version
+/// are refused rather than half-read.
+pub const PROJECT_VERSION: &str = "1.0.0";
+
+/// How `save_project` writes a project. Loading detects either.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SaveFormat {
+    /// Gzip-compressed JSON with an integrity trailer
+    #[default]
+    Compressed,
+    /// Canonical uncompressed JSON that diffs well under version control
+    Plain,
+}
+
+/// Result of checking a project file against its integrity trailer.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumStatus {
+    Valid,
+    Mismatch,
+    /// Written before the trailer existed, or cut off before it
+    Missing,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Vector3([f64; 3]);
+pub struct Vector3(pub [f64; 3]);
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Euler([f64; 3]);
+pub struct Euler(pub [f64; 3]);
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Transform {
@@ -24,6 +61,9 @@ pub struct Transform {
 pub struct CellInstance {
     pub uuid: String,
     pub cell_id: i64,
+    /// Stable library key of `cell_id`, e.g. `samsung/30q`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cell_key: Option<String>,
     pub position: Vector3,
     pub rotation: Euler,
     pub custom_label: Option<String>,
@@ -39,6 +79,8 @@ pub struct Connection {
     pub target_uuid: String,
     pub target_terminal: String, // "positive" | "negative"
     pub material_id: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material_key: Option<String>,
     pub path: Option<Vec<Vector3>>,
 }
 
@@ -47,6 +89,8 @@ pub struct Component {
     pub uuid: String,
     pub component_type: String, // "bms" | "shape" | "custom"
     pub reference_id: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference_key: Option<String>,
     pub position: Vector3,
     pub rotation: Euler,
     pub scale: Vector3,
@@ -104,61 +148,159 @@ pub struct ProjectFile {
 }
 
 pub struct Filesystem {
-    app: AppHandle,
+    paths: Option<AppPaths>,
 }
 
 impl Filesystem {
-    pub fn new(app: AppHandle) -> Self {
-        Filesystem { app }
+    pub fn new(paths: AppPaths) -> Self {
+        Filesystem { paths: Some(paths) }
+    }
+
+    /// Filesystem for use outside the app, e.g. from the command line.
+    /// Projects load and save as usual; there is no app data or resource
+    /// directory.
+    pub fn headless() -> Self {
+        Filesystem { paths: None }
     }
 
-    pub fn save_project(&self, project: &ProjectFile, path: &Path) -> Result<(), String> {
+    pub fn save_project(&self, project: &ProjectFile, path: &Path, format: SaveFormat) -> Result<(), FilesystemError> {
         // Create directory if it doesn't exist
         if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory: {}", e))?;
+            fs::create_dir_all(parent).map_err(FilesystemError::io("Failed to create directory", parent))?;
         }
 
+        let data = match format {
+            SaveFormat::Compressed => self.encode_project(project)?,
+            SaveFormat::Plain => self.encode_project_plain(project)?,
+        };
+
+        // Only replace the existing file once the new one reads back intact
+        self.write_atomic_with(&data, path, |temp_path| {
+            self.load_project(temp_path).map_err(|e| FilesystemError::Corrupt {
+                path: path.to_path_buf(),
+                reason: format!("saved file failed verification: {}", e),
+            })?;
+            rotate_backups(path)
+        })
+    }
+
+    /// Serializes a project to the gzip-compressed `.cellforge` format.
+    pub fn encode_project(&self, project: &ProjectFile) -> Result<Vec<u8>, FilesystemError> {
         // Serialize to JSON
         let json = serde_json::to_string_pretty(project)
-            .map_err(|e| format!("Failed to serialize project: {}", e))?;
+            .map_err(|e| FilesystemError::Serialization(format!("Failed to serialize project: {}", e)))?;
 
         // Compress with gzip
         let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
         encoder.write_all(json.as_bytes())
-            .map_err(|e| format!("Failed to compress data: {}", e))?;
-        let compressed = encoder.finish()
-            .map_err(|e| format!("Failed to finish compression: {}", e))?;
+            .map_err(|e| FilesystemError::Serialization(format!("Failed to compress data: {}", e)))?;
+        let mut data = encoder.finish()
+            .map_err(|e| FilesystemError::Serialization(format!("Failed to finish compression: {}", e)))?;
 
-        // Write to file
-        fs::write(path, compressed)
-            .map_err(|e| format!("Failed to write file: {}", e))?;
-
-        Ok(())
+        let checksum = crc32fast::hash(&data);
+        let length = data.len() as u64;
+        data.extend_from_slice(TRAILER_MAGIC);
+        data.extend_from_slice(&length.to_le_bytes());
+        data.extend_from_slice(&checksum.to_le_bytes());
+        Ok(data)
     }
 
-    pub fn load_project(&self, path: &Path) -> Result<ProjectFile, String> {
-        // Read compressed file
-        let compressed = fs::read(path)
-            .map_err(|e| format!("Failed to read file: {}", e))?;
+    /// Serializes a project to canonical plain JSON: object keys sorted,
+    /// floats in shortest round-trip form with `-0.0` written as `0.0`, and a
+    /// trailing newline. The same project always gives the same bytes.
+    pub fn encode_project_plain(&self, project: &ProjectFile) -> Result<Vec<u8>, FilesystemError> {
+        let value = serde_json::to_value(project)
+            .map_err(|e| FilesystemError::Serialization(format!("Failed to serialize project: {}", e)))?;
 
-        // Decompress
-        let mut decoder = GzDecoder::new(&compressed[..]);
         let mut json = String::new();
-        decoder.read_to_string(&mut json)
-            .map_err(|e| format!("Failed to decompress data: {}", e))?;
+        write_canonical(&value, 0, &mut json);
+        json.push('\n');
+        Ok(json.into_bytes())
+    }
+
+    /// Format of an existing project file, if there is one.
+    pub fn detect_format(&self, path: &Path) -> Option<SaveFormat> {
+        let mut magic = [0u8; 2];
+        fs::File::open(path).ok()?.read_exact(&mut magic).ok()?;
+        Some(if is_gzip(&magic) {
+            SaveFormat::Compressed
+        } else {
+            SaveFormat::Plain
+        })
+    }
+
+    pub fn load_project(&self, path: &Path) -> Result<ProjectFile, FilesystemError> {
+        let data = fs::read(path)
+            .map_err(FilesystemError::io("Failed to read file", path))?;
+        let corrupt = |reason: String| FilesystemError::Corrupt {
+            path: path.to_path_buf(),
+            reason,
+        };
+
+        let json = if is_gzip(&data) {
+            let (compressed, checksum) = split_trailer(&data);
+            if checksum == ChecksumStatus::Mismatch {
+                return Err(corrupt("checksum mismatch".to_string()));
+            }
 
-        // Deserialize from JSON
-        let project: ProjectFile = serde_json::from_str(&json)
-            .map_err(|e| format!("Failed to deserialize project: {}", e))?;
+            // Decompress
+            let mut decoder = GzDecoder::new(compressed);
+            let mut json = String::new();
+            decoder.read_to_string(&mut json)
+                .map_err(|e| corrupt(format!("failed to decompress data: {}", e)))?;
+            json
+        } else {
+            String::from_utf8(data)
+                .map_err(|e| corrupt(format!("not gzip or UTF-8 JSON: {}", e)))?
+        };
+
+        let value: serde_json::Value = serde_json::from_str(&json)
+            .map_err(|e| corrupt(format!("invalid JSON: {}", e)))?;
+
+        // A newer major version may not mean what this version thinks it does
+        if let Some(version) = value.get("version").and_then(|v| v.as_str()) {
+            if major_version(version) > major_version(PROJECT_VERSION) {
+                return Err(FilesystemError::VersionTooNew {
+                    path: path.to_path_buf(),
+                    version: version.to_string(),
+                    supported: PROJECT_VERSION.to_string(),
+                });
+            }
+        }
+
+        // Deserialize, reporting where it failed
+        let project: ProjectFile = serde_path_to_error::deserialize(value)
+            .map_err(|e| corrupt(format!("invalid project at {}: {}", e.path(), e.inner())))?;
 
         Ok(project)
     }
 
+    /// Loads whatever still parses from a damaged project file.
+    ///
+    /// Broken scene entries are dropped one by one and listed in the report;
+    /// unreadable metadata, settings or camera fall back to defaults.
+    pub fn recover_project(&self, path: &Path) -> Result<RecoveredProject, FilesystemError> {
+        let data = fs::read(path)
+            .map_err(FilesystemError::io("Failed to read file", path))?;
+
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("Recovered project")
+            .to_string();
+        recovery::salvage(&data, self.create_new_project(name)).map_err(|reason| {
+            FilesystemError::Corrupt {
+                path: path.to_path_buf(),
+                reason,
+            }
+        })
+    }
+
     pub fn create_new_project(&self, name: String) -> ProjectFile {
         let now = chrono::Utc::now().to_rfc3339();
 
         ProjectFile {
-            version: "1.0.0".to_string(),
+            version: PROJECT_VERSION.to_string(),
             metadata: ProjectMetadata {
                 name,
                 created: now.clone(),
@@ -185,30 +327,231 @@ impl Filesystem {
         }
     }
 
-    pub fn get_autosave_path(&self) -> Result<std::path::PathBuf, String> {
-        let data_dir = self.app.path().app_data_dir()
-            .map_err(|e| format!("Failed to get app data directory: {:?}", e))?;
+    pub fn get_app_data_dir(&self) -> Result<std::path::PathBuf, FilesystemError> {
+        self.paths
+            .as_ref()
+            .map(|paths| paths.data_dir.clone())
+            .ok_or_else(|| FilesystemError::Unavailable("No app data directory outside the app".to_string()))
+    }
 
-        fs::create_dir_all(&data_dir)
-            .map_err(|e| format!("Failed to create app data directory: {}", e))?;
+    pub fn get_autosave_dir(&self) -> Result<std::path::PathBuf, FilesystemError> {
+        let data_dir = self.get_app_data_dir()?;
 
-        Ok(data_dir.join("autosave.cellforge"))
+        let autosave_dir = data_dir.join("autosave");
+        fs::create_dir_all(&autosave_dir)
+            .map_err(FilesystemError::io("Failed to create autosave directory", &autosave_dir))?;
+
+        Ok(autosave_dir)
     }
 
-    pub fn export_stl(&self, data: &[u8], path: &Path) -> Result<(), String> {
-        fs::write(path, data)
-            .map_err(|e| format!("Failed to write STL file: {}", e))?;
-        Ok(())
+    /// Directory meshes extracted from project bundles are kept in.
+    pub fn get_mesh_dir(&self) -> Result<std::path::PathBuf, FilesystemError> {
+        let data_dir = self.get_app_data_dir()?;
+
+        let mesh_dir = data_dir.join("meshes");
+        fs::create_dir_all(&mesh_dir)
+            .map_err(FilesystemError::io("Failed to create mesh directory", &mesh_dir))?;
+
+        Ok(mesh_dir)
+    }
+
+    /// Bundled resources, which relative shape paths are resolved against.
+    pub fn get_resource_dir(&self) -> Option<std::path::PathBuf> {
+        self.paths.as_ref()?.resource_dir.clone()
+    }
+
+    /// Writes `data` to `path` without ever leaving a half-written file behind.
+    ///
+    /// The bytes go to a temporary file in the same directory, are flushed to
+    /// disk, and the temporary file is then renamed over the target.
+    pub fn write_atomic(&self, data: &[u8], path: &Path) -> Result<(), FilesystemError> {
+        self.write_atomic_with(data, path, |_| Ok(()))
+    }
+
+    /// `write_atomic` with a hook that runs on the flushed temporary file
+    /// before it replaces the target; an error leaves the target untouched.
+    fn write_atomic_with(
+        &self,
+        data: &[u8],
+        path: &Path,
+        before_rename: impl FnOnce(&Path) -> Result<(), FilesystemError>,
+    ) -> Result<(), FilesystemError> {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| FilesystemError::InvalidInput(format!("Invalid file path: {}", path.display())))?;
+        let temp_path = parent.join(format!(".{}.{}.tmp", file_name, Uuid::new_v4()));
+
+        let result = (|| {
+            let mut file = fs::File::create(&temp_path)
+                .map_err(FilesystemError::io("Failed to create temporary file", path))?;
+            file.write_all(data)
+                .map_err(FilesystemError::io("Failed to write file", path))?;
+            file.sync_all()
+                .map_err(FilesystemError::io("Failed to flush file to disk", path))?;
+            drop(file);
+
+            before_rename(&temp_path)?;
+            fs::rename(&temp_path, path)
+                .map_err(FilesystemError::io("Failed to move file into place", path))?;
+            sync_dir(parent);
+            Ok(())
+        })();
+
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result
     }
 
-    pub fn export_three_mf(&self, data: &[u8], path: &Path) -> Result<(), String> {
-        fs::write(path, data)
-            .map_err(|e| format!("Failed to write 3MF file: {}", e))?;
-        Ok(())
+    /// Atomically writes an export and its companion files, returning the
+    /// size of the main file in bytes.
+    pub fn write_export(
+        &self,
+        data: &[u8],
+        companions: &[(String, Vec<u8>)],
+        path: &Path,
+    ) -> Result<u64, FilesystemError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(FilesystemError::io("Failed to create directory", parent))?;
+        }
+
+        for (file_name, contents) in companions {
+            self.write_atomic(contents, &path.with_file_name(file_name))?;
+        }
+        self.write_atomic(data, path)?;
+
+        Ok(data.len() as u64)
     }
 
-    pub fn import_mesh(&self, path: &Path) -> Result<Vec<u8>, String> {
+    pub fn import_mesh(&self, path: &Path) -> Result<Vec<u8>, FilesystemError> {
         fs::read(path)
-            .map_err(|e| format!("Failed to read mesh file: {}", e))
+            .map_err(FilesystemError::io("Failed to read mesh file", path))
+    }
+}
+
+/// Leading number of a `major.minor.patch` version; 0 if there is none.
+fn major_version(version: &str) -> u64 {
+    version
+        .split('.')
+        .next()
+        .and_then(|major| major.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+pub(crate) fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(GZIP_MAGIC)
+}
+
+/// Writes indented JSON with sorted keys. Arrays of numbers, such as
+/// positions, stay on one line so moving a cell changes a single line.
+fn write_canonical(value: &serde_json::Value, depth: usize, out: &mut String) {
+    use serde_json::Value;
+
+    let indent = |out: &mut String, depth: usize| out.push_str(&"  ".repeat(depth));
+
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+
+            out.push_str("{\n");
+            for (i, (key, item)) in entries.iter().enumerate() {
+                indent(out, depth + 1);
+                out.push_str(&Value::String(key.to_string()).to_string());
+                out.push_str(": ");
+                write_canonical(item, depth + 1, out);
+                out.push_str(if i + 1 < entries.len() { ",\n" } else { "\n" });
+            }
+            indent(out, depth);
+            out.push('}');
+        }
+        Value::Array(items) if !items.is_empty() && !items.iter().all(Value::is_number) => {
+            out.push_str("[\n");
+            for (i, item) in items.iter().enumerate() {
+                indent(out, depth + 1);
+                write_canonical(item, depth + 1, out);
+                out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+            }
+            indent(out, depth);
+            out.push(']');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_canonical(item, depth, out);
+            }
+            out.push(']');
+        }
+        Value::Number(n) if n.is_f64() && n.as_f64() == Some(0.0) => out.push_str("0.0"),
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+/// Splits a `.cellforge` file into its gzip payload and checks the trailer.
+pub(crate) fn split_trailer(data: &[u8]) -> (&[u8], ChecksumStatus) {
+    if data.len() >= TRAILER_LEN {
+        let (payload, trailer) = data.split_at(data.len() - TRAILER_LEN);
+        if &trailer[..4] == TRAILER_MAGIC {
+            let mut length = [0u8; 8];
+            length.copy_from_slice(&trailer[4..12]);
+            let mut checksum = [0u8; 4];
+            checksum.copy_from_slice(&trailer[12..16]);
+
+            let intact = u64::from_le_bytes(length) == payload.len() as u64
+                && u32::from_le_bytes(checksum) == crc32fast::hash(payload);
+            let status = if intact {
+                ChecksumStatus::Valid
+            } else {
+                ChecksumStatus::Mismatch
+            };
+            return (payload, status);
+        }
     }
-}
\ No newline at end of file
+    (data, ChecksumStatus::Missing)
+}
+
+fn backup_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".bak.{}", index));
+    path.with_file_name(name)
+}
+
+/// Shifts `<file>.bak.N` up by one and copies the current file to `.bak.1`.
+///
+/// The current file is copied rather than moved so it stays in place until
+/// the new version is renamed over it.
+fn rotate_backups(path: &Path) -> Result<(), FilesystemError> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    for index in (1..BACKUP_COUNT).rev() {
+        let from = backup_path(path, index);
+        if from.exists() {
+            fs::rename(&from, backup_path(path, index + 1))
+                .map_err(FilesystemError::io("Failed to rotate backup", &from))?;
+        }
+    }
+
+    fs::copy(path, backup_path(path, 1))
+        .map_err(FilesystemError::io("Failed to back up previous version", path))?;
+    Ok(())
+}
+
+/// Makes a completed rename durable; best effort, and a no-op off Unix.
+fn sync_dir(dir: &Path) {
+    #[cfg(unix)]
+    if let Ok(dir) = fs::File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    #[cfg(not(unix))]
+    let _ = dir;
+}
@@ -0,0 +1,171 @@
+use crate::diff::{self, DiffSummary, ProjectDiff};
+use crate::error::FilesystemError;
+use crate::filesystem::{Filesystem, ProjectFile};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+const INDEX_FILE: &str = "index.json";
+
+/// A named, saved revision of a project.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub id: String,
+    pub name: String,
+    pub note: Option<String>,
+    pub author: Option<String>,
+    pub created_at: String,
+    pub file_name: String,
+    pub cells: usize,
+    pub connections: usize,
+    pub components: usize,
+    pub groups: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotComparison {
+    pub summary: DiffSummary,
+    pub diff: ProjectDiff,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Index {
+    /// Oldest first
+    snapshots: Vec<Snapshot>,
+}
+
+/// Revision history kept next to a project, in `<file>.history/`.
+pub struct History {
+    dir: PathBuf,
+}
+
+impl History {
+    pub fn for_project(project_path: &Path) -> Self {
+        let mut name = project_path.file_name().unwrap_or_default().to_os_string();
+        name.push(".history");
+        History {
+            dir: project_path.with_file_name(name),
+        }
+    }
+
+    /// Snapshots, newest first.
+    pub fn list(&self) -> Result<Vec<Snapshot>, FilesystemError> {
+        let mut snapshots = self.read_index()?.snapshots;
+        snapshots.reverse();
+        Ok(snapshots)
+    }
+
+    /// Stores `project` as a new snapshot. `author` defaults to the
+    /// project's author.
+    pub fn create(
+        &self,
+        filesystem: &Filesystem,
+        project: &ProjectFile,
+        name: String,
+        note: Option<String>,
+        author: Option<String>,
+    ) -> Result<Snapshot, FilesystemError> {
+        fs::create_dir_all(&self.dir)
+            .map_err(FilesystemError::io("Failed to create history directory", &self.dir))?;
+
+        let id = Uuid::new_v4().to_string();
+        let snapshot = Snapshot {
+            file_name: format!("{}.cellforge", id),
+            id,
+            name,
+            note,
+            author: author.or_else(|| project.metadata.author.clone()),
+            created_at: chrono::Utc::now().to_rfc3339(),
+            cells: project.scene.cells.len(),
+            connections: project.scene.connections.len(),
+            components: project.scene.components.len(),
+            groups: project.scene.groups.len(),
+        };
+
+        let data = filesystem.encode_project(project)?;
+        filesystem.write_atomic(&data, &self.dir.join(&snapshot.file_name))?;
+
+        let mut index = self.read_index()?;
+        index.snapshots.push(snapshot.clone());
+        self.write_index(filesystem, &index)?;
+
+        Ok(snapshot)
+    }
+
+    pub fn load(&self, filesystem: &Filesystem, id: &str) -> Result<ProjectFile, FilesystemError> {
+        let snapshot = self.find(id)?;
+        filesystem.load_project(&self.dir.join(&snapshot.file_name))
+    }
+
+    pub fn delete(&self, filesystem: &Filesystem, id: &str) -> Result<(), FilesystemError> {
+        let mut index = self.read_index()?;
+        let position = index
+            .snapshots
+            .iter()
+            .position(|s| s.id == id)
+            .ok_or_else(|| unknown_snapshot(id))?;
+
+        let snapshot = index.snapshots.remove(position);
+        self.write_index(filesystem, &index)?;
+        let _ = fs::remove_file(self.dir.join(snapshot.file_name));
+        Ok(())
+    }
+
+    /// What changed from snapshot `from` to snapshot `to`, or to `current`
+    /// when `to` is not given.
+    pub fn compare(
+        &self,
+        filesystem: &Filesystem,
+        from: &str,
+        to: Option<&str>,
+        current: impl FnOnce() -> Result<ProjectFile, FilesystemError>,
+    ) -> Result<SnapshotComparison, FilesystemError> {
+        let before = self.load(filesystem, from)?;
+        let after = match to {
+            Some(to) => self.load(filesystem, to)?,
+            None => current()?,
+        };
+
+        let diff = diff::diff(&before, &after)?;
+        Ok(SnapshotComparison {
+            summary: diff.summary(),
+            diff,
+        })
+    }
+
+    fn find(&self, id: &str) -> Result<Snapshot, FilesystemError> {
+        self.read_index()?
+            .snapshots
+            .into_iter()
+            .find(|s| s.id == id)
+            .ok_or_else(|| unknown_snapshot(id))
+    }
+
+    fn read_index(&self) -> Result<Index, FilesystemError> {
+        let path = self.dir.join(INDEX_FILE);
+        if !path.exists() {
+            return Ok(Index::default());
+        }
+
+        let json = fs::read_to_string(&path)
+            .map_err(FilesystemError::io("Failed to read history index", &path))?;
+        serde_json::from_str(&json).map_err(|e| FilesystemError::Corrupt {
+            reason: format!("invalid history index: {}", e),
+            path,
+        })
+    }
+
+    fn write_index(&self, filesystem: &Filesystem, index: &Index) -> Result<(), FilesystemError> {
+        let json = serde_json::to_vec_pretty(index)
+            .map_err(|e| FilesystemError::Serialization(format!("Failed to serialize history index: {}", e)))?;
+        filesystem.write_atomic(&json, &self.dir.join(INDEX_FILE))
+    }
+}
+
+fn unknown_snapshot(id: &str) -> FilesystemError {
+    FilesystemError::UnknownId {
+        kind: "Snapshot",
+        id: id.to_string(),
+    }
+}
@@ -0,0 +1,368 @@
+use crate::bundle::BundleLibrary;
+use crate::cell_import::ImportFormat;
+use crate::database::Database;
+use crate::error::{DatabaseError, FilesystemError};
+use crate::filesystem::Filesystem;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const FORMAT: &str = "cellforge-library";
+
+/// Layout of the rows in library files. Bumped when a column is renamed,
+/// removed or changes meaning; new columns don't need it as readers ignore
+/// what they don't know.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LibraryTable {
+    Cells,
+    Bms,
+    Materials,
+    Shapes,
+}
+
+impl LibraryTable {
+    pub const ALL: [LibraryTable; 4] = [
+        LibraryTable::Cells,
+        LibraryTable::Bms,
+        LibraryTable::Materials,
+        LibraryTable::Shapes,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LibraryTable::Cells => "cells",
+            LibraryTable::Bms => "bms",
+            LibraryTable::Materials => "materials",
+            LibraryTable::Shapes => "shapes",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        LibraryTable::ALL.into_iter().find(|t| t.name() == name)
+    }
+}
+
+/// Which rows to export. Filters on columns a table doesn't have, like
+/// `chemistry` for materials, don't apply to it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LibraryFilter {
+    /// Only rows added on this install, not shipped with the library
+    pub user_added_only: bool,
+    /// Full-text search over cells, as in the library browser
+    pub search: Option<String>,
+    /// Cells and BMS by this manufacturer, ignoring case
+    pub manufacturer: Option<String>,
+    pub form_factor: Option<String>,
+    pub chemistry: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LibraryExportOptions {
+    /// All tables when empty
+    pub tables: Vec<LibraryTable>,
+    /// Taken from the file extension when not given
+    pub format: Option<ImportFormat>,
+    pub filter: LibraryFilter,
+    /// Makes a JSON export a catalog other installs can sync from, see
+    /// `catalog`
+    pub catalog_version: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryExportResult {
+    /// The JSON file, or one CSV file per table
+    pub paths: Vec<String>,
+    pub schema_version: u32,
+    pub cells: usize,
+    pub bms: usize,
+    pub materials: usize,
+    pub shapes: usize,
+}
+
+/// A JSON library file: the header fields, then one array per table.
+#[derive(Serialize, Deserialize)]
+struct LibraryDocument {
+    format: String,
+    schema_version: u32,
+    exported: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    catalog_version: Option<String>,
+    #[serde(flatten)]
+    library: BundleLibrary,
+}
+
+/// Rows of the library matching `options`, from the tables it selects.
+pub fn select(
+    db: &Database,
+    options: &LibraryExportOptions,
+) -> Result<BundleLibrary, DatabaseError> {
+    let filter = &options.filter;
+    let wanted = |table| options.tables.is_empty() || options.tables.contains(&table);
+    let matches = |value: &str, wanted: &Option<String>| {
+        wanted
+            .as_deref()
+            .is_none_or(|wanted| value.eq_ignore_ascii_case(wanted.trim()))
+    };
+    let mut library = BundleLibrary::default();
+
+    if wanted(LibraryTable::Cells) {
+        library.cells = db
+            .get_cells(filter.search.as_deref())
+            .map_err(DatabaseError::context("Failed to read library"))?
+            .into_iter()
+            .filter(|c| c.user_added || !filter.user_added_only)
+            .filter(|c| matches(&c.manufacturer, &filter.manufacturer))
+            .filter(|c| matches(&c.form_factor, &filter.form_factor))
+            .filter(|c| matches(&c.chemistry, &filter.chemistry))
+            .collect();
+    }
+    if wanted(LibraryTable::Bms) {
+        library.bms = db
+            .get_bms()
+            .map_err(DatabaseError::context("Failed to read library"))?
+            .into_iter()
+            .filter(|b| b.user_added || !filter.user_added_only)
+            .filter(|b| matches(&b.manufacturer, &filter.manufacturer))
+            .collect();
+    }
+    if wanted(LibraryTable::Materials) {
+        library.materials = db
+            .get_materials()
+            .map_err(DatabaseError::context("Failed to read library"))?
+            .into_iter()
+            .filter(|m| m.user_added || !filter.user_added_only)
+            .collect();
+    }
+    if wanted(LibraryTable::Shapes) {
+        library.shapes = db
+            .get_shapes()
+            .map_err(DatabaseError::context("Failed to read library"))?
+            .into_iter()
+            .filter(|s| s.user_added || !filter.user_added_only)
+            .collect();
+    }
+    Ok(library)
+}
+
+/// Writes the selected rows to `path`. JSON holds every table in one file;
+/// CSV holds one table per file, so exporting several tables writes
+/// `<name>-<table>.csv` files next to `path` instead.
+pub fn export(
+    filesystem: &Filesystem,
+    db: &Database,
+    path: &Path,
+    options: &LibraryExportOptions,
+) -> Result<LibraryExportResult, FilesystemError> {
+    let format = file_format(path, options.format)?;
+    let library = select(db, options)?;
+    let exported = chrono::Utc::now().to_rfc3339();
+
+    let counts = (
+        library.cells.len(),
+        library.bms.len(),
+        library.materials.len(),
+        library.shapes.len(),
+    );
+    let mut paths = Vec::new();
+    match format {
+        ImportFormat::Json => {
+            let document = LibraryDocument {
+                format: FORMAT.to_string(),
+                schema_version: SCHEMA_VERSION,
+                exported,
+                catalog_version: options.catalog_version.clone(),
+                library,
+            };
+            let data = serde_json::to_vec_pretty(&document).map_err(|e| {
+                FilesystemError::Serialization(format!("Failed to serialize library: {}", e))
+            })?;
+            filesystem.write_atomic(&data, path)?;
+            paths.push(path.to_path_buf());
+        }
+        ImportFormat::Csv => {
+            let tables: Vec<_> = LibraryTable::ALL
+                .into_iter()
+                .filter(|t| options.tables.is_empty() || options.tables.contains(t))
+                .collect();
+            for table in &tables {
+                let target = if tables.len() == 1 {
+                    path.to_path_buf()
+                } else {
+                    table_path(path, *table)
+                };
+                let header = format!(
+                    "# {} schema_version={} table={} exported={}\n",
+                    FORMAT,
+                    SCHEMA_VERSION,
+                    table.name(),
+                    exported
+                );
+                let data = match table {
+                    LibraryTable::Cells => write_csv(header, &library.cells),
+                    LibraryTable::Bms => write_csv(header, &library.bms),
+                    LibraryTable::Materials => write_csv(header, &library.materials),
+                    LibraryTable::Shapes => write_csv(header, &library.shapes),
+                }?;
+                filesystem.write_atomic(&data, &target)?;
+                paths.push(target);
+            }
+        }
+    }
+
+    Ok(LibraryExportResult {
+        paths: paths
+            .iter()
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect(),
+        schema_version: SCHEMA_VERSION,
+        cells: counts.0,
+        bms: counts.1,
+        materials: counts.2,
+        shapes: counts.3,
+    })
+}
+
+/// `library.csv` -> `library-cells.csv`
+fn table_path(path: &Path, table: LibraryTable) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "library".to_string());
+    path.with_file_name(format!("{}-{}.csv", stem, table.name()))
+}
+
+fn file_format(path: &Path, format: Option<ImportFormat>) -> Result<ImportFormat, FilesystemError> {
+    format
+        .or_else(|| ImportFormat::from_path(path))
+        .ok_or_else(|| {
+            FilesystemError::InvalidInput(format!(
+                "Can't tell the format of {}; choose CSV or JSON",
+                path.display()
+            ))
+        })
+}
+
+fn write_csv<T: Serialize>(header: String, rows: &[T]) -> Result<Vec<u8>, FilesystemError> {
+    let csv_error =
+        |e: csv::Error| FilesystemError::Serialization(format!("Failed to write CSV: {}", e));
+    let mut writer = csv::Writer::from_writer(header.into_bytes());
+    for row in rows {
+        writer.serialize(row).map_err(csv_error)?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| FilesystemError::Serialization(format!("Failed to write CSV: {}", e)))
+}
+
+/// Reads library files written by `export`, merging their tables. Files
+/// from a newer schema are refused rather than half understood.
+pub fn read(paths: &[PathBuf]) -> Result<BundleLibrary, FilesystemError> {
+    let mut library = BundleLibrary::default();
+    for path in paths {
+        let data = std::fs::read(path)
+            .map_err(FilesystemError::io("Failed to read library file", path))?;
+        match file_format(path, None)? {
+            ImportFormat::Json => {
+                let part = read_json(path, &data)?;
+                library.cells.extend(part.cells);
+                library.bms.extend(part.bms);
+                library.materials.extend(part.materials);
+                library.shapes.extend(part.shapes);
+            }
+            ImportFormat::Csv => match read_csv_header(path, &data)? {
+                LibraryTable::Cells => library.cells.extend(read_csv(path, &data)?),
+                LibraryTable::Bms => library.bms.extend(read_csv(path, &data)?),
+                LibraryTable::Materials => library.materials.extend(read_csv(path, &data)?),
+                LibraryTable::Shapes => library.shapes.extend(read_csv(path, &data)?),
+            },
+        }
+    }
+    Ok(library)
+}
+
+fn corrupt(path: &Path, reason: impl Into<String>) -> FilesystemError {
+    FilesystemError::Corrupt {
+        path: path.to_path_buf(),
+        reason: reason.into(),
+    }
+}
+
+fn check_version(path: &Path, version: u32) -> Result<(), FilesystemError> {
+    if version > SCHEMA_VERSION {
+        return Err(FilesystemError::VersionTooNew {
+            path: path.to_path_buf(),
+            version: version.to_string(),
+            supported: SCHEMA_VERSION.to_string(),
+        });
+    }
+    Ok(())
+}
+
+fn read_json(path: &Path, data: &[u8]) -> Result<BundleLibrary, FilesystemError> {
+    let value: Value =
+        serde_json::from_slice(data).map_err(|e| corrupt(path, format!("invalid JSON: {}", e)))?;
+    from_json(path, value)
+}
+
+/// The tables of a parsed JSON library file. Files without a header, like
+/// a bundle's `library.json`, are read as the first schema version.
+pub(crate) fn from_json(path: &Path, value: Value) -> Result<BundleLibrary, FilesystemError> {
+    if !value.is_object() {
+        return Err(corrupt(path, "not a CellForge library file"));
+    }
+    if let Some(format) = value.get("format") {
+        if format.as_str() != Some(FORMAT) {
+            return Err(corrupt(path, "not a CellForge library file"));
+        }
+        let version = value
+            .get("schema_version")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| corrupt(path, "missing schema_version"))?;
+        check_version(path, version as u32)?;
+    }
+
+    serde_path_to_error::deserialize(value)
+        .map_err(|e| corrupt(path, format!("{} at {}", e.inner(), e.path())))
+}
+
+/// The table named in a CSV file's `# cellforge-library ...` first line.
+fn read_csv_header(path: &Path, data: &[u8]) -> Result<LibraryTable, FilesystemError> {
+    let first = String::from_utf8_lossy(data.split(|&b| b == b'\n').next().unwrap_or_default())
+        .trim_start_matches('\u{feff}')
+        .to_string();
+    let mut words = first
+        .trim()
+        .strip_prefix('#')
+        .unwrap_or_default()
+        .split_whitespace();
+    if words.next() != Some(FORMAT) {
+        return Err(corrupt(path, "not a CellForge library file"));
+    }
+
+    let fields: HashMap<_, _> = words.filter_map(|word| word.split_once('=')).collect();
+    let version = fields
+        .get("schema_version")
+        .and_then(|v| v.parse::<u32>().ok())
+        .ok_or_else(|| corrupt(path, "missing schema_version"))?;
+    check_version(path, version)?;
+    fields
+        .get("table")
+        .and_then(|name| LibraryTable::from_name(name))
+        .ok_or_else(|| corrupt(path, "missing or unknown table"))
+}
+
+fn read_csv<T: DeserializeOwned>(path: &Path, data: &[u8]) -> Result<Vec<T>, FilesystemError> {
+    csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .from_reader(data)
+        .deserialize()
+        .collect::<Result<Vec<T>, _>>()
+        .map_err(|e| corrupt(path, e.to_string()))
+}
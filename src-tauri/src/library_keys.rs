@@ -0,0 +1,140 @@
+use crate::database::Database;
+use crate::filesystem::ProjectFile;
+use std::collections::HashMap;
+
+/// Row id given to a cell whose key is not in the local library. SQLite
+/// never assigns it, so the cell shows up as unknown instead of as
+/// whichever local cell happens to share the original id.
+pub const UNRESOLVED_ID: i64 = 0;
+
+/// Two-way mapping between local row ids and stable keys for one table.
+#[derive(Debug, Default)]
+pub struct KeyMap {
+    ids: HashMap<String, i64>,
+    keys: HashMap<i64, String>,
+}
+
+impl KeyMap {
+    pub fn insert(&mut self, key: String, id: i64) {
+        // With duplicate rows the lowest id wins, as rows are loaded in id order
+        self.ids.entry(key.clone()).or_insert(id);
+        self.keys.insert(id, key);
+    }
+
+    pub fn id(&self, key: &str) -> Option<i64> {
+        self.ids.get(key).copied()
+    }
+
+    pub fn key(&self, id: i64) -> Option<&str> {
+        self.keys.get(&id).map(String::as_str)
+    }
+}
+
+/// Stable keys of the local library, see `database::library_key`.
+#[derive(Debug, Default)]
+pub struct LibraryKeys {
+    pub cells: KeyMap,
+    pub bms: KeyMap,
+    pub materials: KeyMap,
+    pub shapes: KeyMap,
+}
+
+impl LibraryKeys {
+    pub fn load(db: &Database) -> rusqlite::Result<Self> {
+        let mut keys = LibraryKeys::default();
+
+        let mut cells = db.get_cells(None)?;
+        cells.sort_by_key(|c| c.id);
+        for cell in cells {
+            keys.cells.insert(cell.library_key(), cell.id);
+        }
+        let mut bms = db.get_bms()?;
+        bms.sort_by_key(|b| b.id);
+        for bms in bms {
+            keys.bms.insert(bms.library_key(), bms.id);
+        }
+        let mut materials = db.get_materials()?;
+        materials.sort_by_key(|m| m.id);
+        for material in materials {
+            keys.materials.insert(material.library_key(), material.id);
+        }
+        let mut shapes = db.get_shapes()?;
+        shapes.sort_by_key(|s| s.id);
+        for shape in shapes {
+            keys.shapes.insert(shape.library_key(), shape.id);
+        }
+
+        Ok(keys)
+    }
+
+    fn references(&self, component_type: &str) -> Option<&KeyMap> {
+        match component_type {
+            "bms" => Some(&self.bms),
+            "shape" => Some(&self.shapes),
+            _ => None,
+        }
+    }
+
+    /// Records the key of every library row the project references, before
+    /// it is written. References that were cleared lose their key; keys of
+    /// references this library doesn't know are kept, so a project passed
+    /// between installs doesn't lose them.
+    pub fn stamp(&self, project: &mut ProjectFile) {
+        let scene = &mut project.scene;
+        for cell in scene.cells.values_mut() {
+            if let Some(key) = self.cells.key(cell.cell_id) {
+                cell.cell_key = Some(key.to_string());
+            }
+        }
+        for connection in scene.connections.values_mut() {
+            match connection.material_id {
+                None => connection.material_key = None,
+                Some(id) => {
+                    if let Some(key) = self.materials.key(id) {
+                        connection.material_key = Some(key.to_string());
+                    }
+                }
+            }
+        }
+        for component in scene.components.values_mut() {
+            let Some(id) = component.reference_id else {
+                component.reference_key = None;
+                continue;
+            };
+            if let Some(key) = self
+                .references(&component.component_type)
+                .and_then(|keys| keys.key(id))
+            {
+                component.reference_key = Some(key.to_string());
+            }
+        }
+    }
+
+    /// Points keyed references at the matching local rows after a load.
+    ///
+    /// Keys override the stored ids, which are only meaningful on the
+    /// install that wrote the file. References without a key (files from
+    /// before keys existed) keep their ids. Unknown keys leave the reference
+    /// unset, for validation to report.
+    pub fn resolve(&self, project: &mut ProjectFile) {
+        let scene = &mut project.scene;
+
+        for cell in scene.cells.values_mut() {
+            if let Some(key) = &cell.cell_key {
+                cell.cell_id = self.cells.id(key).unwrap_or(UNRESOLVED_ID);
+            }
+        }
+        for connection in scene.connections.values_mut() {
+            if let Some(key) = &connection.material_key {
+                connection.material_id = self.materials.id(key);
+            }
+        }
+        for component in scene.components.values_mut() {
+            let keys = self.references(&component.component_type);
+            let (Some(keys), Some(key)) = (keys, &component.reference_key) else {
+                continue;
+            };
+            component.reference_id = keys.id(key);
+        }
+    }
+}
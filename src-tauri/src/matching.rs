@@ -0,0 +1,454 @@
+use crate::analysis;
+use crate::database::{CellSample, Database};
+use crate::error::FilesystemError;
+use crate::export::resolve_cell_groups;
+use crate::filesystem::ProjectFile;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+// Bounds the swap search on very large packs; each pass makes one swap
+const MAX_PASSES: usize = 2000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MatchOptions {
+    /// Weight of balancing the groups' internal resistance relative to
+    /// their capacity; 0 balances capacity only
+    pub resistance_weight: f64,
+    /// Serials to place; every measured sample of the pack's cell models
+    /// otherwise
+    pub serials: Option<Vec<String>>,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        MatchOptions {
+            resistance_weight: 1.0,
+            serials: None,
+        }
+    }
+}
+
+/// Where one measured cell goes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Placement {
+    /// Position of the group in the series string from its negative end,
+    /// 1-based; `None` when the group isn't wired into the main string
+    pub series: Option<usize>,
+    pub group_id: String,
+    pub group: String,
+    /// 1-based position in the group, ordered along the layout
+    pub slot: usize,
+    pub cell_uuid: String,
+    pub x: f64,
+    pub y: f64,
+    pub serial: String,
+    pub capacity_mah: f64,
+    pub internal_res_mohm: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupBalance {
+    pub group_id: String,
+    pub group: String,
+    pub series: Option<usize>,
+    pub cells: usize,
+    /// Sum of the measured capacities
+    pub capacity_mah: f64,
+    /// Parallel resistance of the group, when every cell was measured
+    pub internal_res_mohm: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CellMatching {
+    /// Ordered by series position, group and slot
+    pub placements: Vec<Placement>,
+    pub groups: Vec<GroupBalance>,
+    /// Largest minus smallest group capacity
+    pub capacity_spread_mah: f64,
+    /// `capacity_spread_mah` as a percentage of the mean group capacity
+    pub capacity_spread_pct: f64,
+    /// Largest minus smallest group resistance, when every cell was measured
+    pub resistance_spread_mohm: Option<f64>,
+    /// Measured serials that weren't placed
+    pub unused: Vec<String>,
+    /// Cells in no parallel group; their labels are left alone
+    pub ungrouped: Vec<String>,
+}
+
+impl CellMatching {
+    /// The placement table as CSV, one row per cell.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "series,group,slot,serial,capacity_mah,internal_res_mohm,cell_uuid,x_mm,y_mm\n",
+        );
+        for p in &self.placements {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{:.1},{:.1}\n",
+                p.series.map(|s| s.to_string()).unwrap_or_default(),
+                csv_field(&p.group),
+                p.slot,
+                csv_field(&p.serial),
+                p.capacity_mah,
+                p.internal_res_mohm
+                    .map(|r| r.to_string())
+                    .unwrap_or_default(),
+                p.cell_uuid,
+                p.x,
+                p.y
+            ));
+        }
+        csv
+    }
+}
+
+/// A project with measured serials written to its cells' labels.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchedProject {
+    pub project: ProjectFile,
+    pub matching: CellMatching,
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+struct GroupSlots {
+    key: String,
+    name: String,
+    series: Option<usize>,
+    /// `(uuid, cell_id, x, y)` in slot order
+    cells: Vec<(String, i64, f64, f64)>,
+}
+
+struct Candidate {
+    serial: String,
+    capacity: f64,
+    resistance: Option<f64>,
+    /// `1 / resistance`, or the model's mean when not measured
+    conductance: f64,
+}
+
+/// Measured samples of every cell model placed in the project.
+pub fn measured_samples(db: &Database, project: &ProjectFile) -> rusqlite::Result<Vec<CellSample>> {
+    let cell_ids: BTreeSet<i64> = project.scene.cells.values().map(|c| c.cell_id).collect();
+    let mut samples = Vec::new();
+    for cell_id in cell_ids {
+        samples.extend(db.get_cell_samples(cell_id)?);
+    }
+    Ok(samples)
+}
+
+/// Parallel groups with cells in them, in series order and then by name.
+fn group_slots(project: &ProjectFile) -> (Vec<GroupSlots>, Vec<String>) {
+    let scene = &project.scene;
+    let cell_group = resolve_cell_groups(scene);
+    let series = analysis::series_groups(scene);
+
+    let mut members: BTreeMap<&str, Vec<(String, i64, f64, f64)>> = BTreeMap::new();
+    let mut ungrouped = Vec::new();
+    for cell in scene.cells.values() {
+        match cell_group.get(cell.uuid.as_str()) {
+            Some(group) => members.entry(group).or_default().push((
+                cell.uuid.clone(),
+                cell.cell_id,
+                cell.position.0[0],
+                cell.position.0[1],
+            )),
+            None => ungrouped.push(cell.uuid.clone()),
+        }
+    }
+    ungrouped.sort();
+
+    let mut groups: Vec<GroupSlots> = members
+        .into_iter()
+        .map(|(key, mut cells)| {
+            // Rows front to back, then left to right
+            cells.sort_by(|a, b| {
+                a.3.total_cmp(&b.3)
+                    .then(a.2.total_cmp(&b.2))
+                    .then_with(|| a.0.cmp(&b.0))
+            });
+            let position = series
+                .iter()
+                .position(|group| cells.iter().any(|(uuid, ..)| group.contains(uuid)));
+            GroupSlots {
+                key: key.to_string(),
+                name: scene.groups[key].name.clone(),
+                series: position.map(|i| i + 1),
+                cells,
+            }
+        })
+        .collect();
+    groups.sort_by(|a, b| {
+        (a.series.unwrap_or(usize::MAX), &a.name, &a.key).cmp(&(
+            b.series.unwrap_or(usize::MAX),
+            &b.name,
+            &b.key,
+        ))
+    });
+    (groups, ungrouped)
+}
+
+/// Splits `pool` into the `count` samples with the narrowest range of
+/// capacity and the rest.
+fn narrowest(mut pool: Vec<Candidate>, count: usize) -> (Vec<Candidate>, Vec<Candidate>) {
+    pool.sort_by(|a, b| {
+        a.capacity
+            .total_cmp(&b.capacity)
+            .then(a.serial.cmp(&b.serial))
+    });
+    let range = |i: usize| pool[i + count - 1].capacity - pool[i].capacity;
+    let start = (0..=pool.len() - count)
+        .min_by(|&a, &b| range(a).total_cmp(&range(b)))
+        .unwrap_or(0);
+
+    let mut chosen = pool.split_off(start);
+    let above = chosen.split_off(count);
+    pool.extend(above);
+    (chosen, pool)
+}
+
+/// Assigns measured serials to the cells of the project's parallel groups
+/// so the groups' total capacity and parallel resistance come out as even
+/// as possible, and writes each serial to its cell's `custom_label`.
+///
+/// Cells are only given samples of their own library cell. When there are
+/// more samples than cells, the ones closest together in capacity are used.
+pub fn match_cells(
+    project: &mut ProjectFile,
+    samples: &[CellSample],
+    options: &MatchOptions,
+) -> Result<CellMatching, FilesystemError> {
+    let (groups, ungrouped) = group_slots(project);
+    if groups.is_empty() {
+        return Err(FilesystemError::InvalidInput(
+            "No cells are in a parallel group; group each parallel group's cells first".to_string(),
+        ));
+    }
+
+    let wanted: Option<HashSet<&str>> = options
+        .serials
+        .as_ref()
+        .map(|serials| serials.iter().map(String::as_str).collect());
+    if let Some(wanted) = &wanted {
+        if let Some(missing) = wanted
+            .iter()
+            .find(|serial| !samples.iter().any(|s| s.serial == **serial))
+        {
+            return Err(FilesystemError::InvalidInput(format!(
+                "Serial '{}' has no measurement for the pack's cells",
+                missing
+            )));
+        }
+    }
+
+    let mut unused = Vec::new();
+    let mut pools: BTreeMap<i64, Vec<Candidate>> = BTreeMap::new();
+    for sample in samples {
+        if wanted
+            .as_ref()
+            .is_some_and(|w| !w.contains(sample.serial.as_str()))
+        {
+            continue;
+        }
+        match sample.capacity_mah {
+            Some(capacity) => pools.entry(sample.cell_id).or_default().push(Candidate {
+                serial: sample.serial.clone(),
+                capacity,
+                resistance: sample.internal_res_mohm.filter(|r| *r > 0.0),
+                conductance: 0.0,
+            }),
+            None => unused.push(sample.serial.clone()),
+        }
+    }
+
+    // Slots as (group, index in group), per model
+    let mut slots: BTreeMap<i64, Vec<(usize, usize)>> = BTreeMap::new();
+    for (g, group) in groups.iter().enumerate() {
+        for (i, (_, cell_id, ..)) in group.cells.iter().enumerate() {
+            slots.entry(*cell_id).or_default().push((g, i));
+        }
+    }
+
+    let mut candidates: Vec<Candidate> = Vec::new();
+    let mut model_of: Vec<i64> = Vec::new();
+    for (cell_id, model_slots) in &slots {
+        let pool = pools.remove(cell_id).unwrap_or_default();
+        if pool.len() < model_slots.len() {
+            return Err(FilesystemError::InvalidInput(format!(
+                "{} cells of library cell {} are in parallel groups but only {} of its samples have a measured capacity",
+                model_slots.len(),
+                cell_id,
+                pool.len()
+            )));
+        }
+        let (mut chosen, rest) = narrowest(pool, model_slots.len());
+        unused.extend(rest.into_iter().map(|c| c.serial));
+
+        let measured: Vec<f64> = chosen.iter().filter_map(|c| c.resistance).collect();
+        let typical = if measured.is_empty() {
+            None
+        } else {
+            Some(measured.iter().sum::<f64>() / measured.len() as f64)
+        };
+        for candidate in &mut chosen {
+            candidate.conductance = candidate
+                .resistance
+                .or(typical)
+                .map(|r| 1.0 / r)
+                .unwrap_or(0.0);
+        }
+        model_of.extend(std::iter::repeat_n(*cell_id, chosen.len()));
+        candidates.extend(chosen);
+    }
+    unused.extend(pools.into_values().flatten().map(|c| c.serial));
+    unused.sort();
+
+    // Largest first into the group with the least capacity so far
+    let mut assigned: Vec<Vec<Option<usize>>> =
+        groups.iter().map(|g| vec![None; g.cells.len()]).collect();
+    let mut capacity: Vec<f64> = vec![0.0; groups.len()];
+    let mut conductance: Vec<f64> = vec![0.0; groups.len()];
+    let mut order: Vec<usize> = (0..candidates.len()).collect();
+    order.sort_by(|&a, &b| {
+        model_of[a]
+            .cmp(&model_of[b])
+            .then(candidates[b].capacity.total_cmp(&candidates[a].capacity))
+    });
+    for c in order {
+        let (g, i) = slots[&model_of[c]]
+            .iter()
+            .copied()
+            .filter(|(g, i)| assigned[*g][*i].is_none())
+            .min_by(|a, b| capacity[a.0].total_cmp(&capacity[b.0]))
+            .expect("a free slot for every chosen sample");
+        assigned[g][i] = Some(c);
+        capacity[g] += candidates[c].capacity;
+        conductance[g] += candidates[c].conductance;
+    }
+
+    // Then swap cells between groups while that evens them out. The
+    // squared deviations from the mean are scaled by the mean so capacity
+    // and resistance weigh alike.
+    let n = groups.len() as f64;
+    let scale = |totals: &[f64]| {
+        let mean = totals.iter().sum::<f64>() / n;
+        if mean > 0.0 {
+            1.0 / (n * mean * mean)
+        } else {
+            0.0
+        }
+    };
+    let capacity_scale = scale(&capacity);
+    let conductance_scale = scale(&conductance) * options.resistance_weight.max(0.0);
+    let slot_list: Vec<(usize, usize)> = slots.values().flatten().copied().collect();
+    for _ in 0..MAX_PASSES {
+        let mut best: Option<(f64, usize, usize)> = None;
+        for (x, &(ga, ia)) in slot_list.iter().enumerate() {
+            for (y, &(gb, ib)) in slot_list.iter().enumerate().skip(x + 1) {
+                let (a, b) = (assigned[ga][ia].unwrap(), assigned[gb][ib].unwrap());
+                if ga == gb || model_of[a] != model_of[b] {
+                    continue;
+                }
+                // Moving b into group a and a into group b
+                let change =
+                    |totals: &[f64], d: f64| 2.0 * d * (totals[ga] - totals[gb]) + 2.0 * d * d;
+                let delta = capacity_scale
+                    * change(&capacity, candidates[b].capacity - candidates[a].capacity)
+                    + conductance_scale
+                        * change(
+                            &conductance,
+                            candidates[b].conductance - candidates[a].conductance,
+                        );
+                if delta < -1e-12 && best.is_none_or(|(d, ..)| delta < d) {
+                    best = Some((delta, x, y));
+                }
+            }
+        }
+        let Some((_, x, y)) = best else {
+            break;
+        };
+        let ((ga, ia), (gb, ib)) = (slot_list[x], slot_list[y]);
+        let (a, b) = (assigned[ga][ia].unwrap(), assigned[gb][ib].unwrap());
+        let dc = candidates[b].capacity - candidates[a].capacity;
+        let dg = candidates[b].conductance - candidates[a].conductance;
+        capacity[ga] += dc;
+        capacity[gb] -= dc;
+        conductance[ga] += dg;
+        conductance[gb] -= dg;
+        assigned[ga][ia] = Some(b);
+        assigned[gb][ib] = Some(a);
+    }
+
+    let mut placements = Vec::new();
+    let mut balance = Vec::new();
+    for (g, group) in groups.iter().enumerate() {
+        let mut total_capacity = 0.0;
+        let mut resistance_measured = true;
+        let mut measured_conductance = 0.0;
+        for (i, (uuid, _, x, y)) in group.cells.iter().enumerate() {
+            let candidate = &candidates[assigned[g][i].unwrap()];
+            total_capacity += candidate.capacity;
+            match candidate.resistance {
+                Some(r) => measured_conductance += 1.0 / r,
+                None => resistance_measured = false,
+            }
+            if let Some(cell) = project.scene.cells.get_mut(uuid) {
+                cell.custom_label = Some(candidate.serial.clone());
+            }
+            placements.push(Placement {
+                series: group.series,
+                group_id: group.key.clone(),
+                group: group.name.clone(),
+                slot: i + 1,
+                cell_uuid: uuid.clone(),
+                x: *x,
+                y: *y,
+                serial: candidate.serial.clone(),
+                capacity_mah: candidate.capacity,
+                internal_res_mohm: candidate.resistance,
+            });
+        }
+        balance.push(GroupBalance {
+            group_id: group.key.clone(),
+            group: group.name.clone(),
+            series: group.series,
+            cells: group.cells.len(),
+            capacity_mah: total_capacity,
+            internal_res_mohm: (resistance_measured && measured_conductance > 0.0)
+                .then(|| 1.0 / measured_conductance),
+        });
+    }
+
+    let spread = |values: &mut dyn Iterator<Item = f64>| {
+        let (min, max) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+            (min.min(v), max.max(v))
+        });
+        max - min
+    };
+    let capacity_spread_mah = spread(&mut balance.iter().map(|g| g.capacity_mah));
+    let mean_capacity = balance.iter().map(|g| g.capacity_mah).sum::<f64>() / n;
+    let resistance_spread_mohm = balance
+        .iter()
+        .all(|g| g.internal_res_mohm.is_some())
+        .then(|| spread(&mut balance.iter().filter_map(|g| g.internal_res_mohm)));
+
+    Ok(CellMatching {
+        placements,
+        groups: balance,
+        capacity_spread_mah,
+        capacity_spread_pct: if mean_capacity > 0.0 {
+            capacity_spread_mah / mean_capacity * 100.0
+        } else {
+            0.0
+        },
+        resistance_spread_mohm,
+        unused,
+        ungrouped,
+    })
+}
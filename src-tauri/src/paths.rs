@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+
+const LIBRARY_DB: &str = "library.db";
+const BUNDLED_LIBRARY_DB: &str = "assets/library.db";
+
+/// Directories CellForge reads and writes.
+///
+/// The desktop app resolves them from Tauri at startup; tools built on this
+/// crate can point them anywhere.
+#[derive(Debug, Clone)]
+pub struct AppPaths {
+    /// Library database, autosaves, recent projects and extracted meshes
+    pub data_dir: PathBuf,
+    /// Bundled assets: the starter library and shape meshes
+    pub resource_dir: Option<PathBuf>,
+}
+
+impl AppPaths {
+    pub fn new(data_dir: impl Into<PathBuf>, resource_dir: Option<PathBuf>) -> Self {
+        AppPaths {
+            data_dir: data_dir.into(),
+            resource_dir,
+        }
+    }
+
+    pub fn library_db(&self) -> PathBuf {
+        self.data_dir.join(LIBRARY_DB)
+    }
+
+    /// Library shipped with the app, copied into place on first run.
+    pub fn bundled_library_db(&self) -> Option<PathBuf> {
+        self.resource_dir.as_ref().map(|dir| dir.join(BUNDLED_LIBRARY_DB))
+    }
+}
@@ -0,0 +1,73 @@
+use crate::database::{self, Database};
+use crate::error::DatabaseError;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::Connection;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Read connections kept open next to the single writer.
+pub const DEFAULT_READERS: u32 = 4;
+
+// How long a statement waits on a lock held by another connection, and how
+// long a caller waits for a free read connection
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+const CHECKOUT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The library database in WAL mode: a pool of read connections that keep
+/// answering while the one writer is busy, e.g. during a long import.
+pub struct DatabasePool {
+    readers: Pool<SqliteConnectionManager>,
+    writer: Mutex<Database>,
+}
+
+impl DatabasePool {
+    /// Opens the library at `path` with `readers` read connections. The
+    /// schema must already exist; see `Database::init_schema`.
+    pub fn open(path: &Path, readers: u32) -> Result<Self, DatabaseError> {
+        let conn = Connection::open(path)
+            .map_err(DatabaseError::context("Failed to open library database"))?;
+        conn.busy_timeout(BUSY_TIMEOUT)
+            .and_then(|_| database::configure(&conn))
+            .and_then(|_| conn.pragma_update(None, "journal_mode", "WAL"))
+            .and_then(|_| conn.pragma_update(None, "synchronous", "NORMAL"))
+            .map_err(DatabaseError::context(
+                "Failed to configure library database",
+            ))?;
+
+        let manager = SqliteConnectionManager::file(path).with_init(|conn| {
+            conn.busy_timeout(BUSY_TIMEOUT)?;
+            database::configure(conn)?;
+            conn.pragma_update(None, "query_only", true)
+        });
+        let readers = Pool::builder()
+            .max_size(readers.max(1))
+            .connection_timeout(CHECKOUT_TIMEOUT)
+            .build(manager)?;
+
+        Ok(DatabasePool {
+            readers,
+            writer: Mutex::new(Database::from_connection(conn)),
+        })
+    }
+
+    /// A read-only connection, returned to the pool when dropped.
+    pub fn reader(&self) -> Result<Database, DatabaseError> {
+        Ok(Database::from_pooled(self.readers.get()?))
+    }
+
+    /// The write connection. Writers queue here; readers don't.
+    ///
+    /// A panic while holding it doesn't take the library down with it: any
+    /// transaction the panicking writer left open is rolled back and the
+    /// connection is handed to the next caller.
+    pub fn writer(&self) -> MutexGuard<'_, Database> {
+        self.writer.lock().unwrap_or_else(|poisoned| {
+            self.writer.clear_poison();
+            let db = poisoned.into_inner();
+            db.rollback_open_transaction();
+            db
+        })
+    }
+}
@@ -0,0 +1,188 @@
+use crate::error::FilesystemError;
+use crate::filesystem::{Filesystem, ProjectFile};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+const REGISTRY_FILE: &str = "recent_projects.json";
+const THUMBNAIL_DIR: &str = "thumbnails";
+// Pinned projects don't count towards the limit
+const MAX_UNPINNED: usize = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentProject {
+    pub id: String,
+    pub path: String,
+    pub name: String,
+    pub author: Option<String>,
+    pub created: String,
+    pub modified: String,
+    pub last_opened: String,
+    pub pinned: bool,
+    pub cell_count: usize,
+    /// PNG preview, see `Exporter::thumbnail`
+    pub thumbnail_path: Option<String>,
+    /// Whether the project file was still there when the list was read
+    #[serde(default)]
+    pub exists: bool,
+}
+
+/// Projects the user recently opened or saved, kept in the app data directory.
+pub struct RecentProjects {
+    dir: PathBuf,
+    entries: Mutex<Vec<RecentProject>>,
+}
+
+impl RecentProjects {
+    pub fn open(dir: PathBuf) -> Self {
+        let entries = fs::read_to_string(dir.join(REGISTRY_FILE))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+
+        RecentProjects {
+            dir,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// Moves `path` to the top of the list with fresh metadata and thumbnail.
+    pub fn record(
+        &self,
+        filesystem: &Filesystem,
+        path: &Path,
+        project: &ProjectFile,
+        thumbnail: Option<&[u8]>,
+    ) -> Result<RecentProject, FilesystemError> {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let path = path.to_string_lossy().into_owned();
+
+        let mut entries = self.entries.lock().unwrap();
+        let existing = entries.iter().position(|e| e.path == path).map(|i| entries.remove(i));
+        let (id, pinned) = existing
+            .as_ref()
+            .map(|e| (e.id.clone(), e.pinned))
+            .unwrap_or_else(|| (Uuid::new_v4().to_string(), false));
+
+        let mut thumbnail_path = existing.and_then(|e| e.thumbnail_path);
+        if let Some(png) = thumbnail {
+            let thumbnail_dir = self.dir.join(THUMBNAIL_DIR);
+            fs::create_dir_all(&thumbnail_dir)
+                .map_err(FilesystemError::io("Failed to create thumbnail directory", &thumbnail_dir))?;
+            let file = thumbnail_dir.join(format!("{}.png", id));
+            filesystem.write_atomic(png, &file)?;
+            thumbnail_path = Some(file.to_string_lossy().into_owned());
+        }
+
+        let entry = RecentProject {
+            id,
+            path,
+            name: project.metadata.name.clone(),
+            author: project.metadata.author.clone(),
+            created: project.metadata.created.clone(),
+            modified: project.metadata.modified.clone(),
+            last_opened: chrono::Utc::now().to_rfc3339(),
+            pinned,
+            cell_count: project.scene.cells.len(),
+            thumbnail_path,
+            exists: true,
+        };
+        entries.insert(0, entry.clone());
+
+        // Drop the oldest unpinned entries past the limit
+        let mut unpinned = 0;
+        let mut dropped = Vec::new();
+        entries.retain(|e| {
+            if e.pinned {
+                return true;
+            }
+            unpinned += 1;
+            if unpinned <= MAX_UNPINNED {
+                return true;
+            }
+            dropped.push(e.clone());
+            false
+        });
+        for entry in &dropped {
+            remove_thumbnail(entry);
+        }
+
+        self.write(filesystem, &entries)?;
+        Ok(entry)
+    }
+
+    /// Pinned projects first, then most recently opened.
+    pub fn list(&self) -> Vec<RecentProject> {
+        let mut entries = self.entries.lock().unwrap().clone();
+        for entry in &mut entries {
+            entry.exists = Path::new(&entry.path).exists();
+        }
+        // Stable sort keeps the most-recent-first order within each half
+        entries.sort_by_key(|e| !e.pinned);
+        entries
+    }
+
+    pub fn set_pinned(
+        &self,
+        filesystem: &Filesystem,
+        path: &str,
+        pinned: bool,
+    ) -> Result<(), FilesystemError> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries
+            .iter_mut()
+            .find(|e| e.path == path)
+            .ok_or_else(|| FilesystemError::UnknownId {
+                kind: "Recent project",
+                id: path.to_string(),
+            })?;
+        entry.pinned = pinned;
+        self.write(filesystem, &entries)
+    }
+
+    pub fn remove(&self, filesystem: &Filesystem, path: &str) -> Result<(), FilesystemError> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|e| {
+            let keep = e.path != path;
+            if !keep {
+                remove_thumbnail(e);
+            }
+            keep
+        });
+        self.write(filesystem, &entries)
+    }
+
+    /// Forgets projects whose files no longer exist and returns their paths.
+    pub fn remove_missing(&self, filesystem: &Filesystem) -> Result<Vec<String>, FilesystemError> {
+        let mut entries = self.entries.lock().unwrap();
+        let mut removed = Vec::new();
+        entries.retain(|e| {
+            let keep = Path::new(&e.path).exists();
+            if !keep {
+                remove_thumbnail(e);
+                removed.push(e.path.clone());
+            }
+            keep
+        });
+        self.write(filesystem, &entries)?;
+        Ok(removed)
+    }
+
+    fn write(&self, filesystem: &Filesystem, entries: &[RecentProject]) -> Result<(), FilesystemError> {
+        fs::create_dir_all(&self.dir)
+            .map_err(FilesystemError::io("Failed to create app data directory", &self.dir))?;
+        let json = serde_json::to_vec_pretty(entries).map_err(|e| {
+            FilesystemError::Serialization(format!("Failed to serialize recent projects: {}", e))
+        })?;
+        filesystem.write_atomic(&json, &self.dir.join(REGISTRY_FILE))?;
+        Ok(())
+    }
+}
+
+fn remove_thumbnail(entry: &RecentProject) {
+    if let Some(path) = &entry.thumbnail_path {
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,219 @@
+use crate::filesystem::{is_gzip, split_trailer, ChecksumStatus, ProjectFile};
+use flate2::read::GzDecoder;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::Read;
+
+/// A project salvaged from a damaged file, and what was lost.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveredProject {
+    pub project: ProjectFile,
+    pub report: RecoveryReport,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveryReport {
+    pub checksum: ChecksumStatus,
+    /// The compressed data or the JSON document ended early
+    pub truncated: bool,
+    pub cells: usize,
+    pub connections: usize,
+    pub components: usize,
+    pub groups: usize,
+    pub dropped: Vec<DroppedEntry>,
+}
+
+/// Part of the file that could not be read, by JSON path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DroppedEntry {
+    pub path: String,
+    pub error: String,
+}
+
+/// Rebuilds as much of a project as possible from raw `.cellforge` bytes,
+/// compressed or plain.
+///
+/// `fallback` supplies metadata, settings and camera when those sections
+/// are unreadable.
+pub fn salvage(data: &[u8], fallback: ProjectFile) -> Result<RecoveredProject, String> {
+    let (json, checksum, mut truncated) = if is_gzip(data) {
+        let (compressed, checksum) = split_trailer(data);
+        let (json, truncated) = decompress_lenient(compressed);
+        (json, checksum, truncated)
+    } else {
+        (data.to_vec(), ChecksumStatus::Missing, false)
+    };
+    if json.is_empty() {
+        return Err("Nothing could be recovered: no project data could be read".to_string());
+    }
+
+    let text = String::from_utf8_lossy(&json);
+    let value: Value = match serde_json::from_str(&text) {
+        Ok(value) => value,
+        Err(e) => {
+            truncated = true;
+            close_truncated_json(&text)
+                .and_then(|closed| serde_json::from_str(&closed).ok())
+                .ok_or_else(|| format!("Nothing could be recovered: {}", e))?
+        }
+    };
+
+    let mut project = fallback;
+    let mut dropped = Vec::new();
+
+    if let Some(version) = value.get("version").and_then(Value::as_str) {
+        project.version = version.to_string();
+    }
+    section(&value, "metadata", &mut project.metadata, &mut dropped);
+    section(&value, "settings", &mut project.settings, &mut dropped);
+    section(&value, "camera", &mut project.camera, &mut dropped);
+
+    let scene_value = value.get("scene");
+    let scene = &mut project.scene;
+    entries(scene_value, "cells", &mut scene.cells, &mut dropped);
+    entries(scene_value, "connections", &mut scene.connections, &mut dropped);
+    entries(scene_value, "components", &mut scene.components, &mut dropped);
+    entries(scene_value, "groups", &mut scene.groups, &mut dropped);
+
+    let report = RecoveryReport {
+        checksum,
+        truncated,
+        cells: project.scene.cells.len(),
+        connections: project.scene.connections.len(),
+        components: project.scene.components.len(),
+        groups: project.scene.groups.len(),
+        dropped,
+    };
+
+    Ok(RecoveredProject { project, report })
+}
+
+/// Keeps everything the decoder produced before it hit damaged data.
+fn decompress_lenient(compressed: &[u8]) -> (Vec<u8>, bool) {
+    let mut decoder = GzDecoder::new(compressed);
+    let mut json = Vec::new();
+    let mut buffer = [0u8; 8192];
+    loop {
+        match decoder.read(&mut buffer) {
+            Ok(0) => return (json, false),
+            Ok(n) => json.extend_from_slice(&buffer[..n]),
+            Err(_) => return (json, true),
+        }
+    }
+}
+
+/// Replaces `target` with the section at `key` if it deserializes.
+fn section<T: DeserializeOwned>(value: &Value, key: &str, target: &mut T, dropped: &mut Vec<DroppedEntry>) {
+    let Some(section) = value.get(key) else {
+        dropped.push(DroppedEntry {
+            path: key.to_string(),
+            error: "missing; defaults were used".to_string(),
+        });
+        return;
+    };
+
+    match deserialize(section) {
+        Ok(parsed) => *target = parsed,
+        Err((path, error)) => dropped.push(DroppedEntry {
+            path: join_path(key, &path),
+            error: format!("{}; defaults were used", error),
+        }),
+    }
+}
+
+/// Deserializes each entry of the scene map at `key` on its own, so one
+/// broken entry only loses itself.
+fn entries<T: DeserializeOwned>(
+    scene: Option<&Value>,
+    key: &str,
+    target: &mut HashMap<String, T>,
+    dropped: &mut Vec<DroppedEntry>,
+) {
+    let base = format!("scene.{}", key);
+    let map = match scene.and_then(|scene| scene.get(key)) {
+        Some(Value::Object(map)) => map,
+        other => {
+            let error = match other {
+                Some(_) => "expected an object keyed by uuid",
+                None => "missing",
+            };
+            dropped.push(DroppedEntry {
+                path: base,
+                error: error.to_string(),
+            });
+            return;
+        }
+    };
+
+    for (uuid, entry) in map {
+        match deserialize(entry) {
+            Ok(parsed) => {
+                target.insert(uuid.clone(), parsed);
+            }
+            Err((path, error)) => dropped.push(DroppedEntry {
+                path: join_path(&format!("{}.{}", base, uuid), &path),
+                error,
+            }),
+        }
+    }
+}
+
+fn deserialize<T: DeserializeOwned>(value: &Value) -> Result<T, (String, String)> {
+    serde_path_to_error::deserialize(value).map_err(|e| (e.path().to_string(), e.inner().to_string()))
+}
+
+fn join_path(base: &str, inner: &str) -> String {
+    if inner == "." {
+        base.to_string()
+    } else {
+        format!("{}.{}", base, inner)
+    }
+}
+
+/// Cuts a truncated JSON document back to its last complete element and
+/// closes whatever objects and arrays are still open.
+fn close_truncated_json(text: &str) -> Option<String> {
+    let mut open: Vec<char> = Vec::new();
+    let mut cut: Option<(usize, Vec<char>)> = None;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in text.char_indices() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_string = true,
+            '{' => {
+                open.push('}');
+                cut = Some((i + 1, open.clone()));
+            }
+            '[' => {
+                open.push(']');
+                cut = Some((i + 1, open.clone()));
+            }
+            '}' | ']' => {
+                open.pop();
+                cut = Some((i + 1, open.clone()));
+            }
+            // Everything before a separator is a complete element
+            ',' => cut = Some((i, open.clone())),
+            _ => {}
+        }
+    }
+
+    let (end, open) = cut?;
+    let mut closed = text[..end].to_string();
+    closed.extend(open.iter().rev());
+    Some(closed)
+}
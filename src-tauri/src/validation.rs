@@ -0,0 +1,310 @@
+use crate::database::Database;
+use crate::filesystem::{ProjectFile, Scene};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Database ids a project may reference.
+#[derive(Debug, Default)]
+pub struct LibraryIds {
+    pub cells: HashSet<i64>,
+    pub materials: HashSet<i64>,
+    pub bms: HashSet<i64>,
+    pub shapes: HashSet<i64>,
+}
+
+impl LibraryIds {
+    pub fn load(db: &Database) -> rusqlite::Result<Self> {
+        Ok(LibraryIds {
+            cells: db.get_cells(None)?.into_iter().map(|c| c.id).collect(),
+            materials: db.get_materials()?.into_iter().map(|m| m.id).collect(),
+            bms: db.get_bms()?.into_iter().map(|b| b.id).collect(),
+            shapes: db.get_shapes()?.into_iter().map(|s| s.id).collect(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IssueKind {
+    /// Map key differs from the entry's embedded `uuid`
+    KeyMismatch,
+    /// Connection endpoint is neither a cell nor a component
+    DanglingConnection,
+    /// `Group.member_uuids` lists a missing entity or repeats one
+    DanglingGroupMember,
+    /// `CellInstance.group_id` names a missing group
+    DanglingGroupId,
+    /// Cell names a group that does not list it as a member
+    GroupMembership,
+    UnknownCell,
+    UnknownMaterial,
+    UnknownReference,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationIssue {
+    pub kind: IssueKind,
+    /// JSON path of the offending field, e.g. `scene.connections.<key>.source_uuid`
+    pub path: String,
+    pub message: String,
+    pub repaired: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    fn push(&mut self, kind: IssueKind, path: String, message: String, repaired: bool) {
+        self.issues.push(ValidationIssue {
+            kind,
+            path,
+            message,
+            repaired,
+        });
+    }
+}
+
+/// A project after validation, repaired if that was requested.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidatedProject {
+    pub project: ProjectFile,
+    pub report: ValidationReport,
+}
+
+/// Checks references inside the scene and against the library.
+///
+/// With `repair`, broken references are fixed in place: embedded uuids are
+/// reset to their map key, dangling connections removed, dangling group links cleared
+/// and unknown material or component references unset. Cells whose
+/// `cell_id` is not in the library are reported but never removed.
+pub fn validate(project: &mut ProjectFile, library: &LibraryIds, repair: bool) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    let scene = &mut project.scene;
+
+    check_keys(scene, repair, &mut report);
+    check_connections(scene, library, repair, &mut report);
+    check_groups(scene, repair, &mut report);
+    check_library(scene, library, repair, &mut report);
+
+    report
+}
+
+/// Map keys are what every other reference resolves against, so a
+/// mismatched embedded uuid is overwritten with its key.
+fn check_keys(scene: &mut Scene, repair: bool, report: &mut ValidationReport) {
+    fn reconcile<T>(
+        map: &mut HashMap<String, T>,
+        section: &str,
+        uuid_of: fn(&mut T) -> &mut String,
+        repair: bool,
+        report: &mut ValidationReport,
+    ) {
+        let mut mismatched: Vec<(&String, &mut T)> = map
+            .iter_mut()
+            .filter_map(|(key, entry)| (*key != *uuid_of(entry)).then_some((key, entry)))
+            .collect();
+        mismatched.sort_by(|a, b| a.0.cmp(b.0));
+
+        for (key, entry) in mismatched {
+            let uuid = uuid_of(entry);
+            report.push(
+                IssueKind::KeyMismatch,
+                format!("scene.{}.{}.uuid", section, key),
+                format!("Entry is stored under '{}' but its uuid is '{}'", key, uuid),
+                repair,
+            );
+            if repair {
+                *uuid = key.clone();
+            }
+        }
+    }
+
+    reconcile(&mut scene.cells, "cells", |c| &mut c.uuid, repair, report);
+    reconcile(&mut scene.connections, "connections", |c| &mut c.uuid, repair, report);
+    reconcile(&mut scene.components, "components", |c| &mut c.uuid, repair, report);
+    reconcile(&mut scene.groups, "groups", |g| &mut g.uuid, repair, report);
+}
+
+fn check_connections(scene: &mut Scene, library: &LibraryIds, repair: bool, report: &mut ValidationReport) {
+    let mut keys: Vec<String> = scene.connections.keys().cloned().collect();
+    keys.sort();
+
+    for key in keys {
+        let connection = &scene.connections[&key];
+        let mut dangling = false;
+        for (field, uuid) in [
+            ("source_uuid", &connection.source_uuid),
+            ("target_uuid", &connection.target_uuid),
+        ] {
+            if !scene.cells.contains_key(uuid) && !scene.components.contains_key(uuid) {
+                dangling = true;
+                report.push(
+                    IssueKind::DanglingConnection,
+                    format!("scene.connections.{}.{}", key, field),
+                    format!("Connection endpoint '{}' does not exist", uuid),
+                    repair,
+                );
+            }
+        }
+
+        if dangling {
+            if repair {
+                scene.connections.remove(&key);
+            }
+            continue;
+        }
+
+        if let (None, Some(material_key)) = (connection.material_id, &connection.material_key) {
+            report.push(
+                IssueKind::UnknownMaterial,
+                format!("scene.connections.{}.material_key", key),
+                format!("Material '{}' is not in the library", material_key),
+                repair,
+            );
+            if repair {
+                scene.connections.get_mut(&key).unwrap().material_key = None;
+            }
+        } else if let Some(material_id) = connection.material_id {
+            if !library.materials.contains(&material_id) {
+                report.push(
+                    IssueKind::UnknownMaterial,
+                    format!("scene.connections.{}.material_id", key),
+                    format!("Material {} is not in the library", material_id),
+                    repair,
+                );
+                if repair {
+                    scene.connections.get_mut(&key).unwrap().material_id = None;
+                }
+            }
+        }
+    }
+}
+
+fn check_groups(scene: &mut Scene, repair: bool, report: &mut ValidationReport) {
+    let mut group_keys: Vec<String> = scene.groups.keys().cloned().collect();
+    group_keys.sort();
+
+    for key in &group_keys {
+        let group = scene.groups.get_mut(key).unwrap();
+        let mut seen = HashSet::new();
+        let mut kept = Vec::with_capacity(group.member_uuids.len());
+
+        for (index, member) in group.member_uuids.iter().enumerate() {
+            let exists = scene.cells.contains_key(member) || scene.components.contains_key(member);
+            let duplicate = !seen.insert(member.clone());
+            if exists && !duplicate {
+                kept.push(member.clone());
+                continue;
+            }
+
+            let message = if duplicate {
+                format!("Member '{}' is listed more than once", member)
+            } else {
+                format!("Member '{}' does not exist", member)
+            };
+            report.push(
+                IssueKind::DanglingGroupMember,
+                format!("scene.groups.{}.member_uuids[{}]", key, index),
+                message,
+                repair,
+            );
+        }
+
+        if repair {
+            group.member_uuids = kept;
+        }
+    }
+
+    let mut cell_keys: Vec<String> = scene.cells.keys().cloned().collect();
+    cell_keys.sort();
+
+    for key in cell_keys {
+        let Some(group_id) = scene.cells[&key].group_id.clone() else {
+            continue;
+        };
+
+        match scene.groups.get_mut(&group_id) {
+            None => {
+                report.push(
+                    IssueKind::DanglingGroupId,
+                    format!("scene.cells.{}.group_id", key),
+                    format!("Group '{}' does not exist", group_id),
+                    repair,
+                );
+                if repair {
+                    scene.cells.get_mut(&key).unwrap().group_id = None;
+                }
+            }
+            Some(group) if !group.member_uuids.contains(&key) => {
+                report.push(
+                    IssueKind::GroupMembership,
+                    format!("scene.cells.{}.group_id", key),
+                    format!("Group '{}' does not list this cell as a member", group_id),
+                    repair,
+                );
+                if repair {
+                    group.member_uuids.push(key);
+                }
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+fn check_library(scene: &mut Scene, library: &LibraryIds, repair: bool, report: &mut ValidationReport) {
+    let mut cells: Vec<_> = scene.cells.iter().collect();
+    cells.sort_by(|a, b| a.0.cmp(b.0));
+    for (key, cell) in cells {
+        if !library.cells.contains(&cell.cell_id) {
+            let (field, message) = match &cell.cell_key {
+                Some(cell_key) => ("cell_key", format!("Cell '{}' is not in the library", cell_key)),
+                None => ("cell_id", format!("Cell {} is not in the library", cell.cell_id)),
+            };
+            report.push(
+                IssueKind::UnknownCell,
+                format!("scene.cells.{}.{}", key, field),
+                message,
+                false,
+            );
+        }
+    }
+
+    let mut keys: Vec<String> = scene.components.keys().cloned().collect();
+    keys.sort();
+    for key in keys {
+        let component = scene.components.get_mut(&key).unwrap();
+        let (table, label) = match component.component_type.as_str() {
+            "bms" => (&library.bms, "BMS"),
+            "shape" => (&library.shapes, "Shape"),
+            _ => continue,
+        };
+
+        let Some(reference_id) = component.reference_id else {
+            if let Some(reference_key) = &component.reference_key {
+                report.push(
+                    IssueKind::UnknownReference,
+                    format!("scene.components.{}.reference_key", key),
+                    format!("{} '{}' is not in the library", label, reference_key),
+                    repair,
+                );
+                if repair {
+                    component.reference_key = None;
+                }
+            }
+            continue;
+        };
+        if !table.contains(&reference_id) {
+            report.push(
+                IssueKind::UnknownReference,
+                format!("scene.components.{}.reference_id", key),
+                format!("{} {} is not in the library", label, reference_id),
+                repair,
+            );
+            if repair {
+                component.reference_id = None;
+            }
+        }
+    }
+}
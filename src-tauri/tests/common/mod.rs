@@ -0,0 +1,97 @@
+//! Fixtures shared by the integration tests.
+
+#![allow(dead_code)]
+
+use cellforge_lib::database::{Cell, Database};
+use cellforge_lib::filesystem::{CellInstance, Euler, Filesystem, Group, ProjectFile, Vector3};
+use std::path::{Path, PathBuf};
+
+/// Directory removed again when the test is done with it.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("cellforge-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+pub fn library() -> Database {
+    let db = Database::open_in_memory().unwrap();
+    db.init_schema().unwrap();
+    db
+}
+
+pub fn cell(manufacturer: &str, model: &str) -> Cell {
+    Cell {
+        id: 0,
+        manufacturer: manufacturer.to_string(),
+        model: model.to_string(),
+        form_factor: "18650".to_string(),
+        chemistry: "NMC".to_string(),
+        nominal_voltage: 3.6,
+        max_voltage: 4.2,
+        min_voltage: 2.5,
+        capacity_mah: 3000,
+        max_discharge_a: 15.0,
+        max_charge_a: 4.0,
+        internal_res_mohm: Some(20.0),
+        weight_g: 48.0,
+        diameter_mm: Some(18.0),
+        length_mm: 65.0,
+        width_mm: None,
+        height_mm: None,
+        datasheet_url: None,
+        thermal_limit_c: None,
+        cycle_life: None,
+        user_added: false,
+        deprecated: false,
+    }
+}
+
+pub fn project() -> ProjectFile {
+    Filesystem::headless().create_new_project("Test pack".to_string())
+}
+
+/// Places a cell of the library cell `cell_id` at `(x, y)`.
+pub fn add_cell(project: &mut ProjectFile, uuid: &str, cell_id: i64, x: f64, y: f64) {
+    project.scene.cells.insert(
+        uuid.to_string(),
+        CellInstance {
+            uuid: uuid.to_string(),
+            cell_id,
+            cell_key: None,
+            position: Vector3([x, y, 0.0]),
+            rotation: Euler([0.0, 0.0, 0.0]),
+            custom_label: None,
+            group_id: None,
+        },
+    );
+}
+
+pub fn add_group(project: &mut ProjectFile, uuid: &str, name: &str, members: &[&str]) {
+    project.scene.groups.insert(
+        uuid.to_string(),
+        Group {
+            uuid: uuid.to_string(),
+            name: name.to_string(),
+            member_uuids: members.iter().map(|m| m.to_string()).collect(),
+            color: None,
+            locked: false,
+            visible: true,
+        },
+    );
+}
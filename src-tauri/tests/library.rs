@@ -0,0 +1,141 @@
+mod common;
+
+use cellforge_lib::catalog::{self, Catalog, ChangeKind};
+use cellforge_lib::cell_samples::{self, SampleImportOptions};
+use cellforge_lib::filesystem::Filesystem;
+use cellforge_lib::library_file::{self, LibraryExportOptions};
+use common::{cell, library, TempDir};
+
+fn publish(
+    db: &cellforge_lib::database::Database,
+    path: &std::path::Path,
+    version: &str,
+) -> Catalog {
+    let options = LibraryExportOptions {
+        catalog_version: Some(version.to_string()),
+        ..Default::default()
+    };
+    library_file::export(&Filesystem::headless(), db, path, &options).unwrap();
+    Catalog::fetch(&path.to_string_lossy()).unwrap()
+}
+
+#[test]
+fn catalog_review_lists_changes_and_apply_writes_the_approved_ones() {
+    let dir = TempDir::new("catalog");
+    let published = library();
+    let p28a = published.insert_cell(&cell("Molicel", "P28A")).unwrap();
+    published.insert_cell(&cell("Samsung", "30Q")).unwrap();
+    let catalog = publish(&published, &dir.path().join("v1.json"), "1");
+
+    let local = library();
+    let mut own = cell("Samsung", "30Q");
+    own.capacity_mah = 2900;
+    own.user_added = true;
+    local.insert_cell(&own).unwrap();
+
+    let review = catalog::review(&local, &catalog).unwrap();
+    assert_eq!(review.version, "1");
+    assert_eq!(review.local_version, None);
+    assert_eq!(review.changes.len(), 2);
+    let add = review
+        .changes
+        .iter()
+        .find(|c| c.id == "cells:molicel/p28a")
+        .unwrap();
+    assert_eq!(add.kind, ChangeKind::Add);
+    // The local 30Q was added on this install, so the catalog leaves it be
+    let update = review
+        .changes
+        .iter()
+        .find(|c| c.id == "cells:samsung/30q")
+        .unwrap();
+    assert_eq!(update.kind, ChangeKind::Update);
+    assert!(update.protected);
+    assert_eq!(update.changes.len(), 1);
+    assert_eq!(update.changes[0].field, "capacity_mah");
+
+    let approved: Vec<String> = review.changes.iter().map(|c| c.id.clone()).collect();
+    let result = catalog::apply(&local, &catalog, &approved).unwrap();
+    assert_eq!((result.added, result.updated, result.skipped), (1, 0, 1));
+    assert_eq!(local.get_cells(None).unwrap().len(), 2);
+    assert_eq!(
+        catalog::status(&local).unwrap().version.as_deref(),
+        Some("1")
+    );
+
+    // A new catalog version updates the row it added, once approved
+    let mut changed = cell("Molicel", "P28A");
+    changed.max_discharge_a = 35.0;
+    published.update_cell(p28a, &changed).unwrap();
+    let catalog = publish(&published, &dir.path().join("v2.json"), "2");
+    let review = catalog::review(&local, &catalog).unwrap();
+    assert_eq!(review.local_version.as_deref(), Some("1"));
+    let update = review
+        .changes
+        .iter()
+        .find(|c| c.id == "cells:molicel/p28a")
+        .unwrap();
+    assert_eq!(update.kind, ChangeKind::Update);
+    assert!(!update.protected);
+
+    let result = catalog::apply(&local, &catalog, &[]).unwrap();
+    assert_eq!(result.updated, 0);
+    let result = catalog::apply(&local, &catalog, &[update.id.clone()]).unwrap();
+    assert_eq!(result.updated, 1);
+    let applied = local
+        .get_cell_by_id(update.local_id.unwrap())
+        .unwrap()
+        .unwrap();
+    assert_eq!(applied.max_discharge_a, 35.0);
+    assert!(!applied.user_added);
+
+    assert!(catalog.check_revision(&review.revision).is_ok());
+    assert!(catalog.check_revision("00000000").is_err());
+}
+
+#[test]
+fn sample_statistics_give_median_and_histogram() {
+    let dir = TempDir::new("samples");
+    let db = library();
+    let cell_id = db.insert_cell(&cell("Samsung", "30Q")).unwrap();
+
+    let log = dir.path().join("log.csv");
+    std::fs::write(
+        &log,
+        "Serial;Capacity (Ah);IR (mOhm)\nA1;2,70;22\nA2;2,80;21\nA3;2,90;20\nA4;3,00;19\nA5;bad;18\n",
+    )
+    .unwrap();
+    let report =
+        cell_samples::import_samples(&db, cell_id, &log, &SampleImportOptions::default()).unwrap();
+    assert_eq!(report.added, 4);
+    assert_eq!(report.rejected.len(), 1);
+    assert_eq!(report.rejected[0].serial, "A5");
+
+    let stats = cell_samples::statistics(&db, cell_id, Some(2)).unwrap();
+    assert_eq!(stats.samples, 4);
+    let capacity = stats.capacity_mah.unwrap();
+    assert_eq!(capacity.count, 4);
+    assert!((capacity.median - 2850.0).abs() < 1e-6);
+    assert!((capacity.mean - 2850.0).abs() < 1e-6);
+    assert_eq!((capacity.min, capacity.max), (2700.0, 3000.0));
+    assert_eq!(capacity.rated, Some(3000.0));
+    let counts: Vec<usize> = capacity.histogram.iter().map(|b| b.count).collect();
+    assert_eq!(counts, vec![2, 2]);
+    assert_eq!(capacity.histogram[1].end, 3000.0);
+
+    let resistance = stats.internal_res_mohm.unwrap();
+    assert!((resistance.median - 20.5).abs() < 1e-6);
+    assert!(stats.voltage.is_none());
+
+    // Measuring a serial again replaces its values
+    std::fs::write(&log, "serial,capacity_mah\nA1,2750\nA6,2950\n").unwrap();
+    let report =
+        cell_samples::import_samples(&db, cell_id, &log, &SampleImportOptions::default()).unwrap();
+    assert_eq!((report.added, report.updated), (1, 1));
+    let capacity = cell_samples::statistics(&db, cell_id, None)
+        .unwrap()
+        .capacity_mah
+        .unwrap();
+    assert_eq!(capacity.count, 5);
+    assert!((capacity.median - 2900.0).abs() < 1e-6);
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use cellforge_lib::database::CellSample;
+use cellforge_lib::matching::{self, MatchOptions};
+use common::{add_cell, add_group, cell, library, project};
+use std::collections::BTreeSet;
+
+#[test]
+fn match_cells_balances_group_capacity() {
+    let db = library();
+    let cell_id = db.insert_cell(&cell("Samsung", "30Q")).unwrap();
+    for (serial, capacity) in [
+        ("S1", 3000.0),
+        ("S2", 2900.0),
+        ("S3", 2800.0),
+        ("S4", 2700.0),
+        ("S5", 2400.0),
+    ] {
+        db.upsert_cell_sample(&CellSample {
+            id: 0,
+            cell_id,
+            serial: serial.to_string(),
+            capacity_mah: Some(capacity),
+            internal_res_mohm: None,
+            voltage: None,
+            measured_at: None,
+        })
+        .unwrap();
+    }
+
+    let mut project = project();
+    for (i, uuid) in ["a", "b", "c", "d"].into_iter().enumerate() {
+        add_cell(
+            &mut project,
+            uuid,
+            cell_id,
+            (i % 2) as f64 * 19.0,
+            (i / 2) as f64 * 19.0,
+        );
+    }
+    add_cell(&mut project, "loose", cell_id, 100.0, 0.0);
+    add_group(&mut project, "g1", "P1", &["a", "b"]);
+    add_group(&mut project, "g2", "P2", &["c", "d"]);
+
+    let samples = matching::measured_samples(&db, &project).unwrap();
+    assert_eq!(samples.len(), 5);
+    let options = MatchOptions {
+        resistance_weight: 0.0,
+        serials: None,
+    };
+    let matched = matching::match_cells(&mut project, &samples, &options).unwrap();
+
+    // 3000 + 2700 and 2900 + 2800; the outlier is left over
+    assert_eq!(matched.capacity_spread_mah, 0.0);
+    assert_eq!(matched.capacity_spread_pct, 0.0);
+    assert_eq!(matched.unused, vec!["S5".to_string()]);
+    assert_eq!(matched.ungrouped, vec!["loose".to_string()]);
+    assert_eq!(matched.groups.len(), 2);
+    for group in &matched.groups {
+        assert_eq!(group.cells, 2);
+        assert_eq!(group.capacity_mah, 5700.0);
+    }
+
+    // Every grouped cell is labelled with its own serial
+    let labels: BTreeSet<_> = ["a", "b", "c", "d"]
+        .iter()
+        .map(|uuid| project.scene.cells[*uuid].custom_label.clone().unwrap())
+        .collect();
+    assert_eq!(labels.len(), 4);
+    assert!(!labels.contains("S5"));
+    assert_eq!(project.scene.cells["loose"].custom_label, None);
+    for placement in &matched.placements {
+        assert_eq!(
+            project.scene.cells[&placement.cell_uuid]
+                .custom_label
+                .as_deref(),
+            Some(placement.serial.as_str())
+        );
+    }
+
+    let ungrouped = {
+        let mut project = common::project();
+        add_cell(&mut project, "a", cell_id, 0.0, 0.0);
+        matching::match_cells(&mut project, &samples, &options)
+    };
+    assert!(ungrouped.is_err());
+}
@@ -0,0 +1,207 @@
+mod common;
+
+use cellforge_lib::diff::{self, ChangeKind, ConflictKind};
+use cellforge_lib::error::FilesystemError;
+use cellforge_lib::filesystem::{ChecksumStatus, Filesystem, SaveFormat, Vector3};
+use cellforge_lib::library_keys::{LibraryKeys, UNRESOLVED_ID};
+use cellforge_lib::recovery;
+use common::{add_cell, cell, library, project, TempDir};
+
+fn pack(cells: usize) -> cellforge_lib::filesystem::ProjectFile {
+    let mut project = project();
+    for i in 0..cells {
+        add_cell(
+            &mut project,
+            &format!("cell-{:02}", i),
+            1,
+            i as f64 * 19.0,
+            0.0,
+        );
+    }
+    project
+}
+
+#[test]
+fn compressed_and_plain_projects_round_trip() {
+    let dir = TempDir::new("round-trip");
+    let filesystem = Filesystem::headless();
+    let project = pack(3);
+
+    for (format, name) in [
+        (SaveFormat::Compressed, "pack.cellforge"),
+        (SaveFormat::Plain, "plain.cellforge"),
+    ] {
+        let path = dir.path().join(name);
+        filesystem.save_project(&project, &path, format).unwrap();
+        assert_eq!(filesystem.detect_format(&path), Some(format));
+
+        let loaded = filesystem.load_project(&path).unwrap();
+        assert_eq!(loaded.metadata.name, project.metadata.name);
+        assert_eq!(loaded.scene.cells.len(), 3);
+        assert_eq!(loaded.scene.cells["cell-02"].position.0, [38.0, 0.0, 0.0]);
+    }
+
+    // Plain files are canonical, so saving again gives the same bytes
+    let plain = dir.path().join("plain.cellforge");
+    let first = std::fs::read(&plain).unwrap();
+    let loaded = filesystem.load_project(&plain).unwrap();
+    assert_eq!(filesystem.encode_project_plain(&loaded).unwrap(), first);
+}
+
+#[test]
+fn compressed_projects_carry_a_checksum_trailer() {
+    let dir = TempDir::new("trailer");
+    let filesystem = Filesystem::headless();
+    let data = filesystem.encode_project(&pack(2)).unwrap();
+
+    let trailer = &data[data.len() - 16..];
+    assert_eq!(&trailer[..4], b"CFCK");
+    let length = u64::from_le_bytes(trailer[4..12].try_into().unwrap());
+    assert_eq!(length as usize, data.len() - 16);
+    let checksum = u32::from_le_bytes(trailer[12..].try_into().unwrap());
+    assert_eq!(checksum, crc32fast::hash(&data[..data.len() - 16]));
+
+    // A flipped byte in the payload is caught before decompressing
+    let mut damaged = data.clone();
+    damaged[length as usize / 2] ^= 0xff;
+    let path = dir.path().join("damaged.cellforge");
+    std::fs::write(&path, &damaged).unwrap();
+    match filesystem.load_project(&path) {
+        Err(FilesystemError::Corrupt { reason, .. }) => assert_eq!(reason, "checksum mismatch"),
+        other => panic!("expected a checksum mismatch, got {:?}", other.map(|_| ())),
+    }
+
+    // Files from before the trailer existed still load
+    let legacy = dir.path().join("legacy.cellforge");
+    std::fs::write(&legacy, &data[..length as usize]).unwrap();
+    assert_eq!(
+        filesystem.load_project(&legacy).unwrap().scene.cells.len(),
+        2
+    );
+}
+
+#[test]
+fn salvage_keeps_what_a_truncated_file_still_holds() {
+    let filesystem = Filesystem::headless();
+    let project = pack(40);
+    let data = filesystem.encode_project(&project).unwrap();
+
+    let truncated = &data[..data.len() * 3 / 4];
+    let recovered = recovery::salvage(
+        truncated,
+        filesystem.create_new_project("Fallback".to_string()),
+    )
+    .unwrap();
+    let report = &recovered.report;
+    assert!(report.truncated);
+    assert_eq!(report.checksum, ChecksumStatus::Missing);
+    assert!(
+        report.cells > 0 && report.cells < 40,
+        "recovered {} cells",
+        report.cells
+    );
+    assert_eq!(recovered.project.scene.cells.len(), report.cells);
+    for (key, cell) in &recovered.project.scene.cells {
+        assert_eq!(&cell.uuid, key);
+        assert_eq!(cell.position.0, project.scene.cells[key].position.0);
+    }
+
+    let plain = filesystem.encode_project_plain(&project).unwrap();
+    let recovered = recovery::salvage(
+        &plain[..plain.len() / 2],
+        filesystem.create_new_project("Fallback".to_string()),
+    )
+    .unwrap();
+    assert!(recovered.report.truncated);
+    assert!(recovered.report.cells < 40);
+
+    assert!(recovery::salvage(&data[..8], project).is_err());
+}
+
+#[test]
+fn library_keys_follow_cells_between_installs() {
+    let here = library();
+    let id = here.insert_cell(&cell("Samsung", "30Q")).unwrap();
+    let mut project = project();
+    add_cell(&mut project, "a", id, 0.0, 0.0);
+    add_cell(&mut project, "b", 999, 19.0, 0.0);
+
+    LibraryKeys::load(&here).unwrap().stamp(&mut project);
+    assert_eq!(
+        project.scene.cells["a"].cell_key.as_deref(),
+        Some("samsung/30q")
+    );
+    assert_eq!(project.scene.cells["b"].cell_key, None);
+
+    // Elsewhere the same cell has another row id, and the key finds it
+    let there = library();
+    there.insert_cell(&cell("Molicel", "P28A")).unwrap();
+    let there_id = there.insert_cell(&cell("  SAMSUNG ", "30q")).unwrap();
+    assert_ne!(there_id, id);
+    LibraryKeys::load(&there).unwrap().resolve(&mut project);
+    assert_eq!(project.scene.cells["a"].cell_id, there_id);
+    // Cells without a key keep their id
+    assert_eq!(project.scene.cells["b"].cell_id, 999);
+
+    // A key the library doesn't know leaves the cell unresolved
+    let empty = library();
+    LibraryKeys::load(&empty).unwrap().resolve(&mut project);
+    assert_eq!(project.scene.cells["a"].cell_id, UNRESOLVED_ID);
+}
+
+#[test]
+fn diff_reports_moved_added_and_changed_settings() {
+    let base = pack(2);
+    let mut after = base.clone();
+    after.scene.cells.get_mut("cell-00").unwrap().position = Vector3([5.0, 5.0, 0.0]);
+    after.scene.cells.remove("cell-01");
+    add_cell(&mut after, "new", 1, 40.0, 0.0);
+    after.settings.grid_size = 2.5;
+
+    let diff = diff::diff(&base, &after).unwrap();
+    let kind = |uuid: &str| diff.cells.iter().find(|c| c.uuid == uuid).map(|c| c.kind);
+    assert_eq!(kind("cell-00"), Some(ChangeKind::Modified));
+    assert_eq!(kind("cell-01"), Some(ChangeKind::Removed));
+    assert_eq!(kind("new"), Some(ChangeKind::Added));
+
+    let summary = diff.summary();
+    assert_eq!(
+        (
+            summary.cells.added,
+            summary.cells.removed,
+            summary.cells.modified
+        ),
+        (1, 1, 1)
+    );
+    assert_eq!(summary.cells_moved, 1);
+    assert_eq!(summary.settings, vec!["grid_size".to_string()]);
+
+    assert!(diff::diff(&base, &base).unwrap().cells.is_empty());
+}
+
+#[test]
+fn merge_combines_both_sides_and_reports_conflicts() {
+    let base = pack(2);
+    let mut ours = base.clone();
+    ours.scene.cells.get_mut("cell-00").unwrap().position = Vector3([5.0, 5.0, 0.0]);
+    ours.settings.grid_size = 2.0;
+    let mut theirs = base.clone();
+    add_cell(&mut theirs, "theirs", 1, 60.0, 0.0);
+    theirs.scene.cells.get_mut("cell-01").unwrap().custom_label = Some("B2".to_string());
+    theirs.settings.grid_size = 5.0;
+
+    let merged = diff::merge(&base, &ours, &theirs).unwrap();
+    let cells = &merged.project.scene.cells;
+    assert_eq!(cells.len(), 3);
+    assert_eq!(cells["cell-00"].position.0, [5.0, 5.0, 0.0]);
+    assert_eq!(cells["cell-01"].custom_label.as_deref(), Some("B2"));
+    assert!(cells.contains_key("theirs"));
+
+    // Both changed the grid; ours wins and the conflict is reported
+    assert_eq!(merged.project.settings.grid_size, 2.0);
+    assert_eq!(merged.conflicts.len(), 1);
+    let conflict = &merged.conflicts[0];
+    assert_eq!(conflict.kind, ConflictKind::Modified);
+    assert_eq!(conflict.path, "settings.grid_size");
+    assert_eq!(conflict.theirs, serde_json::json!(5.0));
+}
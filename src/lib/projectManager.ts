@@ -1,4 +1,4 @@
-import { ProjectFile, ProjectMetadata, Scene, Settings, Camera } from '../types/project';
+import { ProjectFile, ProjectMetadata, Scene, Settings, Camera, BundleExportResult, BundleImport, ExportFormat, ExportResult, RecoveredProject, RecoveryInfo, SaveFormat, ValidatedProject } from '../types/project';
 import { useSceneStore } from '../stores/sceneStore';
 import { useUIStore } from '../stores/uiStore';
 import { api } from './webApi';
@@ -8,7 +8,9 @@ import { api } from './webApi';
  */
 export class ProjectManager {
   private static autosaveInterval: NodeJS.Timeout | null = null;
-  private static readonly AUTOSAVE_INTERVAL = 60000; // 1 minute
+  private static unsubscribeScene: (() => void) | null = null;
+  private static sceneDirty = false;
+  private static readonly AUTOSAVE_INTERVAL = 2000; // backend debounces further
 
   /**
    * Create a new project
@@ -25,9 +27,9 @@ export class ProjectManager {
   /**
    * Save project to file
    */
-  static async saveProject(project: ProjectFile, path: string): Promise<void> {
+  static async saveProject(project: ProjectFile, path: string, format?: SaveFormat): Promise<void> {
     try {
-      await api.saveProject(project, path);
+      await api.saveProject(project, path, format);
     } catch (error) {
       console.error('Failed to save project:', error);
       throw error;
@@ -47,22 +49,65 @@ export class ProjectManager {
   }
 
   /**
-   * Export data using Tauri backend
+   * Load project from file and check its references, optionally repairing them
    */
-  static async exportSTL(data: Uint8Array, path: string): Promise<void> {
+  static async loadProjectValidated(path: string, repair = false): Promise<ValidatedProject> {
     try {
-      await invoke('export_stl', { data, path });
+      const validated = await api.loadProjectValidated(path, repair);
+      for (const issue of validated.report.issues) {
+        console.warn(`Project issue at ${issue.path}: ${issue.message}${issue.repaired ? ' (repaired)' : ''}`);
+      }
+      return validated;
+    } catch (error) {
+      console.error('Failed to load project:', error);
+      throw error;
+    }
+  }
+
+  /**
+   * Salvage what still parses from a damaged project file
+   */
+  static async recoverProject(path: string): Promise<RecoveredProject> {
+    try {
+      return await api.recoverProject(path);
+    } catch (error) {
+      console.error('Failed to recover project:', error);
+      throw error;
+    }
+  }
+
+  /**
+   * Generate and write an export using the Tauri backend
+   */
+  static async exportProject(project: ProjectFile, format: ExportFormat, path: string): Promise<ExportResult> {
+    try {
+      return await api.exportProject(project, format, path);
+    } catch (error) {
+      console.error(`Failed to export ${format.format.toUpperCase()}:`, error);
+      throw error;
+    }
+  }
+
+  /**
+   * Write the project with its library data and meshes into one file
+   */
+  static async exportBundle(project: ProjectFile, path: string): Promise<BundleExportResult> {
+    try {
+      return await api.exportBundle(project, path);
     } catch (error) {
-      console.error('Failed to export STL:', error);
+      console.error('Failed to export bundle:', error);
       throw error;
     }
   }
 
-  static async exportThreeMF(data: Uint8Array, path: string): Promise<void> {
+  /**
+   * Open a project bundle, adding missing library entries locally
+   */
+  static async importBundle(path: string): Promise<BundleImport> {
     try {
-      await invoke('export_three_mf', { data, path });
+      return await api.importBundle(path);
     } catch (error) {
-      console.error('Failed to export 3MF:', error);
+      console.error('Failed to import bundle:', error);
       throw error;
     }
   }
@@ -135,17 +180,27 @@ export class ProjectManager {
 
   /**
    * Start auto-save functionality
+   *
+   * Scene changes are handed to the backend autosave service, which decides
+   * when to write a snapshot.
    */
-  static startAutosave(): void {
+  static startAutosave(path?: string): void {
     if (this.autosaveInterval) {
       this.stopAutosave();
     }
 
+    this.sceneDirty = false;
+    this.unsubscribeScene = useSceneStore.subscribe(() => {
+      this.sceneDirty = true;
+    });
+
     this.autosaveInterval = setInterval(async () => {
+      if (!this.sceneDirty) {
+        return;
+      }
+      this.sceneDirty = false;
       try {
-        const project = this.getCurrentProject();
-        // Save to autosave location (would be handled by Tauri)
-        console.log('Auto-saving project...');
+        await api.updateAutosave(this.getCurrentProject(), path);
       } catch (error) {
         console.error('Auto-save failed:', error);
       }
@@ -160,6 +215,22 @@ export class ProjectManager {
       clearInterval(this.autosaveInterval);
       this.autosaveInterval = null;
     }
+    if (this.unsubscribeScene) {
+      this.unsubscribeScene();
+      this.unsubscribeScene = null;
+    }
+  }
+
+  /**
+   * Autosave snapshot to offer after a crash, if any
+   */
+  static async getRecoveryInfo(): Promise<RecoveryInfo | null> {
+    try {
+      return await api.getRecoveryInfo();
+    } catch (error) {
+      console.error('Failed to check for autosave recovery:', error);
+      return null;
+    }
   }
 
   /**
@@ -199,6 +270,10 @@ export class ProjectManager {
     return [
       { extension: '.stl', name: 'STL (Binary)' },
       { extension: '.3mf', name: '3MF (3D Manufacturing Format)' },
+      { extension: '.glb', name: 'glTF Binary' },
+      { extension: '.gltf', name: 'glTF' },
+      { extension: '.step', name: 'STEP (AP214)' },
+      { extension: '.obj', name: 'Wavefront OBJ' },
     ];
   }
 }
\ No newline at end of file
@@ -1,5 +1,6 @@
 // Web-compatible API layer that mocks Tauri IPC calls for web deployment
-import { Cell, Material, Shape } from '../types/cell';
+import { CatalogChangeset, CatalogStatus, CatalogSyncResult, Cell, CellImportOptions, CellImportReport, CellSample, CellSampleStats, LibraryExportOptions, LibraryExportResult, Material, SampleImportOptions, SampleImportReport, Shape } from '../types/cell';
+import { ApiServerStatus, AppError, AutosaveSnapshot, Bom, BundleExportResult, BundleImport, DrcReport, ExportFormat, ExportJob, ExportResult, MatchedProject, MatchOptions, MergeResult, PackAnalysis, ProjectDiff, ProjectFile, RecentProject, RecoveredProject, RecoveryInfo, SaveFormat, Snapshot, SnapshotComparison, ValidatedProject } from '../types/project';
 
 // Mock data for web deployment
 const mockCells: Cell[] = [
@@ -207,6 +208,10 @@ export const webApi = {
 // Check if we're running in Tauri or web
 export const isTauri = typeof window !== 'undefined' && (window as any).__TAURI__ !== undefined;
 
+// Backend commands reject with an `AppError`; anything else is unexpected
+export const isAppError = (error: unknown): error is AppError =>
+  typeof error === 'object' && error !== null && 'code' in error && 'message' in error;
+
 // For web builds, always use web fallback
 const invoke: any = null;
 
@@ -256,10 +261,52 @@ export const api = {
     return webApi.getShapes();
   },
 
-  saveProject: async (project: any, path: string) => {
+  previewCellImport: async (path: string, options: CellImportOptions = {}): Promise<CellImportReport> => {
+    if (invoke) {
+      return await invoke('preview_cell_import', { path, options });
+    }
+    throw new Error('Cell import not supported in web version');
+  },
+
+  getCellSamples: async (cellId: number): Promise<CellSample[]> => {
+    if (invoke) {
+      return await invoke('get_cell_samples', { cellId });
+    }
+    return [];
+  },
+
+  importCellSamples: async (cellId: number, path: string, options: SampleImportOptions = {}): Promise<SampleImportReport> => {
+    if (invoke) {
+      return await invoke('import_cell_samples', { cellId, path, options });
+    }
+    throw new Error('Cell sample import not supported in web version');
+  },
+
+  deleteCellSample: async (id: number): Promise<void> => {
+    if (invoke) {
+      return await invoke('delete_cell_sample', { id });
+    }
+    throw new Error('Cell samples not supported in web version');
+  },
+
+  getCellSampleStats: async (cellId: number, bins?: number): Promise<CellSampleStats> => {
+    if (invoke) {
+      return await invoke('get_cell_sample_stats', { cellId, bins });
+    }
+    throw new Error('Cell samples not supported in web version');
+  },
+
+  importCells: async (path: string, options: CellImportOptions = {}): Promise<CellImportReport> => {
+    if (invoke) {
+      return await invoke('import_cells', { path, options });
+    }
+    throw new Error('Cell import not supported in web version');
+  },
+
+  saveProject: async (project: any, path: string, format?: SaveFormat) => {
     if (invoke) {
       try {
-        return await invoke('save_project', { project, path });
+        return await invoke('save_project', { project, path, format });
       } catch (e) {
         console.warn('Tauri API failed, using web fallback');
       }
@@ -290,25 +337,281 @@ export const api = {
   },
 
   exportSTL: async (data: Uint8Array, path: string) => {
-    if (invoke) {
-      try {
-        return await invoke('export_stl', { data, path });
-      } catch (e) {
-        console.warn('Tauri API failed, using web fallback');
-      }
-    }
     return webApi.exportSTL(data, path);
   },
 
   exportThreeMF: async (data: Uint8Array, path: string) => {
+    return webApi.exportThreeMF(data, path);
+  },
+
+  exportProject: async (project: ProjectFile, format: ExportFormat, path: string): Promise<ExportResult> => {
     if (invoke) {
-      try {
-        return await invoke('export_three_mf', { data, path });
-      } catch (e) {
-        console.warn('Tauri API failed, using web fallback');
-      }
+      return await invoke('export_project', { project, format, path });
+    }
+    throw new Error('Backend export not supported in web version');
+  },
+
+  startExport: async (project: ProjectFile, format: ExportFormat, path: string): Promise<ExportJob> => {
+    if (invoke) {
+      return await invoke('start_export', { project, format, path });
+    }
+    throw new Error('Backend export not supported in web version');
+  },
+
+  cancelExport: async (jobId: string): Promise<void> => {
+    if (invoke) {
+      return await invoke('cancel_export', { jobId });
+    }
+  },
+
+  getExportJobs: async (): Promise<ExportJob[]> => {
+    if (invoke) {
+      return await invoke('get_export_jobs');
+    }
+    return [];
+  },
+
+  loadProjectValidated: async (path: string, repair: boolean): Promise<ValidatedProject> => {
+    if (invoke) {
+      return await invoke('load_project_validated', { path, repair });
+    }
+    return { project: await webApi.loadProject(path), report: { issues: [] } };
+  },
+
+  validateProject: async (project: ProjectFile, repair: boolean): Promise<ValidatedProject> => {
+    if (invoke) {
+      return await invoke('validate_project', { project, repair });
+    }
+    return { project, report: { issues: [] } };
+  },
+
+  analyzePack: async (project: ProjectFile): Promise<PackAnalysis> => {
+    if (invoke) {
+      return await invoke('analyze_pack', { project });
+    }
+    throw new Error('Pack analysis not supported in web version');
+  },
+
+  checkDesignRules: async (project: ProjectFile): Promise<DrcReport> => {
+    if (invoke) {
+      return await invoke('check_design_rules', { project });
+    }
+    throw new Error('Design rule check not supported in web version');
+  },
+
+  generateBom: async (project: ProjectFile): Promise<Bom> => {
+    if (invoke) {
+      return await invoke('generate_bom', { project });
+    }
+    throw new Error('BOM generation not supported in web version');
+  },
+
+  // Labels each grouped cell with the measured serial placed there
+  matchCells: async (project: ProjectFile, options: MatchOptions = {}): Promise<MatchedProject> => {
+    if (invoke) {
+      return await invoke('match_cells', { project, options });
+    }
+    throw new Error('Cell matching not supported in web version');
+  },
+
+  getApiServerStatus: async (): Promise<ApiServerStatus> => {
+    if (invoke) {
+      return await invoke('get_api_server_status');
+    }
+    throw new Error('API server not supported in web version');
+  },
+
+  startApiServer: async (bind?: string, port?: number): Promise<ApiServerStatus> => {
+    if (invoke) {
+      return await invoke('start_api_server', { bind, port });
+    }
+    throw new Error('API server not supported in web version');
+  },
+
+  stopApiServer: async (): Promise<ApiServerStatus> => {
+    if (invoke) {
+      return await invoke('stop_api_server');
+    }
+    throw new Error('API server not supported in web version');
+  },
+
+  regenerateApiToken: async (): Promise<ApiServerStatus> => {
+    if (invoke) {
+      return await invoke('regenerate_api_token');
+    }
+    throw new Error('API server not supported in web version');
+  },
+
+  diffProjects: async (base: ProjectFile, other: ProjectFile): Promise<ProjectDiff> => {
+    if (invoke) {
+      return await invoke('diff_projects', { base, other });
+    }
+    throw new Error('Project diff not supported in web version');
+  },
+
+  mergeProjects: async (base: ProjectFile, ours: ProjectFile, theirs: ProjectFile): Promise<MergeResult> => {
+    if (invoke) {
+      return await invoke('merge_projects', { base, ours, theirs });
+    }
+    throw new Error('Project merge not supported in web version');
+  },
+
+  getRecentProjects: async (): Promise<RecentProject[]> => {
+    if (invoke) {
+      return await invoke('get_recent_projects');
+    }
+    return [];
+  },
+
+  setRecentProjectPinned: async (path: string, pinned: boolean): Promise<void> => {
+    if (invoke) {
+      return await invoke('set_recent_project_pinned', { path, pinned });
+    }
+  },
+
+  removeRecentProject: async (path: string): Promise<void> => {
+    if (invoke) {
+      return await invoke('remove_recent_project', { path });
+    }
+  },
+
+  removeMissingRecentProjects: async (): Promise<string[]> => {
+    if (invoke) {
+      return await invoke('remove_missing_recent_projects');
+    }
+    return [];
+  },
+
+  // Resolves to a data: URL usable as an <img> src
+  getProjectThumbnail: async (path: string): Promise<string | null> => {
+    if (invoke) {
+      return await invoke('get_project_thumbnail', { path });
+    }
+    return null;
+  },
+
+  createSnapshot: async (path: string, project: ProjectFile, name: string, note?: string, author?: string): Promise<Snapshot> => {
+    if (invoke) {
+      return await invoke('create_snapshot', { path, project, name, note, author });
+    }
+    throw new Error('Project history not supported in web version');
+  },
+
+  listSnapshots: async (path: string): Promise<Snapshot[]> => {
+    if (invoke) {
+      return await invoke('list_snapshots', { path });
+    }
+    return [];
+  },
+
+  restoreSnapshot: async (path: string, id: string): Promise<ProjectFile> => {
+    if (invoke) {
+      return await invoke('restore_snapshot', { path, id });
+    }
+    throw new Error('Project history not supported in web version');
+  },
+
+  deleteSnapshot: async (path: string, id: string): Promise<void> => {
+    if (invoke) {
+      return await invoke('delete_snapshot', { path, id });
+    }
+  },
+
+  compareSnapshots: async (path: string, from: string, to?: string): Promise<SnapshotComparison> => {
+    if (invoke) {
+      return await invoke('compare_snapshots', { path, from, to });
+    }
+    throw new Error('Project history not supported in web version');
+  },
+
+  recoverProject: async (path: string): Promise<RecoveredProject> => {
+    if (invoke) {
+      return await invoke('recover_project', { path });
+    }
+    throw new Error('Project recovery not supported in web version');
+  },
+
+  exportBundle: async (project: ProjectFile, path: string): Promise<BundleExportResult> => {
+    if (invoke) {
+      return await invoke('export_bundle', { project, path });
+    }
+    throw new Error('Project bundles not supported in web version');
+  },
+
+  importBundle: async (path: string): Promise<BundleImport> => {
+    if (invoke) {
+      return await invoke('import_bundle', { path });
+    }
+    throw new Error('Project bundles not supported in web version');
+  },
+
+  exportLibrary: async (path: string, options: LibraryExportOptions = {}): Promise<LibraryExportResult> => {
+    if (invoke) {
+      return await invoke('export_library', { path, options });
+    }
+    throw new Error('Library export not supported in web version');
+  },
+
+  importLibraryFiles: async (paths: string[]): Promise<BundleImport['report']> => {
+    if (invoke) {
+      return await invoke('import_library_files', { paths });
+    }
+    throw new Error('Library import not supported in web version');
+  },
+
+  getCatalogStatus: async (): Promise<CatalogStatus> => {
+    if (invoke) {
+      return await invoke('get_catalog_status');
+    }
+    return {};
+  },
+
+  reviewCatalog: async (source: string): Promise<CatalogChangeset> => {
+    if (invoke) {
+      return await invoke('review_catalog', { source });
+    }
+    throw new Error('Catalog sync not supported in web version');
+  },
+
+  // `revision` comes from the review the approvals were made in
+  applyCatalog: async (source: string, revision: string, approved: string[]): Promise<CatalogSyncResult> => {
+    if (invoke) {
+      return await invoke('apply_catalog', { source, revision, approved });
+    }
+    throw new Error('Catalog sync not supported in web version');
+  },
+
+  updateAutosave: async (project: ProjectFile, path?: string): Promise<void> => {
+    if (invoke) {
+      return await invoke('update_autosave', { project, path });
+    }
+  },
+
+  listAutosaves: async (): Promise<AutosaveSnapshot[]> => {
+    if (invoke) {
+      return await invoke('list_autosaves');
+    }
+    return [];
+  },
+
+  getRecoveryInfo: async (): Promise<RecoveryInfo | null> => {
+    if (invoke) {
+      return await invoke('get_recovery_info');
+    }
+    return null;
+  },
+
+  recoverAutosave: async (): Promise<ProjectFile> => {
+    if (invoke) {
+      return await invoke('recover_autosave');
+    }
+    throw new Error('Autosave recovery not supported in web version');
+  },
+
+  dismissRecovery: async (): Promise<void> => {
+    if (invoke) {
+      return await invoke('dismiss_recovery');
     }
-    return webApi.exportThreeMF(data, path);
   },
 
   importMesh: async (path: string) => {
@@ -19,6 +19,10 @@ export interface Cell {
   datasheet_url?: string;
   thermal_limit_c?: number;
   cycle_life?: number;
+  // Added on this install rather than shipped with the library
+  user_added?: boolean;
+  // Withdrawn from the catalog; kept so existing projects still resolve
+  deprecated?: boolean;
 }
 
 export interface Bms {
@@ -32,6 +36,10 @@ export interface Bms {
   width_mm: number;
   height_mm: number;
   pinout_json?: string;
+  // Added on this install rather than shipped with the library
+  user_added?: boolean;
+  // Withdrawn from the catalog; kept so existing projects still resolve
+  deprecated?: boolean;
 }
 
 export interface Material {
@@ -42,6 +50,10 @@ export interface Material {
   width_mm?: number;
   resistance_mohm_per_m: number;
   max_current_a: number;
+  // Added on this install rather than shipped with the library
+  user_added?: boolean;
+  // Withdrawn from the catalog; kept so existing projects still resolve
+  deprecated?: boolean;
 }
 
 export interface Shape {
@@ -50,4 +62,174 @@ export interface Shape {
   category: 'enclosure' | 'bracket' | 'spacer' | 'vent' | 'terminal';
   file_path: string;
   default_scale: string;
-}
\ No newline at end of file
+  // Added on this install rather than shipped with the library
+  user_added?: boolean;
+  // Withdrawn from the catalog; kept so existing projects still resolve
+  deprecated?: boolean;
+}
+// What happens to an imported cell that is already in the library
+export type ConflictPolicy = 'skip' | 'merge' | 'overwrite';
+
+export interface CellImportOptions {
+  format?: 'csv' | 'json';
+  // Column name in the file -> Cell field; '' ignores the column
+  mapping?: Record<string, string>;
+  // Cell field -> unit of the file's values, e.g. { capacity_mah: 'Ah' }
+  units?: Record<string, string>;
+  policy?: ConflictPolicy;
+  delimiter?: string;
+}
+
+export interface ImportColumn {
+  column: string;
+  field?: string;
+  unit?: string;
+}
+
+export interface FieldChange {
+  field: string;
+  current: unknown;
+  imported: unknown;
+}
+
+export interface CellImportRow {
+  row: number;
+  name: string;
+  action: 'add' | 'update' | 'skip' | 'reject';
+  existing_id?: number;
+  cell?: Cell;
+  changes: FieldChange[];
+  errors: string[];
+}
+
+export interface CellImportReport {
+  committed: boolean;
+  policy: ConflictPolicy;
+  columns: ImportColumn[];
+  rows: CellImportRow[];
+  added: number;
+  updated: number;
+  skipped: number;
+  rejected: number;
+}
+
+export interface CellSample {
+  id: number;
+  cell_id: number;
+  serial: string;
+  capacity_mah?: number;
+  internal_res_mohm?: number;
+  voltage?: number;
+  measured_at?: string;
+}
+
+export interface SampleImportOptions {
+  mapping?: Record<string, string>;
+  units?: Record<string, string>;
+  delimiter?: string;
+  decimal?: string;
+}
+
+export interface RejectedSample {
+  row: number;
+  serial: string;
+  errors: string[];
+}
+
+export interface SampleImportReport {
+  cell_id: number;
+  columns: ImportColumn[];
+  added: number;
+  updated: number;
+  rejected: RejectedSample[];
+}
+
+export interface HistogramBin {
+  start: number;
+  end: number;
+  count: number;
+}
+
+export interface Distribution {
+  count: number;
+  mean: number;
+  std_dev: number;
+  min: number;
+  max: number;
+  median: number;
+  rated?: number;
+  mean_of_rated?: number;
+  histogram: HistogramBin[];
+}
+
+export interface CellSampleStats {
+  cell_id: number;
+  samples: number;
+  capacity_mah?: Distribution;
+  internal_res_mohm?: Distribution;
+  voltage?: Distribution;
+}
+
+export type LibraryTable = 'cells' | 'bms' | 'materials' | 'shapes';
+
+export interface LibraryFilter {
+  user_added_only?: boolean;
+  search?: string;
+  manufacturer?: string;
+  form_factor?: string;
+  chemistry?: string;
+}
+
+export interface LibraryExportOptions {
+  // All tables when empty
+  tables?: LibraryTable[];
+  format?: 'csv' | 'json';
+  filter?: LibraryFilter;
+  // Makes a JSON export a catalog other installs can sync from
+  catalog_version?: string;
+}
+
+export interface LibraryExportResult {
+  // The JSON file, or one CSV file per table
+  paths: string[];
+  schema_version: number;
+  cells: number;
+  bms: number;
+  materials: number;
+  shapes: number;
+}
+
+export interface CatalogStatus {
+  source?: string;
+  version?: string;
+  synced_at?: string;
+}
+
+export interface CatalogChange {
+  // '<table>:<library key>', passed back to approve the change
+  id: string;
+  table: LibraryTable;
+  kind: 'add' | 'update' | 'deprecate';
+  name: string;
+  local_id?: number;
+  changes: FieldChange[];
+  // The local row is user-added and is never overwritten
+  protected: boolean;
+}
+
+export interface CatalogChangeset {
+  source: string;
+  version: string;
+  revision: string;
+  local_version?: string;
+  changes: CatalogChange[];
+  unchanged: number;
+}
+
+export interface CatalogSyncResult {
+  version: string;
+  added: number;
+  updated: number;
+  deprecated: number;
+  skipped: number;
+}
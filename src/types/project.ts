@@ -3,6 +3,7 @@ import { Vector3, Euler, Transform as GeometryTransform } from './geometry';
 export interface CellInstance {
   uuid: string;
   cellId: number; // FK to cells table
+  cellKey?: string; // Stable library key, e.g. 'samsung/30q'; set by the backend on save
   position: Vector3;
   rotation: Euler;
   scale?: Vector3;
@@ -25,6 +26,7 @@ export interface Connection {
   targetUuid: string;
   targetTerminal: 'positive' | 'negative';
   materialId?: number; // FK to materials table
+  materialKey?: string; // Set from materialId on save
   path?: Vector3[]; // Custom routing points
 }
 
@@ -32,6 +34,7 @@ export interface Component {
   uuid: string;
   componentType: 'bms' | 'shape' | 'custom';
   referenceId?: number; // FK to bms or shapes table
+  referenceKey?: string; // Set from referenceId on save
   position: Vector3;
   rotation: Euler;
   scale: Vector3;
@@ -99,6 +102,345 @@ export interface ThreeMFExportOptions {
   fileName: string;
 }
 
+export type ExportSelection =
+  | { mode: 'all' }
+  | { mode: 'selected'; uuids: string[] }
+  | { mode: 'holders-only' }
+  | { mode: 'cells-only' };
+
+// Options for the backend `export_project` command (snake_case, as serialized by Rust)
+export type ExportFormat =
+  | { format: 'stl'; options: { selection: ExportSelection; merge_geometries: boolean; apply_transforms: boolean; scale: number; file_name: string } }
+  | { format: '3mf'; options: { selection: ExportSelection; include_colors: boolean; include_materials: boolean; separate_objects: boolean; build_plate_origin: boolean; file_name: string } }
+  | { format: 'gltf'; options: { selection: ExportSelection; binary: boolean; include_connections: boolean; segments: number; file_name: string } }
+  | { format: 'step'; options: { selection: ExportSelection; group_assemblies: boolean; file_name: string } }
+  | { format: 'obj'; options: { selection: ExportSelection; merge_geometries: boolean; apply_transforms: boolean; scale: number; include_materials: boolean; file_name: string } };
+
+export interface ExportResult {
+  path: string;
+  file_size: number;
+  companion_files: string[];
+  warnings: string[];
+}
+
+export type ExportJobStatus = 'queued' | 'running' | 'completed' | 'failed' | 'cancelled';
+
+// Payload of the backend `export-progress` event and `get_export_jobs`
+export interface ExportJob {
+  id: string;
+  format: string;
+  path: string;
+  status: ExportJobStatus;
+  stage: string; // geometry | encoding | writing
+  percent: number;
+  eta_seconds: number | null;
+  started_at: string;
+  finished_at: string | null;
+  result: ExportResult | null;
+  error: string | null;
+  error_code: ErrorCode | null;
+}
+
+export interface AutosaveSnapshot {
+  file_name: string;
+  created_at: string;
+  project_name: string;
+  project_path: string | null;
+}
+
+// Offered on startup when the previous session did not shut down cleanly
+export interface RecoveryInfo {
+  snapshot: AutosaveSnapshot;
+  snapshot_path: string;
+  last_saved_at: string | null;
+}
+
+export interface RecoveryReport {
+  checksum: 'valid' | 'mismatch' | 'missing';
+  truncated: boolean;
+  cells: number;
+  connections: number;
+  components: number;
+  groups: number;
+  dropped: Array<{ path: string; error: string }>;
+}
+
+export interface RecoveredProject {
+  project: ProjectFile;
+  report: RecoveryReport;
+}
+
+export interface ValidationIssue {
+  kind:
+    | 'key-mismatch'
+    | 'dangling-connection'
+    | 'dangling-group-member'
+    | 'dangling-group-id'
+    | 'group-membership'
+    | 'unknown-cell'
+    | 'unknown-material'
+    | 'unknown-reference';
+  path: string;
+  message: string;
+  repaired: boolean;
+}
+
+export interface ValidatedProject {
+  project: ProjectFile;
+  report: { issues: ValidationIssue[] };
+}
+
+export interface MatchOptions {
+  resistance_weight?: number;
+  serials?: string[];
+}
+
+export interface Placement {
+  series?: number;
+  group_id: string;
+  group: string;
+  slot: number;
+  cell_uuid: string;
+  x: number;
+  y: number;
+  serial: string;
+  capacity_mah: number;
+  internal_res_mohm?: number;
+}
+
+export interface GroupBalance {
+  group_id: string;
+  group: string;
+  series?: number;
+  cells: number;
+  capacity_mah: number;
+  internal_res_mohm?: number;
+}
+
+export interface CellMatching {
+  placements: Placement[];
+  groups: GroupBalance[];
+  capacity_spread_mah: number;
+  capacity_spread_pct: number;
+  resistance_spread_mohm?: number;
+  unused: string[];
+  ungrouped: string[];
+}
+
+export interface MatchedProject {
+  project: ProjectFile;
+  matching: CellMatching;
+}
+
+export interface BundleExportResult {
+  path: string;
+  file_size: number;
+  cells: number;
+  bms: number;
+  materials: number;
+  shapes: number;
+  meshes: number;
+  warnings: string[];
+}
+
+export interface LibraryMatch {
+  kind: 'cell' | 'bms' | 'material' | 'shape';
+  name: string;
+  bundled_id: number;
+  local_id: number;
+}
+
+export interface BundleImport {
+  project: ProjectFile;
+  report: {
+    matched: LibraryMatch[];
+    added: LibraryMatch[];
+    meshes: string[];
+    warnings: string[];
+  };
+}
+
+export interface FieldChange {
+  field: string;
+  before: unknown;
+  after: unknown;
+}
+
+export interface EntityChange {
+  uuid: string;
+  kind: 'added' | 'removed' | 'modified';
+  fields: FieldChange[];
+}
+
+export interface ProjectDiff {
+  metadata: FieldChange[];
+  settings: FieldChange[];
+  cells: EntityChange[];
+  connections: EntityChange[];
+  components: EntityChange[];
+  groups: EntityChange[];
+  memberships: Array<{ group: string; added: string[]; removed: string[] }>;
+}
+
+export interface MergeConflict {
+  kind: 'modified' | 'modify-delete' | 'add-add';
+  path: string;
+  base: unknown;
+  ours: unknown;
+  theirs: unknown;
+}
+
+export interface MergeResult {
+  project: ProjectFile;
+  conflicts: MergeConflict[];
+}
+
+export interface Snapshot {
+  id: string;
+  name: string;
+  note: string | null;
+  author: string | null;
+  created_at: string;
+  file_name: string;
+  cells: number;
+  connections: number;
+  components: number;
+  groups: number;
+}
+
+export interface DiffSummary {
+  cells: { added: number; removed: number; modified: number };
+  cells_moved: number;
+  connections: { added: number; removed: number; modified: number };
+  components: { added: number; removed: number; modified: number };
+  groups: { added: number; removed: number; modified: number };
+  settings: string[];
+}
+
+export interface SnapshotComparison {
+  summary: DiffSummary;
+  diff: ProjectDiff;
+}
+
+export interface RecentProject {
+  id: string;
+  path: string;
+  name: string;
+  author: string | null;
+  created: string;
+  modified: string;
+  last_opened: string;
+  pinned: boolean;
+  cell_count: number;
+  thumbnail_path: string | null;
+  exists: boolean;
+}
+
+export interface ParallelGroup {
+  cells: string[];
+  capacity_ah: number;
+  nominal_voltage: number;
+  max_discharge_a: number;
+}
+
+export interface PackAnalysis {
+  cell_count: number;
+  series: number;
+  parallel: number;
+  configuration: string; // e.g. "13S4P"
+  chemistry: string | null; // null when mixed
+  nominal_voltage: number;
+  max_voltage: number;
+  min_voltage: number;
+  capacity_ah: number;
+  energy_wh: number;
+  max_discharge_a: number;
+  max_charge_a: number;
+  internal_resistance_mohm: number | null;
+  cell_weight_g: number;
+  groups: ParallelGroup[]; // negative end first
+}
+
+export interface DrcIssue {
+  rule:
+    | 'unknown-cell'
+    | 'floating-cell'
+    | 'short-circuit'
+    | 'series-topology'
+    | 'reversed-polarity'
+    | 'disconnected-cells'
+    | 'mixed-chemistry'
+    | 'voltage-mismatch'
+    | 'unbalanced-groups'
+    | 'missing-material'
+    | 'undersized-connection'
+    | 'bms-series-mismatch'
+    | 'bms-undersized';
+  severity: 'error' | 'warning';
+  message: string;
+  entities: string[];
+}
+
+export interface DrcReport {
+  passed: boolean;
+  errors: number;
+  warnings: number;
+  issues: DrcIssue[];
+}
+
+export interface BomLine {
+  category: 'cell' | 'bms' | 'material' | 'shape' | 'custom';
+  key: string | null;
+  description: string;
+  quantity: number;
+  unit: 'pcs' | 'm';
+  weight_g: number | null;
+}
+
+export interface Bom {
+  project: string;
+  lines: BomLine[];
+  total_weight_g: number;
+  warnings: string[];
+}
+
+export interface ApiConfig {
+  enabled: boolean;
+  bind: string;
+  port: number;
+  token: string;
+}
+
+export interface ApiServerStatus {
+  running: boolean;
+  url: string | null;
+  config: ApiConfig;
+}
+
+// Stable, machine-readable kind of a failed backend command
+export type ErrorCode =
+  | 'NOT_FOUND'
+  | 'CORRUPT'
+  | 'VERSION_TOO_NEW'
+  | 'PERMISSION_DENIED'
+  | 'CONSTRAINT_VIOLATION'
+  | 'BUSY'
+  | 'INVALID_INPUT'
+  | 'UNAVAILABLE'
+  | 'IO'
+  | 'INTERNAL';
+
+// What a failed backend command rejects with
+export interface AppError {
+  code: ErrorCode;
+  message: string;
+  path?: string;
+  id?: string;
+}
+
+// 'plain' writes canonical uncompressed JSON for version control
+export type SaveFormat = 'compressed' | 'plain';
+
 export type ImportableFormat = 'stl' | '3mf' | 'obj' | 'glb' | 'gltf';
 
 export interface ImportResult {